@@ -0,0 +1,356 @@
+//! Benchmarks the streaming modes over loopback with the synthetic source.
+//!
+//! Every mode runs its real sender binary against `universal_receiver` with
+//! burned-in timecodes, for each resolution and frame rate. Results are
+//! printed and written as CSV.
+//!
+//! Configured from the environment:
+//! - `BENCH_SIZES`: comma separated `WxH` list, default `320x240,640x480,1280x720`
+//! - `BENCH_FPS`: comma separated frame rates, default `15,30`
+//! - `BENCH_FRAMES`: frames per run, default 150
+//! - `BENCH_MODES`: subset of jpeg-message, jpeg-chunked, ts-mjpeg, ts-h264
+//! - `BENCH_CSV`: output path, default `bench_results.csv`
+//!
+//! CPU time is read from the children's resource usage, which is only
+//! available on Unix. Elsewhere those columns stay empty.
+
+#[cfg(unix)]
+use std::os::unix::process::ExitStatusExt;
+use std::{
+    io::Read,
+    path::PathBuf,
+    process::{Child, Command, ExitStatus, Stdio},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+#[cfg(unix)]
+use anyhow::bail;
+use anyhow::{Context, Result};
+
+const STARTUP_DELAY: Duration = Duration::from_millis(500);
+
+struct Mode {
+    name: &'static str,
+    sender: &'static str,
+    sender_listens: bool,
+    receiver_env: &'static [(&'static str, &'static str)],
+}
+
+const MODES: &[Mode] = &[
+    Mode {
+        name: "jpeg-message",
+        sender: "v5_sender",
+        sender_listens: true,
+        receiver_env: &[("SRT_CALL", "127.0.0.1:9999")],
+    },
+    Mode {
+        name: "jpeg-chunked",
+        sender: "v4_sender",
+        sender_listens: false,
+        receiver_env: &[("SRT_LISTEN", "0.0.0.0:4200")],
+    },
+    Mode {
+        name: "ts-mjpeg",
+        sender: "v2_sender",
+        sender_listens: true,
+        receiver_env: &[("SRT_CALL", "127.0.0.1:1234")],
+    },
+    Mode {
+        name: "ts-h264",
+        sender: "v3_sender",
+        sender_listens: true,
+        receiver_env: &[("SRT_CALL", "127.0.0.1:1234")],
+    },
+];
+
+struct Outcome {
+    mode: &'static str,
+    width: usize,
+    height: usize,
+    fps: f64,
+    frames_sent: u64,
+    frames_received: u64,
+    achieved_fps: f64,
+    bitrate_kbps: f64,
+    // `None` where the platform does not report child CPU time
+    sender_cpu_ms: Option<f64>,
+    receiver_cpu_ms: Option<f64>,
+    latency_ms: [f64; 4],
+    loss_percent: f64,
+}
+
+const CSV_HEADER: &str = "mode,width,height,fps,frames_sent,frames_received,achieved_fps,bitrate_kbps,sender_cpu_ms_per_frame,receiver_cpu_ms_per_frame,latency_p50_ms,latency_p95_ms,latency_p99_ms,latency_max_ms,loss_percent";
+
+impl Outcome {
+    fn csv(&self) -> String {
+        format!(
+            "{},{},{},{},{},{},{:.2},{:.1},{},{},{:.2},{:.2},{:.2},{:.2},{:.2}",
+            self.mode,
+            self.width,
+            self.height,
+            self.fps,
+            self.frames_sent,
+            self.frames_received,
+            self.achieved_fps,
+            self.bitrate_kbps,
+            cpu_ms(self.sender_cpu_ms, 3),
+            cpu_ms(self.receiver_cpu_ms, 3),
+            self.latency_ms[0],
+            self.latency_ms[1],
+            self.latency_ms[2],
+            self.latency_ms[3],
+            self.loss_percent
+        )
+    }
+}
+
+/// Formats a CPU time per frame, empty when unknown.
+fn cpu_ms(ms: Option<f64>, precision: usize) -> String {
+    ms.map_or_else(String::new, |ms| format!("{ms:.precision$}"))
+}
+
+/// A child with its stdout collected in the background.
+struct Running {
+    child: Child,
+    stdout: JoinHandle<String>,
+}
+
+fn spawn(mut cmd: Command) -> Result<Running> {
+    let mut child = cmd
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .with_context(|| format!("cannot start {:?}", cmd.get_program()))?;
+    let mut pipe = child.stdout.take().context("stdout is piped")?;
+    let stdout = thread::spawn(move || {
+        let mut output = String::new();
+        let _ = pipe.read_to_string(&mut output);
+        output
+    });
+    Ok(Running { child, stdout })
+}
+
+/// Waits for the child and returns its output and consumed CPU time (user +
+/// system), killing it after `timeout`.
+#[cfg(unix)]
+fn finish(running: Running, timeout: Duration) -> Result<(ExitStatus, String, Option<Duration>)> {
+    let pid = running.child.id() as libc::pid_t;
+    let deadline = Instant::now() + timeout;
+    let mut killed = false;
+
+    loop {
+        let mut status = 0;
+        // SAFETY: plain FFI call with valid out pointers, the child is ours.
+        let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+        let reaped = unsafe { libc::wait4(pid, &mut status, libc::WNOHANG, &mut usage) };
+        if reaped == pid {
+            let cpu = |t: libc::timeval| {
+                Duration::from_secs(t.tv_sec as u64) + Duration::from_micros(t.tv_usec as u64)
+            };
+            let output = running.stdout.join().unwrap_or_default();
+            return Ok((
+                ExitStatus::from_raw(status),
+                output,
+                Some(cpu(usage.ru_utime) + cpu(usage.ru_stime)),
+            ));
+        }
+        if reaped < 0 {
+            bail!("wait4 failed: {}", std::io::Error::last_os_error());
+        }
+        if !killed && Instant::now() >= deadline {
+            // SAFETY: signalling our own, not yet reaped child.
+            unsafe { libc::kill(pid, libc::SIGKILL) };
+            killed = true;
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+}
+
+/// Waits for the child and returns its output, killing it after `timeout`.
+/// CPU time is not available here.
+#[cfg(not(unix))]
+fn finish(
+    mut running: Running,
+    timeout: Duration,
+) -> Result<(ExitStatus, String, Option<Duration>)> {
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        if let Some(status) = running.child.try_wait()? {
+            break status;
+        }
+        if Instant::now() >= deadline {
+            // Fails if it exited meanwhile, the wait below still reaps it
+            let _ = running.child.kill();
+            break running.child.wait()?;
+        }
+        thread::sleep(Duration::from_millis(20));
+    };
+    let output = running.stdout.join().unwrap_or_default();
+    Ok((status, output, None))
+}
+
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    if sorted.is_empty() {
+        return f64::NAN;
+    }
+    sorted[((sorted.len() - 1) as f64 * p / 100.0).round() as usize]
+}
+
+fn run_case(mode: &Mode, width: usize, height: usize, fps: f64, frames: u64) -> Result<Outcome> {
+    let exe_dir = std::env::current_exe()?
+        .parent()
+        .map(PathBuf::from)
+        .context("binary has no parent directory")?;
+
+    let mut sender = Command::new(exe_dir.join(mode.sender));
+    sender
+        .env("SOURCE", "synthetic")
+        .env("SYNTHETIC_SIZE", format!("{width}x{height}"))
+        .env("SYNTHETIC_FPS", fps.to_string())
+        .env("FRAME_LIMIT", frames.to_string())
+        .env("TIMECODE", "1");
+
+    let mut receiver = Command::new(exe_dir.join("universal_receiver"));
+    receiver.env("HEADLESS", "1").env("TIMECODE", "1");
+    for (key, value) in mode.receiver_env {
+        receiver.env(key, value);
+    }
+
+    let (sending, receiving) = if mode.sender_listens {
+        let sending = spawn(sender)?;
+        thread::sleep(STARTUP_DELAY);
+        (sending, spawn(receiver)?)
+    } else {
+        let receiving = spawn(receiver)?;
+        thread::sleep(STARTUP_DELAY);
+        (spawn(sender)?, receiving)
+    };
+
+    // Generous: synthetic pacing plus encoder startup
+    let timeout = Duration::from_secs_f64(frames as f64 / fps * 3.0) + Duration::from_secs(20);
+    let (_, _, sender_cpu) = finish(sending, timeout)?;
+    let (_, output, receiver_cpu) = finish(receiving, Duration::from_secs(10))?;
+
+    let mut stamps = Vec::new();
+    let mut latencies = Vec::new();
+    let mut bytes = 0u64;
+    for line in output.lines() {
+        if let Some((_, rest)) = line.split_once(" timecode ") {
+            let Some((stamp, rest)) = rest.split_once(" us, glass-to-glass latency ") else {
+                continue;
+            };
+            let latency = rest.trim_end_matches(" ms");
+            if let (Ok(stamp), Ok(latency)) = (stamp.parse::<u128>(), latency.parse::<f64>()) {
+                stamps.push(stamp);
+                latencies.push(latency);
+            }
+        } else if let Some(rest) = line.strip_suffix(" bytes received") {
+            bytes = rest
+                .rsplit(' ')
+                .next()
+                .and_then(|b| b.parse().ok())
+                .unwrap_or(0);
+        }
+    }
+
+    let received = latencies.len() as u64;
+    latencies.sort_by(|a, b| a.total_cmp(b));
+    let achieved_fps = match (stamps.first(), stamps.last()) {
+        (Some(first), Some(last)) if stamps.len() > 1 && last > first => {
+            (stamps.len() - 1) as f64 / ((last - first) as f64 / 1e6)
+        }
+        _ => 0.0,
+    };
+    let duration = if achieved_fps > 0.0 {
+        frames as f64 / achieved_fps
+    } else {
+        frames as f64 / fps
+    };
+
+    Ok(Outcome {
+        mode: mode.name,
+        width,
+        height,
+        fps,
+        frames_sent: frames,
+        frames_received: received,
+        achieved_fps,
+        bitrate_kbps: bytes as f64 * 8.0 / duration / 1000.0,
+        sender_cpu_ms: sender_cpu.map(|cpu| cpu.as_secs_f64() * 1000.0 / frames as f64),
+        receiver_cpu_ms: receiver_cpu
+            .map(|cpu| cpu.as_secs_f64() * 1000.0 / received.max(1) as f64),
+        latency_ms: [
+            percentile(&latencies, 50.0),
+            percentile(&latencies, 95.0),
+            percentile(&latencies, 99.0),
+            percentile(&latencies, 100.0),
+        ],
+        loss_percent: 100.0 * frames.saturating_sub(received) as f64 / frames as f64,
+    })
+}
+
+fn env_list<T: std::str::FromStr>(name: &str, default: &str) -> Result<Vec<T>> {
+    let value = std::env::var(name).unwrap_or_else(|_| default.into());
+    value
+        .split(',')
+        .map(|item| {
+            item.trim()
+                .parse()
+                .map_err(|_| anyhow::anyhow!("{name}: cannot parse {item:?}"))
+        })
+        .collect()
+}
+
+fn main() -> Result<()> {
+    let sizes: Vec<(usize, usize)> = env_list::<String>("BENCH_SIZES", "320x240,640x480,1280x720")?
+        .iter()
+        .map(|size| {
+            size.split_once('x')
+                .and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)))
+                .with_context(|| format!("BENCH_SIZES: {size:?} is not WIDTHxHEIGHT"))
+        })
+        .collect::<Result<_>>()?;
+    let rates: Vec<f64> = env_list("BENCH_FPS", "15,30")?;
+    let frames: u64 = std::env::var("BENCH_FRAMES")
+        .ok()
+        .map(|f| f.parse())
+        .transpose()
+        .context("BENCH_FRAMES must be a number")?
+        .unwrap_or(150);
+    let selected: Vec<String> =
+        env_list("BENCH_MODES", "jpeg-message,jpeg-chunked,ts-mjpeg,ts-h264")?;
+    let csv_path = std::env::var("BENCH_CSV").unwrap_or_else(|_| "bench_results.csv".into());
+
+    let mut rows = vec![CSV_HEADER.to_string()];
+    for mode in MODES
+        .iter()
+        .filter(|m| selected.iter().any(|s| s == m.name))
+    {
+        for &(width, height) in &sizes {
+            for &fps in &rates {
+                println!("Running {} at {width}x{height} @ {fps} fps...", mode.name);
+                match run_case(mode, width, height, fps, frames) {
+                    Ok(outcome) => {
+                        println!(
+                            "  {:.0} kbit/s, {:.1} fps, CPU {}/{} ms per frame (send/recv), latency p50 {:.1} ms p95 {:.1} ms, loss {:.1}%",
+                            outcome.bitrate_kbps,
+                            outcome.achieved_fps,
+                            cpu_ms(outcome.sender_cpu_ms, 2),
+                            cpu_ms(outcome.receiver_cpu_ms, 2),
+                            outcome.latency_ms[0],
+                            outcome.latency_ms[1],
+                            outcome.loss_percent
+                        );
+                        rows.push(outcome.csv());
+                    }
+                    Err(e) => eprintln!("  failed: {e:#}"),
+                }
+            }
+        }
+    }
+
+    std::fs::write(&csv_path, rows.join("\n") + "\n")?;
+    println!("Results written to {csv_path}");
+    Ok(())
+}
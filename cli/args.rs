@@ -0,0 +1,609 @@
+//! Command line arguments. Apart from the subcommand everything is optional
+//! so a [`Profile`] can fill it in; defaults and checks apply after the
+//! merge, in `resolve`.
+
+use std::{net::IpAddr, path::PathBuf, time::Duration};
+
+use clap::{
+    builder::{PossibleValuesParser, TypedValueParser},
+    error::ErrorKind,
+    Args, CommandFactory, Parser, Subcommand,
+};
+use rust_srt_playground::{
+    audio::AudioSettings,
+    encode::{EncoderOption, FrameBudget, RateControl, VideoSettings},
+    filter::{Region, Scale},
+    pipeline::{FilterSpec, SourceSpec},
+    rate::{Ladder, QualityRange},
+    sender::Mode,
+    transport::{Connector, Role},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::profile::Profile;
+
+const DEFAULT_LISTEN: &str = ":1234";
+const DEFAULT_CALL: &str = "127.0.0.1:1234";
+
+// Largest chunk that still fits into a single SRT packet.
+const MAX_CHUNK_LEN: u16 = 1456;
+
+#[derive(Parser)]
+#[command(
+    name = "srt-playground",
+    version,
+    about = "Send and receive video over SRT"
+)]
+pub struct Cli {
+    /// Profile file [default: srt-playground.toml]
+    #[arg(long, global = true, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+
+    /// Profile to start from, flags override its fields
+    #[arg(long, global = true, value_name = "NAME")]
+    pub profile: Option<String>,
+
+    /// Print the resolved settings as a profile and exit
+    #[arg(long, global = true)]
+    pub print_config: bool,
+
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Capture, encode and send a stream
+    Send(SendArgs),
+    /// Receive, decode and show a stream
+    Recv(RecvArgs),
+}
+
+#[derive(Args, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct SrtArgs {
+    /// Which side sets up the connection [default: listener for send,
+    /// caller for recv]
+    #[arg(long, value_parser = role_parser())]
+    #[serde(with = "text")]
+    pub role: Option<Role>,
+
+    /// Address to listen on, or the peer to call: HOST:PORT with a host
+    /// name, IPv4 address or [IPv6] address; listeners may leave out the
+    /// host [default: :1234 for listeners, 127.0.0.1:1234 for callers]
+    #[arg(long)]
+    pub addr: Option<String>,
+
+    /// Local interface on hosts with several, listeners use it with the
+    /// port of --addr
+    #[arg(long, value_name = "IP")]
+    pub bind: Option<IpAddr>,
+
+    /// SRT latency, the SRT default when unset
+    #[arg(long, value_name = "MS")]
+    pub latency: Option<u64>,
+
+    /// Local UDP port of a caller [default: any] or a rendezvous [default:
+    /// the port of --addr]
+    #[arg(long, value_name = "PORT")]
+    pub local_port: Option<u16>,
+
+    /// Give up when the handshake takes longer, 0 waits forever [default:
+    /// 30 for callers and rendezvous, 0 for listeners]
+    #[arg(long, value_name = "SECS")]
+    pub connect_timeout: Option<u64>,
+}
+
+impl SrtArgs {
+    pub fn is_unset(&self) -> bool {
+        self.role.is_none()
+            && self.addr.is_none()
+            && self.bind.is_none()
+            && self.latency.is_none()
+            && self.local_port.is_none()
+            && self.connect_timeout.is_none()
+    }
+
+    fn merge(self, profile: Self) -> Self {
+        Self {
+            role: self.role.or(profile.role),
+            addr: self.addr.or(profile.addr),
+            bind: self.bind.or(profile.bind),
+            latency: self.latency.or(profile.latency),
+            local_port: self.local_port.or(profile.local_port),
+            connect_timeout: self.connect_timeout.or(profile.connect_timeout),
+        }
+    }
+
+    /// Fills in the role and address for `default_role`.
+    fn resolve(self, default_role: Role) -> Self {
+        let role = self.role.unwrap_or(default_role);
+        let addr = match (self.addr, role) {
+            (Some(addr), _) => addr,
+            (None, Role::Listener) => DEFAULT_LISTEN.into(),
+            (None, Role::Caller) => DEFAULT_CALL.into(),
+            (None, Role::Rendezvous) => usage_error("--role rendezvous needs the peer's --addr"),
+        };
+        if self.local_port.is_some() && role == Role::Listener {
+            usage_error("a listener's port is part of --addr, --local-port is for callers");
+        }
+        Self {
+            role: Some(role),
+            addr: Some(addr),
+            ..self
+        }
+    }
+
+    pub fn connector(&self) -> Connector {
+        let mut connector = Connector::new(
+            self.role.expect("resolved"),
+            self.addr.as_deref().expect("resolved"),
+        )
+        .latency(self.latency.map(Duration::from_millis));
+        if let Some(ip) = self.bind {
+            connector = connector.bind(ip);
+        }
+        if let Some(port) = self.local_port {
+            connector = connector.local_port(port);
+        }
+        if let Some(secs) = self.connect_timeout {
+            connector = connector.timeout((secs > 0).then(|| Duration::from_secs(secs)));
+        }
+        connector
+    }
+}
+
+#[derive(Args, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct SendArgs {
+    /// Wire format
+    #[arg(long, value_parser = mode_parser())]
+    #[serde(with = "text")]
+    pub mode: Option<Mode>,
+
+    /// camera[:INDEX], synthetic[:WxH[@FPS]] or file:PATH [default: camera]
+    #[arg(long)]
+    #[serde(with = "text")]
+    pub source: Option<SourceSpec>,
+
+    /// Drop frames down to this rate before encoding
+    #[arg(long, value_name = "FPS")]
+    pub max_fps: Option<f64>,
+
+    /// Send only this region of interest of the source frames, e.g.
+    /// 640x360+0+60 for 640x360 pixels starting 60 rows down
+    #[arg(long, value_name = "WxH+X+Y")]
+    #[serde(with = "text")]
+    pub crop: Option<Region>,
+
+    /// Scale frames to this size after --crop, stretching them or keeping
+    /// the aspect ratio with letterbox (black bars) or crop, e.g.
+    /// 640x360,letterbox
+    #[arg(long, value_name = "WxH[,FIT]")]
+    #[serde(with = "text")]
+    pub resize: Option<Scale>,
+
+    /// Drop the color before encoding
+    #[arg(long, value_name = "BOOL", num_args = 0..=1, default_missing_value = "true")]
+    pub grayscale: Option<bool>,
+
+    #[command(flatten)]
+    #[serde(skip_serializing_if = "SrtArgs::is_unset")]
+    pub srt: SrtArgs,
+
+    /// Keep accepting callers while streaming, each joining one gets a
+    /// keyframe (listener only, not jpeg-chunked or Matroska)
+    #[arg(long, value_name = "BOOL", num_args = 0..=1, default_missing_value = "true")]
+    pub fan_out: Option<bool>,
+
+    /// JPEG quality of the JPEG based modes, 1 to 100, the OpenCV default
+    /// when unset
+    #[arg(long)]
+    pub quality: Option<i32>,
+
+    /// Let the JPEG quality follow the link within these bounds, starting
+    /// from --quality, e.g. 30-90
+    #[arg(long, value_name = "MIN-MAX")]
+    #[serde(with = "text")]
+    pub adaptive_quality: Option<QualityRange>,
+
+    /// Keep every JPEG within this many bytes, or within a share of a
+    /// bitrate like 2mbps, lowering the quality and then the resolution
+    #[arg(long, value_name = "BUDGET")]
+    #[serde(with = "text")]
+    pub frame_budget: Option<FrameBudget>,
+
+    /// Resolution and bitrate rungs for ts-h264 and ts-hevc to step between
+    /// with the link, e.g. 1280x720@2500,854x480@1200,640x360@600 (kbit/s)
+    #[arg(long, value_name = "RUNGS")]
+    #[serde(with = "text")]
+    pub ladder: Option<Ladder>,
+
+    /// Encoder speed preset of the encoded modes, e.g. ultrafast for x264:
+    /// faster presets cost bitrate, slower ones encode time on every frame
+    #[arg(long, value_name = "NAME")]
+    pub preset: Option<String>,
+
+    /// Turn off encoder lookahead, which holds back over a second of frames
+    #[arg(long, value_name = "BOOL", num_args = 0..=1, default_missing_value = "true")]
+    pub zero_latency: Option<bool>,
+
+    /// Most B-frames in a row, each delays a frame by one frame interval;
+    /// 0 for the lowest latency
+    #[arg(long, value_name = "N")]
+    pub b_frames: Option<u32>,
+
+    /// Frames from one keyframe to the next: shorter GOPs recover from loss
+    /// sooner but send more keyframe bursts
+    #[arg(long, value_name = "FRAMES")]
+    pub gop: Option<u32>,
+
+    /// Refresh a column of blocks per frame instead of sending keyframes,
+    /// keeping frame sizes and queueing delay even (ts-h264, ts-hevc)
+    #[arg(long, value_name = "BOOL", num_args = 0..=1, default_missing_value = "true")]
+    pub intra_refresh: Option<bool>,
+
+    /// crf:QUALITY for constant quality with bursts on motion, or cbr:KBPS
+    /// for a constant bitrate held to --vbv-buffer
+    #[arg(long, value_name = "MODE")]
+    #[serde(with = "text")]
+    pub rate_control: Option<RateControl>,
+
+    /// VBV buffer of cbr and the ladder rungs, the longest a frame may take
+    /// to drain at the bitrate [default: 1000]
+    #[arg(long, value_name = "MS")]
+    pub vbv_buffer: Option<u64>,
+
+    /// Option for the ffmpeg encoder of the encoded modes, e.g.
+    /// preset=veryfast; repeatable, added after the profile's
+    #[arg(long, value_name = "KEY=VALUE")]
+    #[serde(with = "text_list", skip_serializing_if = "Vec::is_empty")]
+    pub encoder_opt: Vec<EncoderOption>,
+
+    /// Audio track of the MPEG-TS modes: aac or opus, with a test tone
+    /// (,tone=HZ, 440 by default) or a 16-bit WAV file (,wav=PATH)
+    #[arg(long, value_name = "CODEC[,INPUT]")]
+    #[serde(with = "text")]
+    pub audio: Option<AudioSettings>,
+
+    /// Largest SRT message of jpeg-chunked [default: 1200]
+    #[arg(long, value_name = "BYTES")]
+    pub chunk_size: Option<u16>,
+
+    /// Stop after this many frames
+    #[arg(long, value_name = "N")]
+    pub frame_limit: Option<u64>,
+
+    /// Burn the capture time into every frame for latency measurements
+    #[arg(long, value_name = "BOOL", num_args = 0..=1, default_missing_value = "true")]
+    pub timecode: Option<bool>,
+}
+
+impl SendArgs {
+    /// Fields set on the command line win over the profile's.
+    pub fn merge(self, profile: &Profile) -> Self {
+        let other = profile.send.clone().unwrap_or_default();
+        Self {
+            mode: self.mode.or(other.mode),
+            source: self.source.or(other.source),
+            max_fps: self.max_fps.or(other.max_fps),
+            crop: self.crop.or(other.crop),
+            resize: self.resize.or(other.resize),
+            grayscale: self.grayscale.or(other.grayscale),
+            srt: self.srt.merge(other.srt).merge(profile.srt.clone()),
+            fan_out: self.fan_out.or(other.fan_out),
+            quality: self.quality.or(other.quality),
+            adaptive_quality: self.adaptive_quality.or(other.adaptive_quality),
+            frame_budget: self.frame_budget.or(other.frame_budget),
+            ladder: self.ladder.or(other.ladder),
+            preset: self.preset.or(other.preset),
+            zero_latency: self.zero_latency.or(other.zero_latency),
+            b_frames: self.b_frames.or(other.b_frames),
+            gop: self.gop.or(other.gop),
+            intra_refresh: self.intra_refresh.or(other.intra_refresh),
+            rate_control: self.rate_control.or(other.rate_control),
+            vbv_buffer: self.vbv_buffer.or(other.vbv_buffer),
+            // Later options win, so the command line still overrides
+            encoder_opt: [other.encoder_opt, self.encoder_opt].concat(),
+            audio: self.audio.or(other.audio),
+            chunk_size: self.chunk_size.or(other.chunk_size),
+            frame_limit: self.frame_limit.or(other.frame_limit),
+            timecode: self.timecode.or(other.timecode),
+        }
+    }
+
+    /// Preprocessing before the encoder, dropping frames first so the
+    /// others have less to do.
+    pub fn filters(&self) -> Vec<FilterSpec> {
+        let mut filters = Vec::new();
+        filters.extend(self.max_fps.map(FilterSpec::MaxFps));
+        filters.extend(self.crop.map(FilterSpec::Crop));
+        filters.extend(self.resize.map(FilterSpec::Resize));
+        if self.grayscale == Some(true) {
+            filters.push(FilterSpec::Grayscale);
+        }
+        filters
+    }
+
+    /// Encoder settings of the encoded modes.
+    pub fn video_settings(&self) -> VideoSettings {
+        VideoSettings {
+            ladder: self.ladder.clone(),
+            preset: self.preset.clone(),
+            zero_latency: self.zero_latency == Some(true),
+            b_frames: self.b_frames,
+            gop: self.gop,
+            intra_refresh: self.intra_refresh == Some(true),
+            rate_control: self.rate_control,
+            vbv_buffer: self.vbv_buffer.map(Duration::from_millis),
+            options: self.encoder_opt.clone(),
+        }
+    }
+
+    /// Applies the defaults and exits on invalid combinations.
+    pub fn resolve(self) -> Self {
+        let Some(mode) = self.mode else {
+            usage_error("--mode is required, on the command line or in the profile");
+        };
+        if self.max_fps.is_some_and(|fps| fps <= 0.0 || fps.is_nan()) {
+            usage_error("--max-fps must be above zero");
+        }
+        if let Some(quality) = self.quality {
+            if !mode.is_jpeg() {
+                usage_error("--quality only applies to the JPEG based modes");
+            }
+            if !(1..=100).contains(&quality) {
+                usage_error("--quality must be between 1 and 100");
+            }
+        }
+        if self.adaptive_quality.is_some() && !mode.is_jpeg() {
+            usage_error("--adaptive-quality only applies to the JPEG based modes");
+        }
+        if self.frame_budget.is_some() && !mode.is_jpeg() {
+            usage_error("--frame-budget only applies to the JPEG based modes");
+        }
+        let encoder_flags = [
+            ("--ladder", self.ladder.is_some()),
+            ("--preset", self.preset.is_some()),
+            ("--zero-latency", self.zero_latency.is_some()),
+            ("--b-frames", self.b_frames.is_some()),
+            ("--gop", self.gop.is_some()),
+            ("--intra-refresh", self.intra_refresh.is_some()),
+            ("--rate-control", self.rate_control.is_some()),
+            ("--vbv-buffer", self.vbv_buffer.is_some()),
+            ("--encoder-opt", !self.encoder_opt.is_empty()),
+        ];
+        let first_set = encoder_flags.iter().find(|(_, set)| *set);
+        if let (true, Some((flag, _))) = (mode.is_jpeg(), first_set) {
+            usage_error(&format!("{flag} only applies to the encoded modes"));
+        }
+        let cbr = matches!(self.rate_control, Some(RateControl::Cbr(_)));
+        if cbr && self.ladder.is_some() {
+            usage_error("--rate-control cbr and --ladder both set the bitrate, pick one");
+        }
+        if self.vbv_buffer.is_some() && !cbr && self.ladder.is_none() {
+            usage_error("--vbv-buffer needs --rate-control cbr:KBPS or --ladder");
+        }
+        // A rung switch restarts the muxer, which MPEG-TS receivers follow
+        if self.ladder.is_some() && !matches!(mode, Mode::TsH264 | Mode::TsHevc) {
+            usage_error("--ladder only applies to --mode ts-h264 and ts-hevc");
+        }
+        if self.intra_refresh == Some(true) && !matches!(mode, Mode::TsH264 | Mode::TsHevc) {
+            usage_error("--intra-refresh only applies to --mode ts-h264 and ts-hevc");
+        }
+        if self.audio.is_some() && !matches!(mode, Mode::TsMjpeg | Mode::TsH264 | Mode::TsHevc) {
+            usage_error("--audio only applies to --mode ts-mjpeg, ts-h264 and ts-hevc");
+        }
+        if let Some(len) = self.chunk_size {
+            if mode != Mode::JpegChunked {
+                usage_error("--chunk-size only applies to --mode jpeg-chunked");
+            }
+            if !(1..=MAX_CHUNK_LEN).contains(&len) {
+                usage_error(&format!(
+                    "--chunk-size must be between 1 and {MAX_CHUNK_LEN}"
+                ));
+            }
+        }
+        let srt = self.srt.resolve(Role::Listener);
+        if self.fan_out == Some(true) {
+            if srt.role != Some(Role::Listener) {
+                usage_error("--fan-out needs the listener role");
+            }
+            if !mode.is_joinable() {
+                usage_error(&format!(
+                    "--fan-out cannot be used with --mode {mode}, receivers cannot join it midway"
+                ));
+            }
+        }
+        Self {
+            source: Some(self.source.unwrap_or(SourceSpec::Camera(0))),
+            srt,
+            timecode: Some(self.timecode.unwrap_or(false)),
+            ..self
+        }
+    }
+}
+
+#[derive(Args, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct RecvArgs {
+    /// Expected wire format, detected from the first payloads when unset
+    #[arg(long, value_parser = mode_parser())]
+    #[serde(with = "text")]
+    pub mode: Option<Mode>,
+
+    #[command(flatten)]
+    #[serde(skip_serializing_if = "SrtArgs::is_unset")]
+    pub srt: SrtArgs,
+
+    /// Do not open a window
+    #[arg(long, value_name = "BOOL", num_args = 0..=1, default_missing_value = "true")]
+    pub headless: Option<bool>,
+
+    /// Read the sender's burned-in timecode and report glass-to-glass latency
+    #[arg(long, value_name = "BOOL", num_args = 0..=1, default_missing_value = "true")]
+    pub timecode: Option<bool>,
+
+    /// Serve the frames to browsers, e.g. 0.0.0.0:8080
+    #[arg(long, value_name = "ADDR")]
+    pub mjpeg_addr: Option<String>,
+
+    /// Write frames into this directory
+    #[arg(long, value_name = "DIR")]
+    pub dump_dir: Option<PathBuf>,
+
+    /// Keep every Nth frame [default: 1]
+    #[arg(long, value_name = "N")]
+    pub dump_every: Option<u64>,
+
+    /// Keep at most one frame per interval, instead of --dump-every
+    #[arg(long, value_name = "MS")]
+    pub dump_interval: Option<u64>,
+
+    /// Image format of dumped frames [default: jpg]
+    #[arg(long, value_parser = ["jpg", "png"])]
+    pub dump_format: Option<String>,
+
+    /// Pace display on sender timestamps with this much delay, which has
+    /// to cover the SRT latency as well
+    #[arg(long, value_name = "MS")]
+    pub playout_delay: Option<u64>,
+
+    /// Upper bound of the adaptive playout delay [default: 4x --playout-delay]
+    #[arg(long, value_name = "MS")]
+    pub playout_max_delay: Option<u64>,
+}
+
+impl RecvArgs {
+    /// Fields set on the command line win over the profile's.
+    pub fn merge(self, profile: &Profile) -> Self {
+        let other = profile.recv.clone().unwrap_or_default();
+        Self {
+            mode: self.mode.or(other.mode),
+            srt: self.srt.merge(other.srt).merge(profile.srt.clone()),
+            headless: self.headless.or(other.headless),
+            timecode: self.timecode.or(other.timecode),
+            mjpeg_addr: self.mjpeg_addr.or(other.mjpeg_addr),
+            dump_dir: self.dump_dir.or(other.dump_dir),
+            dump_every: self.dump_every.or(other.dump_every),
+            dump_interval: self.dump_interval.or(other.dump_interval),
+            dump_format: self.dump_format.or(other.dump_format),
+            playout_delay: self.playout_delay.or(other.playout_delay),
+            playout_max_delay: self.playout_max_delay.or(other.playout_max_delay),
+        }
+    }
+
+    /// Applies the defaults and exits on invalid combinations.
+    pub fn resolve(self) -> Self {
+        let dumping = self.dump_dir.is_some();
+        if !dumping
+            && (self.dump_every.is_some()
+                || self.dump_interval.is_some()
+                || self.dump_format.is_some())
+        {
+            usage_error("the --dump-* options need --dump-dir");
+        }
+        if self.dump_every.is_some() && self.dump_interval.is_some() {
+            usage_error("--dump-every and --dump-interval exclude each other");
+        }
+        if self.dump_every == Some(0) {
+            usage_error("--dump-every must be at least 1");
+        }
+        if let Some(format) = &self.dump_format {
+            if format != "jpg" && format != "png" {
+                usage_error("--dump-format must be jpg or png");
+            }
+        }
+        if self.playout_max_delay.is_some() && self.playout_delay.is_none() {
+            usage_error("--playout-max-delay needs --playout-delay");
+        }
+
+        Self {
+            srt: self.srt.resolve(Role::Caller),
+            headless: Some(self.headless.unwrap_or(false)),
+            timecode: Some(self.timecode.unwrap_or(false)),
+            dump_every: (dumping && self.dump_interval.is_none())
+                .then(|| self.dump_every.unwrap_or(1)),
+            dump_format: dumping.then(|| self.dump_format.unwrap_or_else(|| "jpg".into())),
+            playout_max_delay: self
+                .playout_delay
+                .map(|target| self.playout_max_delay.unwrap_or(target * 4)),
+            ..self
+        }
+    }
+}
+
+fn mode_parser() -> impl TypedValueParser<Value = Mode> {
+    PossibleValuesParser::new(Mode::ALL.map(|mode| mode.to_string()))
+        .try_map(|mode| mode.parse::<Mode>())
+}
+
+fn role_parser() -> impl TypedValueParser<Value = Role> {
+    PossibleValuesParser::new(["caller", "listener", "rendezvous"])
+        .try_map(|role| role.parse::<Role>())
+}
+
+/// Exits like clap does for invalid arguments.
+pub fn usage_error(message: &str) -> ! {
+    Cli::command()
+        .error(ErrorKind::ArgumentConflict, message)
+        .exit()
+}
+
+/// Profile values in the same text form as on the command line.
+mod text {
+    use std::{fmt::Display, str::FromStr};
+
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<T: Display, S: Serializer>(
+        value: &Option<T>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match value {
+            Some(value) => serializer.collect_str(value),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+    where
+        T: FromStr,
+        T::Err: Display,
+        D: Deserializer<'de>,
+    {
+        Option::<String>::deserialize(deserializer)?
+            .map(|text| text.parse().map_err(D::Error::custom))
+            .transpose()
+    }
+}
+
+/// Like [`text`], for lists.
+mod text_list {
+    use std::{fmt::Display, str::FromStr};
+
+    use serde::{de::Error, ser::SerializeSeq, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<T: Display, S: Serializer>(
+        values: &[T],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(values.len()))?;
+        for value in values {
+            seq.serialize_element(&value.to_string())?;
+        }
+        seq.end()
+    }
+
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<Vec<T>, D::Error>
+    where
+        T: FromStr,
+        T::Err: Display,
+        D: Deserializer<'de>,
+    {
+        Vec::<String>::deserialize(deserializer)?
+            .iter()
+            .map(|text| text.parse().map_err(D::Error::custom))
+            .collect()
+    }
+}
@@ -0,0 +1,183 @@
+//! `srt-playground`: every sender and receiver of the playground in one
+//! binary, configured with flags instead of hardcoded values.
+//!
+//! ```text
+//! srt-playground send --mode ts-h264 --source synthetic:1280x720@60 --latency 200
+//! srt-playground recv --addr 127.0.0.1:1234 --timecode
+//! srt-playground send --mode jpeg-chunked --role caller --addr 127.0.0.1:4200
+//! srt-playground recv --mode jpeg-chunked --role listener --addr 0.0.0.0:4200
+//! srt-playground --profile wan-robust send --addr 203.0.113.7:1234 --role caller
+//! srt-playground send --mode ts-h264 --role rendezvous --addr 198.51.100.4:4200
+//! srt-playground send --mode ts-h264 --fan-out --gop 300
+//! srt-playground send --mode ts-h264 --audio opus,wav=clip.wav
+//! srt-playground send --mode jpeg-message --crop 640x360+0+60 --grayscale --max-fps 5
+//! ```
+//!
+//! Profiles are described in [`profile`]; `--print-config` shows what a
+//! profile and the flags resolve to.
+
+mod args;
+mod profile;
+
+use std::time::Duration;
+
+use anyhow::Result;
+use clap::Parser;
+use opencv::prelude::*;
+use rust_srt_playground::{
+    dump::{DumpSchedule, FrameDumper},
+    mjpeg::MjpegServer,
+    output::FrameOutput,
+    pipeline::StreamInfo,
+    playout::PlayoutBuffer,
+    receiver::VideoReceiver,
+    sender::VideoSender,
+    timecode::LatencyStats,
+};
+
+use args::{usage_error, Cli, Command, RecvArgs, SendArgs};
+use profile::Profile;
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    pretty_env_logger::init();
+
+    let cli = Cli::parse();
+    let profile = match &cli.profile {
+        Some(name) => {
+            let path = cli
+                .config
+                .as_deref()
+                .unwrap_or(profile::DEFAULT_FILE.as_ref());
+            Profile::load(path, name)?
+        }
+        None if cli.config.is_some() => usage_error("--config needs --profile to pick a profile"),
+        None => Profile::default(),
+    };
+    // The name the resolved settings are printed under
+    let name = cli.profile.as_deref().unwrap_or("resolved");
+    if !cli.print_config && cli.profile.is_some() {
+        println!("Profile: {name}");
+    }
+
+    match cli.command {
+        Command::Send(args) => {
+            let args = args.merge(&profile).resolve();
+            if cli.print_config {
+                return Profile::sending(&args).print(name);
+            }
+            send(args).await
+        }
+        Command::Recv(args) => {
+            let args = args.merge(&profile).resolve();
+            if cli.print_config {
+                return Profile::receiving(&args).print(name);
+            }
+            recv(args).await
+        }
+    }
+}
+
+async fn send(args: SendArgs) -> Result<()> {
+    let mode = args.mode.expect("resolved");
+    let source = args.source.as_ref().expect("resolved").capture()?;
+    let info = StreamInfo::of(&*source);
+    println!(
+        "Capturing {}x{} @ {} fps",
+        info.width, info.height, info.fps
+    );
+
+    let connector = args.srt.connector();
+    let sender = match args.fan_out {
+        Some(true) => VideoSender::with_transport(Box::new(connector.fan_out().await?), mode),
+        _ => VideoSender::new(connector.connect().await?, mode),
+    };
+
+    let mut sender = sender
+        .burn_timecode(args.timecode == Some(true))
+        .frame_limit(args.frame_limit)
+        .adaptive_quality(args.adaptive_quality)
+        .frame_budget(args.frame_budget)
+        .filters(args.filters())
+        .video_settings(args.video_settings())
+        .audio(args.audio.clone());
+    if let Some(quality) = args.quality {
+        sender = sender.jpeg_quality(quality);
+    }
+    if let Some(len) = args.chunk_size {
+        sender = sender.chunk_len(len.into());
+    }
+
+    println!("Streaming {mode}");
+    let frame_count = sender.run(source).await?;
+    println!("Sender finished streaming, {frame_count} frames.");
+    Ok(())
+}
+
+async fn recv(args: RecvArgs) -> Result<()> {
+    let socket = args.srt.connector().connect().await?;
+
+    let receiver = match args.mode {
+        Some(mode) => VideoReceiver::new(socket, mode.format()),
+        None => {
+            println!("Probing stream format...");
+            VideoReceiver::detect(socket).await?
+        }
+    };
+    let format = receiver.format();
+    println!("Receiving {format}");
+
+    let playout = args
+        .playout_delay
+        .zip(args.playout_max_delay)
+        .map(|(target, max)| {
+            PlayoutBuffer::new(Duration::from_millis(target), Duration::from_millis(max))
+        });
+    let mut receiver = receiver.playout(playout);
+
+    let mut output = FrameOutput::new((args.headless != Some(true)).then_some("SRT Playground"))?;
+    output.latency = (args.timecode == Some(true)).then(LatencyStats::new);
+    if let Some(dir) = args.dump_dir {
+        let schedule = match args.dump_interval {
+            Some(ms) => DumpSchedule::Interval(Duration::from_millis(ms)),
+            None => DumpSchedule::EveryNth(args.dump_every.expect("resolved")),
+        };
+        let format = args.dump_format.as_deref().expect("resolved");
+        output.dumper = Some(FrameDumper::new(dir, format, schedule)?);
+    }
+    if let Some(addr) = &args.mjpeg_addr {
+        output.mjpeg = Some(MjpegServer::bind(addr).await?);
+        println!("MJPEG viewer on http://{addr}/");
+    }
+
+    let mut frame_count = 0;
+    while let Some(frame) = receiver.next_frame().await? {
+        frame_count = frame.number;
+        println!(
+            "Frame #{} decoded: {}x{}",
+            frame.number,
+            frame.image.cols(),
+            frame.image.rows()
+        );
+        if !output.present(&frame)? {
+            println!("Exit requested by user");
+            break;
+        }
+    }
+
+    output.summary();
+    if let Some(playout) = receiver.playout_stats() {
+        println!(
+            "Playout: {} frames shown, {} late, final delay {:?}",
+            playout.played(),
+            playout.late(),
+            playout.delay()
+        );
+    }
+    println!(
+        "SRT stream closed after {} decoded frames ({format}), {} bytes received",
+        frame_count,
+        receiver.received_bytes()
+    );
+    Ok(())
+}
@@ -0,0 +1,79 @@
+//! Named profiles in a TOML file, so a tested set of settings can be picked
+//! with `--profile NAME` instead of repeating the flags:
+//!
+//! ```toml
+//! [lan-lowlatency.srt]
+//! latency = 40
+//!
+//! [lan-lowlatency.send]
+//! mode = "jpeg-message"
+//! quality = 70
+//!
+//! [lan-lowlatency.recv]
+//! mode = "jpeg-message"
+//! ```
+//!
+//! `send` and `recv` take the flags of the subcommand without the leading
+//! dashes. SRT settings go into `srt`, shared by both sides, or into
+//! `send.srt` and `recv.srt` for one side only.
+
+use std::{collections::BTreeMap, path::Path};
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::args::{RecvArgs, SendArgs, SrtArgs};
+
+pub const DEFAULT_FILE: &str = "srt-playground.toml";
+
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Profile {
+    #[serde(skip_serializing_if = "SrtArgs::is_unset")]
+    pub srt: SrtArgs,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub send: Option<SendArgs>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recv: Option<RecvArgs>,
+}
+
+impl Profile {
+    pub fn load(path: &Path, name: &str) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("cannot read profiles from {}", path.display()))?;
+        let mut profiles: BTreeMap<String, Profile> = toml::from_str(&text)
+            .with_context(|| format!("invalid profile file {}", path.display()))?;
+
+        match profiles.remove(name) {
+            Some(profile) => Ok(profile),
+            None => bail!(
+                "no profile {name:?} in {}, it has: {}",
+                path.display(),
+                profiles.into_keys().collect::<Vec<_>>().join(", ")
+            ),
+        }
+    }
+
+    /// The resolved settings of a sender, as a profile.
+    pub fn sending(args: &SendArgs) -> Self {
+        Self {
+            send: Some(args.clone()),
+            ..Self::default()
+        }
+    }
+
+    /// The resolved settings of a receiver, as a profile.
+    pub fn receiving(args: &RecvArgs) -> Self {
+        Self {
+            recv: Some(args.clone()),
+            ..Self::default()
+        }
+    }
+
+    /// Prints the profile as `name` in the file format, ready to be pasted
+    /// into a profile file.
+    pub fn print(&self, name: &str) -> Result<()> {
+        print!("{}", toml::to_string(&BTreeMap::from([(name, self)]))?);
+        Ok(())
+    }
+}
@@ -0,0 +1,41 @@
+//! Runs a sender pipeline assembled from a text description, given as the
+//! arguments or in `PIPELINE`, see `rust_srt_playground::pipeline`:
+//!
+//! ```text
+//! pipeline file:clip.mp4 ! resize:640x480 ! h264 ! mpegts ! srt-call:127.0.0.1:1234
+//! ```
+//!
+//! `FRAME_LIMIT` stops after that many source frames.
+
+use anyhow::{Context, Result};
+use rust_srt_playground::{
+    pipeline::{Pipeline, PipelineSpec},
+    source,
+};
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    pretty_env_logger::init();
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let description = if args.is_empty() {
+        std::env::var("PIPELINE").context("pass a pipeline description or set PIPELINE")?
+    } else {
+        args.join(" ")
+    };
+    let spec: PipelineSpec = description.parse()?;
+    println!("Pipeline: {spec}");
+
+    let pipeline = Pipeline::from_spec(&spec)
+        .await?
+        .frame_limit(source::frame_limit_from_env()?);
+    let info = pipeline.info();
+    println!("Encoding {}x{} @ {} fps", info.width, info.height, info.fps);
+
+    let stats = pipeline.run().await?;
+    println!(
+        "Sent {} frames in {} packets, {} SRT messages, {} bytes",
+        stats.frames, stats.packets, stats.messages, stats.bytes
+    );
+    Ok(())
+}
@@ -0,0 +1,365 @@
+//! Audio for the MPEG-TS senders: a generated test tone or a WAV file,
+//! encoded as AAC or Opus and muxed next to the video by
+//! [`crate::packetize::MuxPacketizer`].
+//!
+//! Neither input has a clock of its own, so the audio follows the video
+//! timeline. Before each video packet the muxer gets every audio frame that
+//! starts before the packet's DTS. Both timelines start at zero, so audio
+//! and video stay in sync however the video is captured and paced.
+//!
+//! Selected from the environment with `AUDIO`, in the form of
+//! [`AudioSettings`], e.g. `AUDIO=aac` or `AUDIO=opus,wav=clip.wav`.
+
+use std::{f64::consts::TAU, fmt, fs, str::FromStr};
+
+use ac_ffmpeg::{
+    codec::{
+        audio::{
+            frame::get_sample_format, AudioEncoder, AudioFrameMut, AudioResampler, ChannelLayout,
+        },
+        CodecParameters, Encoder as _,
+    },
+    packet::Packet,
+    time::{TimeBase, Timestamp},
+};
+use anyhow::{bail, Context, Result};
+
+/// Frequency of the test tone unless one is given.
+pub const DEFAULT_TONE_HZ: u32 = 440;
+
+// The tone is generated at this rate in stereo, about -14 dBFS
+const TONE_SAMPLE_RATE: u32 = 48_000;
+const TONE_AMPLITUDE: f64 = 0.2 * i16::MAX as f64;
+
+/// Opus only takes a few rates, AAC any; both are encoded at this one.
+const ENCODER_SAMPLE_RATE: u32 = 48_000;
+
+// Input samples per channel read at a time
+const READ_SAMPLES: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AudioCodec {
+    Aac,
+    Opus,
+}
+
+impl AudioCodec {
+    /// Name of the ffmpeg encoder.
+    fn encoder(self) -> &'static str {
+        match self {
+            AudioCodec::Aac => "aac",
+            AudioCodec::Opus => "libopus",
+        }
+    }
+
+    /// Sample format the encoder takes.
+    fn sample_format(self) -> &'static str {
+        match self {
+            AudioCodec::Aac => "fltp",
+            AudioCodec::Opus => "s16",
+        }
+    }
+
+    fn bit_rate(self) -> u64 {
+        match self {
+            AudioCodec::Aac => 128_000,
+            AudioCodec::Opus => 96_000,
+        }
+    }
+}
+
+impl fmt::Display for AudioCodec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            AudioCodec::Aac => "aac",
+            AudioCodec::Opus => "opus",
+        })
+    }
+}
+
+impl FromStr for AudioCodec {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "aac" => Ok(AudioCodec::Aac),
+            "opus" => Ok(AudioCodec::Opus),
+            _ => bail!("unknown audio codec {s:?}, expected aac or opus"),
+        }
+    }
+}
+
+/// Where the samples come from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AudioInput {
+    /// A sine at this frequency.
+    Tone(u32),
+    /// A 16-bit PCM WAV file, played in a loop.
+    Wav(String),
+}
+
+impl fmt::Display for AudioInput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AudioInput::Tone(hz) => write!(f, "tone={hz}"),
+            AudioInput::Wav(path) => write!(f, "wav={path}"),
+        }
+    }
+}
+
+/// The audio track of a stream, written as `CODEC[,tone[=HZ]|,wav=PATH]`:
+/// `aac` is a 440 Hz tone in AAC, `opus,wav=clip.wav` a WAV file in Opus.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AudioSettings {
+    pub codec: AudioCodec,
+    pub input: AudioInput,
+}
+
+impl fmt::Display for AudioSettings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{},{}", self.codec, self.input)
+    }
+}
+
+impl FromStr for AudioSettings {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (codec, input) = s.split_once(',').unwrap_or((s, "tone"));
+        let input = match input.split_once('=') {
+            None if input == "tone" => AudioInput::Tone(DEFAULT_TONE_HZ),
+            Some(("tone", hz)) => match hz.parse() {
+                Ok(hz) if hz > 0 => AudioInput::Tone(hz),
+                _ => bail!("tone frequency {hz:?} is not a positive number of Hz"),
+            },
+            Some(("wav", path)) if !path.is_empty() => AudioInput::Wav(path.into()),
+            _ => bail!("unknown audio input {input:?}, expected tone[=HZ] or wav=PATH"),
+        };
+        Ok(Self {
+            codec: codec.parse()?,
+            input,
+        })
+    }
+}
+
+/// Reads `AUDIO`, no audio when unset.
+pub fn settings_from_env() -> Result<Option<AudioSettings>> {
+    match std::env::var("AUDIO") {
+        Ok(settings) => Ok(Some(settings.parse().context("AUDIO")?)),
+        Err(_) => Ok(None),
+    }
+}
+
+/// Interleaved 16-bit samples, endless.
+enum Signal {
+    Tone { hz: u32, position: u64 },
+    Wav { samples: Vec<i16>, position: usize },
+}
+
+impl Signal {
+    /// The signal and its sample rate and channel count.
+    fn open(input: &AudioInput) -> Result<(Self, u32, u32)> {
+        Ok(match input {
+            AudioInput::Tone(hz) => (
+                Signal::Tone {
+                    hz: *hz,
+                    position: 0,
+                },
+                TONE_SAMPLE_RATE,
+                2,
+            ),
+            AudioInput::Wav(path) => {
+                let (samples, sample_rate, channels) = read_wav(path)?;
+                (
+                    Signal::Wav {
+                        samples,
+                        position: 0,
+                    },
+                    sample_rate,
+                    channels,
+                )
+            }
+        })
+    }
+
+    fn fill(&mut self, out: &mut [i16], channels: usize) {
+        match self {
+            Signal::Tone { hz, position } => {
+                for frame in out.chunks_exact_mut(channels) {
+                    let phase = TAU * (*position * *hz as u64) as f64 / TONE_SAMPLE_RATE as f64;
+                    frame.fill((phase.sin() * TONE_AMPLITUDE) as i16);
+                    *position = (*position + 1) % TONE_SAMPLE_RATE as u64;
+                }
+            }
+            Signal::Wav { samples, position } => {
+                for sample in out {
+                    *sample = samples[*position];
+                    *position = (*position + 1) % samples.len();
+                }
+            }
+        }
+    }
+}
+
+/// Samples, sample rate and channel count of a 16-bit PCM WAV file.
+fn read_wav(path: &str) -> Result<(Vec<i16>, u32, u32)> {
+    let data = fs::read(path).with_context(|| format!("cannot read {path}"))?;
+    if data.len() < 12 || &data[..4] != b"RIFF" || &data[8..12] != b"WAVE" {
+        bail!("{path} is not a WAV file");
+    }
+    let (mut format, mut pcm) = (None, None);
+    let mut chunks = &data[12..];
+    while chunks.len() >= 8 {
+        let len = u32::from_le_bytes(chunks[4..8].try_into().unwrap()) as usize;
+        // Streaming writers leave the length of the last chunk open
+        let body = &chunks[8..(8 + len).min(chunks.len())];
+        match &chunks[..4] {
+            b"fmt " => format = Some(body),
+            b"data" => pcm = Some(body),
+            _ => {}
+        }
+        // Chunks are padded to an even length
+        chunks = chunks.get(8 + len + len % 2..).unwrap_or_default();
+    }
+
+    let format = format
+        .filter(|f| f.len() >= 16)
+        .with_context(|| format!("{path} has no format chunk"))?;
+    let field = |at: usize| u16::from_le_bytes([format[at], format[at + 1]]);
+    let (tag, channels, bits) = (field(0), field(2), field(14));
+    let sample_rate = u32::from_le_bytes(format[4..8].try_into().unwrap());
+    if tag != 1 || bits != 16 {
+        bail!("{path} is not 16-bit PCM, the only WAV format supported");
+    }
+    if !(1..=2).contains(&channels) {
+        bail!("{path} has {channels} channels, only mono and stereo are supported");
+    }
+    let samples: Vec<i16> = pcm
+        .with_context(|| format!("{path} has no data chunk"))?
+        .chunks_exact(2)
+        .map(|s| i16::from_le_bytes([s[0], s[1]]))
+        .collect();
+    // Whole frames only, and at least one to loop over
+    let samples = samples[..samples.len() - samples.len() % channels as usize].to_vec();
+    if samples.is_empty() {
+        bail!("{path} has no samples");
+    }
+    Ok((samples, sample_rate, channels.into()))
+}
+
+/// Encodes an [`AudioSettings`] input into packets for stream index 1,
+/// next to the video at 0.
+pub struct AudioTrack {
+    signal: Signal,
+    sample_rate: u32,
+    channels: u32,
+    layout: ChannelLayout,
+    resampler: AudioResampler,
+    encoder: AudioEncoder,
+    // Input samples per channel read, and encoder samples per channel
+    // encoded, so far
+    read: i64,
+    encoded: i64,
+}
+
+impl AudioTrack {
+    pub fn new(settings: &AudioSettings) -> Result<Self> {
+        let (signal, sample_rate, channels) = Signal::open(&settings.input)?;
+        let layout = ChannelLayout::from_channels(channels)
+            .with_context(|| format!("no channel layout for {channels} channels"))?;
+        let name = settings.codec.encoder();
+        let encoder_format = get_sample_format(settings.codec.sample_format());
+        let encoder = AudioEncoder::builder(name)?
+            .sample_rate(ENCODER_SAMPLE_RATE)
+            .sample_format(encoder_format)
+            .channel_layout(layout.clone())
+            .bit_rate(settings.codec.bit_rate())
+            .build()
+            .with_context(|| format!("cannot open {name}"))?;
+        let resampler = AudioResampler::builder()
+            .source_channel_layout(layout.clone())
+            .source_sample_format(get_sample_format("s16"))
+            .source_sample_rate(sample_rate)
+            .target_channel_layout(layout.clone())
+            .target_sample_format(encoder_format)
+            .target_sample_rate(ENCODER_SAMPLE_RATE)
+            .target_frame_samples(encoder.samples_per_frame())
+            .build()?;
+        println!(
+            "Audio: {} from {}, {sample_rate} Hz, {channels} channels",
+            settings.codec, settings.input
+        );
+
+        Ok(Self {
+            signal,
+            sample_rate,
+            channels,
+            layout,
+            resampler,
+            encoder,
+            read: 0,
+            encoded: 0,
+        })
+    }
+
+    pub fn codec_parameters(&self) -> CodecParameters {
+        self.encoder.codec_parameters().into()
+    }
+
+    /// Encodes the input up to `until` on the video timeline and returns
+    /// the packets that are ready.
+    pub fn packets_until(&mut self, until: Timestamp) -> Result<Vec<Packet>> {
+        let input_time_base = TimeBase::new(1, self.sample_rate as i32);
+        while Timestamp::new(self.read, input_time_base) < until {
+            let mut samples = vec![0; READ_SAMPLES * self.channels as usize];
+            self.signal.fill(&mut samples, self.channels as usize);
+            let mut frame = AudioFrameMut::silence(
+                &self.layout,
+                get_sample_format("s16"),
+                self.sample_rate,
+                READ_SAMPLES,
+            );
+            {
+                let mut planes = frame.planes_mut();
+                let data = planes[0].data_mut();
+                for (dst, sample) in data.chunks_exact_mut(2).zip(&samples) {
+                    dst.copy_from_slice(&sample.to_le_bytes());
+                }
+            }
+            let frame = frame
+                .with_pts(Timestamp::new(self.read, input_time_base))
+                .freeze();
+            self.read += READ_SAMPLES as i64;
+            self.resampler.push(frame)?;
+            self.encode_resampled()?;
+        }
+        self.take()
+    }
+
+    /// Drains the resampler and the encoder at the end of the stream.
+    pub fn finish(&mut self) -> Result<Vec<Packet>> {
+        self.resampler.flush()?;
+        self.encode_resampled()?;
+        self.encoder.flush()?;
+        self.take()
+    }
+
+    fn encode_resampled(&mut self) -> Result<()> {
+        let time_base = TimeBase::new(1, ENCODER_SAMPLE_RATE as i32);
+        while let Some(frame) = self.resampler.take()? {
+            let samples = frame.samples() as i64;
+            self.encoder
+                .push(frame.with_pts(Timestamp::new(self.encoded, time_base)))?;
+            self.encoded += samples;
+        }
+        Ok(())
+    }
+
+    fn take(&mut self) -> Result<Vec<Packet>> {
+        let mut packets = Vec::new();
+        while let Some(packet) = self.encoder.take()? {
+            packets.push(packet.with_stream_index(1));
+        }
+        Ok(packets)
+    }
+}
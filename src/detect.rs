@@ -0,0 +1,84 @@
+//! Guesses which sender produced a stream by looking at its first payloads.
+
+use std::fmt;
+
+use bytes::Bytes;
+
+use crate::framing::MAX_FRAME_LEN;
+
+pub const TS_PACKET_LEN: usize = 188;
+pub const TS_SYNC_BYTE: u8 = 0x47;
+
+/// Payloads to inspect before giving up on detection.
+pub const MAX_PROBE_PAYLOADS: usize = 32;
+
+// Consecutive sync bytes at a 188 byte stride required to call it MPEG-TS.
+const TS_MIN_SYNCS: usize = 3;
+
+const JPEG_SOI: [u8; 3] = [0xFF, 0xD8, 0xFF];
+// EBML header ID, the first bytes of every Matroska and WebM stream
+const EBML_MAGIC: [u8; 4] = [0x1A, 0x45, 0xDF, 0xA3];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamFormat {
+    /// One complete JPEG per SRT message (v1, v5).
+    JpegMessage,
+    /// u32 length prefixed JPEG split over several messages (v4).
+    ChunkedJpeg,
+    /// MPEG transport stream (v2 MJPEG, v3 H.264 or HEVC).
+    MpegTs,
+    /// Live Matroska (VP9, AV1).
+    Matroska,
+}
+
+impl fmt::Display for StreamFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            StreamFormat::JpegMessage => "JPEG per SRT message (v1/v5)",
+            StreamFormat::ChunkedJpeg => "length-prefixed chunked JPEG (v4)",
+            StreamFormat::MpegTs => "MPEG-TS (v2/v3)",
+            StreamFormat::Matroska => "Matroska (VP9/AV1)",
+        })
+    }
+}
+
+/// Returns the detected format, or `None` if `payloads` are not conclusive yet.
+pub fn detect(payloads: &[Bytes]) -> Option<StreamFormat> {
+    let first = payloads.first()?;
+
+    if first.starts_with(&JPEG_SOI) {
+        return Some(StreamFormat::JpegMessage);
+    }
+
+    if first.starts_with(&EBML_MAGIC) {
+        return Some(StreamFormat::Matroska);
+    }
+
+    if first.len() >= 4 + JPEG_SOI.len() && first[4..].starts_with(&JPEG_SOI) {
+        let len = u32::from_be_bytes([first[0], first[1], first[2], first[3]]) as usize;
+        if len > 0 && len <= MAX_FRAME_LEN {
+            return Some(StreamFormat::ChunkedJpeg);
+        }
+    }
+
+    // The TS muxer output is not necessarily split on packet boundaries, so look
+    // for the sync byte pattern anywhere in the concatenated payloads.
+    let joined: Vec<u8> = payloads.iter().flat_map(|p| p.iter().copied()).collect();
+    if find_ts_sync(&joined).is_some() {
+        return Some(StreamFormat::MpegTs);
+    }
+
+    None
+}
+
+/// Offset of the first TS packet in `data`, if `data` looks like MPEG-TS.
+pub fn find_ts_sync(data: &[u8]) -> Option<usize> {
+    (0..TS_PACKET_LEN.min(data.len())).find(|&offset| {
+        let syncs = data[offset..]
+            .iter()
+            .step_by(TS_PACKET_LEN)
+            .take_while(|&&b| b == TS_SYNC_BYTE)
+            .count();
+        syncs >= TS_MIN_SYNCS
+    })
+}
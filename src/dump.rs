@@ -0,0 +1,100 @@
+//! Writes decoded frames to disk, used by the receivers in headless mode.
+//!
+//! Configured from the environment:
+//! - `DUMP_DIR`: output directory, dumping is disabled when unset
+//! - `DUMP_EVERY`: keep every Nth frame (default 1)
+//! - `DUMP_INTERVAL_MS`: keep at most one frame per interval, overrides `DUMP_EVERY`
+//! - `DUMP_FORMAT`: `jpg` (default) or `png`
+
+use std::{
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context, Result};
+use opencv::{core::Vector, imgcodecs, prelude::*};
+
+#[derive(Debug, Clone, Copy)]
+pub enum DumpSchedule {
+    EveryNth(u64),
+    Interval(Duration),
+}
+
+pub struct FrameDumper {
+    dir: PathBuf,
+    extension: &'static str,
+    schedule: DumpSchedule,
+    seen: u64,
+    last_written: Option<Instant>,
+}
+
+impl FrameDumper {
+    pub fn new(dir: impl Into<PathBuf>, extension: &str, schedule: DumpSchedule) -> Result<Self> {
+        let extension = match extension.to_ascii_lowercase().as_str() {
+            "jpg" | "jpeg" => "jpg",
+            "png" => "png",
+            other => bail!("unsupported dump format {other:?}, expected jpg or png"),
+        };
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("cannot create dump directory {}", dir.display()))?;
+
+        Ok(Self {
+            dir,
+            extension,
+            schedule,
+            seen: 0,
+            last_written: None,
+        })
+    }
+
+    /// Builds a dumper from the `DUMP_*` variables, `None` when `DUMP_DIR` is unset.
+    pub fn from_env() -> Result<Option<Self>> {
+        let Ok(dir) = std::env::var("DUMP_DIR") else {
+            return Ok(None);
+        };
+        let format = std::env::var("DUMP_FORMAT").unwrap_or_else(|_| "jpg".into());
+
+        let schedule = match std::env::var("DUMP_INTERVAL_MS") {
+            Ok(ms) => DumpSchedule::Interval(Duration::from_millis(
+                ms.parse().context("DUMP_INTERVAL_MS must be a number")?,
+            )),
+            Err(_) => {
+                let every: u64 = match std::env::var("DUMP_EVERY") {
+                    Ok(n) => n.parse().context("DUMP_EVERY must be a number")?,
+                    Err(_) => 1,
+                };
+                DumpSchedule::EveryNth(every.max(1))
+            }
+        };
+
+        Self::new(dir, &format, schedule).map(Some)
+    }
+
+    /// Writes `frame` if the schedule selects it, returning the written path.
+    ///
+    /// `sequence` ends up in the file name, so pass the receiver's frame counter
+    /// to keep gaps from skipped frames visible.
+    pub fn offer(&mut self, sequence: u64, frame: &Mat) -> Result<Option<PathBuf>> {
+        self.seen += 1;
+        let selected = match self.schedule {
+            DumpSchedule::EveryNth(n) => (self.seen - 1) % n == 0,
+            DumpSchedule::Interval(interval) => self
+                .last_written
+                .is_none_or(|last| last.elapsed() >= interval),
+        };
+        if !selected {
+            return Ok(None);
+        }
+
+        let path = self
+            .dir
+            .join(format!("frame_{sequence:06}.{}", self.extension));
+        let written = imgcodecs::imwrite(&path.to_string_lossy(), frame, &Vector::new())?;
+        if !written {
+            bail!("OpenCV could not write {}", path.display());
+        }
+        self.last_written = Some(Instant::now());
+        Ok(Some(path))
+    }
+}
@@ -0,0 +1,1063 @@
+//! Encoder stages for the sender [`crate::pipeline`]: still JPEG images,
+//! optionally held to a [`FrameBudget`], JPEG packets for muxing, and
+//! H.264, HEVC, VP9 or AV1 fed with OpenCV frames, optionally switching
+//! along a [`Ladder`].
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fmt,
+    str::FromStr,
+    sync::mpsc,
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use ac_ffmpeg::{
+    codec::{
+        video::{
+            frame::{get_pixel_format, PictureType, PixelFormat},
+            scaler::VideoFrameScaler,
+            VideoEncoder, VideoFrame, VideoFrameMut,
+        },
+        CodecParameters, Encoder as _,
+    },
+    format::{
+        demuxer::{Demuxer, InputFormat},
+        io::IO,
+    },
+    packet::Packet,
+    time::{TimeBase, Timestamp},
+};
+use anyhow::{bail, Context, Result};
+use bytes::Bytes;
+use opencv::{
+    core::{Mat, Size, Vector, CV_8UC3},
+    imgcodecs, imgproc,
+    prelude::*,
+};
+
+use crate::{
+    keyframe::KeyframeTrigger,
+    mux::Container,
+    pipeline::{EncodedPacket, Encoder, Frame, FrameMeta, Payload},
+    rate::{self, Ladder, LadderController, QualityKnob, Rung, RungKnob},
+    ts_decode::ChannelReader,
+};
+
+/// OpenCV's JPEG quality when none is given.
+const DEFAULT_JPEG_QUALITY: i32 = 95;
+
+/// Lowest quality the frame budget search goes to before downscaling.
+pub const MIN_BUDGET_QUALITY: i32 = 10;
+
+// Downscaling rounds per frame, the last result goes out even if it is
+// still over the budget
+const DOWNSCALE_ATTEMPTS: usize = 4;
+const MIN_DOWNSCALED_SIDE: i32 = 16;
+
+/// VBV buffer of bitrate capped encoders unless one is set.
+const DEFAULT_VBV_BUFFER: Duration = Duration::from_secs(1);
+
+/// Size limit for every JPEG, see [`JpegEncoder::max_frame_bytes`]. Written
+/// as a byte count, or as a bitrate with a `kbps` or `mbps` suffix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameBudget {
+    Bytes(usize),
+    /// Kilobits per second, split evenly over the frames.
+    Kbps(u64),
+}
+
+impl FrameBudget {
+    /// Bytes per frame at `fps`.
+    pub fn frame_bytes(self, fps: f64) -> usize {
+        match self {
+            FrameBudget::Bytes(bytes) => bytes,
+            FrameBudget::Kbps(kbps) => (kbps as f64 * 1000.0 / 8.0 / fps.max(1.0)) as usize,
+        }
+    }
+}
+
+impl fmt::Display for FrameBudget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FrameBudget::Bytes(bytes) => write!(f, "{bytes}"),
+            FrameBudget::Kbps(kbps) => write!(f, "{kbps}kbps"),
+        }
+    }
+}
+
+impl FromStr for FrameBudget {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let number = |n: &str| -> Result<u64> {
+            n.parse()
+                .with_context(|| format!("{s:?} is not a byte count or a bitrate like 2mbps"))
+        };
+        let budget = if let Some(kbps) = s.strip_suffix("kbps") {
+            FrameBudget::Kbps(number(kbps)?)
+        } else if let Some(mbps) = s.strip_suffix("mbps") {
+            FrameBudget::Kbps(number(mbps)? * 1000)
+        } else {
+            FrameBudget::Bytes(number(s)? as usize)
+        };
+        if matches!(budget, FrameBudget::Bytes(0) | FrameBudget::Kbps(0)) {
+            bail!("the frame budget must be above zero");
+        }
+        Ok(budget)
+    }
+}
+
+/// Reads `FRAME_BUDGET`, a cap on every JPEG in bytes (`40000`) or as a
+/// bitrate (`2mbps`). `None` keeps the JPEG size unbounded.
+pub fn budget_from_env() -> Result<Option<FrameBudget>> {
+    match std::env::var("FRAME_BUDGET") {
+        Ok(budget) => Ok(Some(budget.parse().context("FRAME_BUDGET")?)),
+        Err(_) => Ok(None),
+    }
+}
+
+/// Encodes frames as standalone JPEG images.
+pub struct JpegEncoder {
+    quality: Option<i32>,
+    // Replaces `quality` once someone adjusts it while encoding
+    knob: Option<QualityKnob>,
+    budget: Option<usize>,
+    // Where the budget search ended for the previous frame
+    last_fit: Option<i32>,
+    downscaled: bool,
+}
+
+impl JpegEncoder {
+    /// `quality` is 0..=100, `None` keeps the OpenCV default (95).
+    pub fn new(quality: Option<i32>) -> Self {
+        Self {
+            quality: quality.map(|quality| quality.clamp(0, 100)),
+            knob: None,
+            budget: None,
+            last_fit: None,
+            downscaled: false,
+        }
+    }
+
+    /// Keeps every JPEG at or below `bytes`: the quality becomes an upper
+    /// bound and each frame gets the highest quality that fits, down to
+    /// [`MIN_BUDGET_QUALITY`]. Frames that are still too large at that
+    /// quality are downscaled. Costs a few extra encodes per frame.
+    pub fn max_frame_bytes(mut self, bytes: Option<usize>) -> Self {
+        self.budget = bytes;
+        self
+    }
+
+    /// Handle to change the quality of the following frames, see
+    /// [`crate::rate`].
+    pub fn knob(&mut self) -> QualityKnob {
+        let quality = self.quality.unwrap_or(DEFAULT_JPEG_QUALITY);
+        self.knob
+            .get_or_insert_with(|| QualityKnob::new(quality))
+            .clone()
+    }
+
+    pub fn encode(&mut self, frame: &Mat) -> Result<Bytes> {
+        let quality = match &self.knob {
+            Some(knob) => Some(knob.get().clamp(0, 100)),
+            None => self.quality,
+        };
+        match self.budget {
+            Some(budget) => {
+                self.encode_within(frame, quality.unwrap_or(DEFAULT_JPEG_QUALITY), budget)
+            }
+            None => encode_jpeg(frame, quality),
+        }
+    }
+
+    fn encode_within(&mut self, frame: &Mat, max_quality: i32, budget: usize) -> Result<Bytes> {
+        // JPEG size grows with the quality, so bisect for the highest one
+        // that fits. Consecutive frames tend to land close to each other,
+        // so the previous result is tried first.
+        let mut low = MIN_BUDGET_QUALITY.min(max_quality);
+        let mut high = max_quality;
+        let mut probe = self.last_fit.unwrap_or(high).clamp(low, high);
+        let mut best = None;
+        let mut smallest = None;
+        while low <= high {
+            let jpeg = encode_jpeg(frame, Some(probe))?;
+            if jpeg.len() <= budget {
+                best = Some((probe, jpeg));
+                low = probe + 1;
+            } else {
+                smallest = Some(jpeg);
+                high = probe - 1;
+            }
+            probe = low + (high - low) / 2;
+        }
+
+        if let Some((quality, jpeg)) = best {
+            if self.downscaled {
+                println!("JPEG fits the {budget} byte budget at full size again");
+                self.downscaled = false;
+            }
+            self.last_fit = Some(quality);
+            return Ok(jpeg);
+        }
+
+        // Nothing fits, `smallest` is the minimum quality at full size
+        let quality = MIN_BUDGET_QUALITY.min(max_quality);
+        self.last_fit = Some(quality);
+        let mut jpeg = smallest.context("budget search tried no quality")?;
+        let (width, height) = (frame.cols(), frame.rows());
+        let mut scale = 1.0;
+        let mut size = Size::new(width, height);
+        for _ in 0..DOWNSCALE_ATTEMPTS {
+            // JPEG size roughly follows the pixel count
+            scale *= (budget as f64 / jpeg.len() as f64).sqrt() * 0.9;
+            size = Size::new(
+                ((width as f64 * scale) as i32).max(MIN_DOWNSCALED_SIDE),
+                ((height as f64 * scale) as i32).max(MIN_DOWNSCALED_SIDE),
+            );
+            let mut small = Mat::default();
+            imgproc::resize(frame, &mut small, size, 0.0, 0.0, imgproc::INTER_AREA)?;
+            jpeg = encode_jpeg(&small, Some(quality))?;
+            if jpeg.len() <= budget || size.width.min(size.height) == MIN_DOWNSCALED_SIDE {
+                break;
+            }
+        }
+        if !self.downscaled {
+            println!(
+                "JPEG over the {budget} byte budget at quality {quality}, downscaling to {}x{}",
+                size.width, size.height
+            );
+            self.downscaled = true;
+        }
+        Ok(jpeg)
+    }
+}
+
+fn encode_jpeg(frame: &Mat, quality: Option<i32>) -> Result<Bytes> {
+    let mut params = Vector::new();
+    if let Some(quality) = quality {
+        params.push(imgcodecs::IMWRITE_JPEG_QUALITY);
+        params.push(quality);
+    }
+    let mut buf = Vector::<u8>::new();
+    imgcodecs::imencode(".jpg", frame, &mut buf, &params)?;
+    Ok(Bytes::from(buf.to_vec()))
+}
+
+impl Encoder for JpegEncoder {
+    fn encode(&mut self, frame: Frame) -> Result<Vec<EncodedPacket>> {
+        Ok(vec![EncodedPacket {
+            payload: Payload::Image(JpegEncoder::encode(self, &frame.image)?),
+            meta: frame.meta,
+            dts: None,
+            keyframe: true,
+            codec: None,
+        }])
+    }
+
+    fn quality_control(&mut self) -> Option<QualityKnob> {
+        Some(self.knob())
+    }
+}
+
+/// Video codecs with a software encoder in ffmpeg.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum VideoCodec {
+    #[default]
+    H264,
+    Hevc,
+    Vp9,
+    Av1,
+}
+
+impl VideoCodec {
+    pub const ALL: [VideoCodec; 4] = [
+        VideoCodec::H264,
+        VideoCodec::Hevc,
+        VideoCodec::Vp9,
+        VideoCodec::Av1,
+    ];
+
+    /// ffmpeg encoders for the codec, the first one the linked ffmpeg has
+    /// is used.
+    pub fn encoders(self) -> &'static [&'static str] {
+        match self {
+            VideoCodec::H264 => &["libx264"],
+            VideoCodec::Hevc => &["libx265"],
+            VideoCodec::Vp9 => &["libvpx-vp9"],
+            VideoCodec::Av1 => &["libsvtav1", "libaom-av1"],
+        }
+    }
+
+    /// MPEG-TS has no mapping for VP9, and AV1 in MPEG-TS is too new for
+    /// most demuxers, so those two go into Matroska.
+    pub fn container(self) -> Container {
+        match self {
+            VideoCodec::H264 | VideoCodec::Hevc => Container::MpegTs,
+            VideoCodec::Vp9 | VideoCodec::Av1 => Container::Matroska,
+        }
+    }
+}
+
+impl fmt::Display for VideoCodec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            VideoCodec::H264 => "h264",
+            VideoCodec::Hevc => "hevc",
+            VideoCodec::Vp9 => "vp9",
+            VideoCodec::Av1 => "av1",
+        })
+    }
+}
+
+impl FromStr for VideoCodec {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "h265" => Ok(VideoCodec::Hevc),
+            _ => match VideoCodec::ALL.into_iter().find(|c| c.to_string() == s) {
+                Some(codec) => Ok(codec),
+                None => bail!("unknown codec {s:?}, expected h264, hevc, vp9 or av1"),
+            },
+        }
+    }
+}
+
+/// An ffmpeg encoder option, written `KEY=VALUE`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EncoderOption {
+    pub key: String,
+    pub value: String,
+}
+
+impl EncoderOption {
+    pub fn new(key: &str, value: impl ToString) -> Self {
+        Self {
+            key: key.into(),
+            value: value.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for EncoderOption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.key, self.value)
+    }
+}
+
+impl FromStr for EncoderOption {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.split_once('=') {
+            Some((key, value)) if !key.is_empty() => Ok(Self::new(key, value)),
+            _ => bail!("encoder option {s:?} is not KEY=VALUE"),
+        }
+    }
+}
+
+/// How an encoder spends its bits, see [`VideoSettings::rate_control`].
+/// Written `crf:QUALITY` or `cbr:KBPS`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateControl {
+    /// Constant quality on the encoder's CRF scale, 0 to 51 for H.264 and
+    /// HEVC, 0 to 63 for VP9 and AV1, lower is better. Frame sizes follow
+    /// the picture, so motion and scene cuts send bursts that queue in the
+    /// SRT send buffer unless a ladder caps them.
+    Crf(u32),
+    /// Constant bitrate in kbit/s. No frame exceeds what the VBV buffer
+    /// holds, so the link never queues more than a buffer's worth of
+    /// video, at the cost of blockier motion.
+    Cbr(u64),
+}
+
+impl fmt::Display for RateControl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RateControl::Crf(crf) => write!(f, "crf:{crf}"),
+            RateControl::Cbr(kbps) => write!(f, "cbr:{kbps}"),
+        }
+    }
+}
+
+impl FromStr for RateControl {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let value = |v: &str| v.parse::<u64>().ok();
+        match s.split_once(':') {
+            Some(("crf", crf)) => match value(crf) {
+                Some(crf @ 0..=63) => Ok(RateControl::Crf(crf as u32)),
+                _ => bail!("CRF {crf:?} is not between 0 and 63"),
+            },
+            Some(("cbr", kbps)) => match value(kbps) {
+                Some(kbps) if kbps > 0 => Ok(RateControl::Cbr(kbps)),
+                _ => bail!("CBR bitrate {kbps:?} is not a number of kbit/s above zero"),
+            },
+            _ => bail!("rate control {s:?} is not crf:QUALITY or cbr:KBPS"),
+        }
+    }
+}
+
+/// How a [`CodecEncoder`] is set up, apart from the codec.
+///
+/// The latency figures below are at 30 fps. The typed settings are
+/// translated for each encoder, H.264 and HEVC support all of them.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct VideoSettings {
+    pub ladder: Option<Ladder>,
+    /// Encoder speed preset, e.g. `ultrafast` to `medium` for x264 and x265,
+    /// `0` to `13` for SVT-AV1, and the `cpu-used` level for VP9 and libaom.
+    /// Faster presets cost bitrate, slower ones add encode time to every
+    /// frame: x264 `medium` takes several times as long as `veryfast`.
+    pub preset: Option<String>,
+    /// Turns off lookahead and frame threading, x264 otherwise holds back
+    /// around 40 frames, over a second, before the first packet leaves.
+    pub zero_latency: bool,
+    /// Largest run of B-frames. Every B-frame delays its reference frame by
+    /// one frame interval, so `Some(0)` is needed for the lowest latency.
+    pub b_frames: Option<u32>,
+    /// Frames from one keyframe to the next. Shorter GOPs let a receiver
+    /// join or recover from loss sooner, but every keyframe is a burst
+    /// several times the size of other frames.
+    pub gop: Option<u32>,
+    /// Refreshes a column of blocks in every frame instead of sending
+    /// keyframes, spreading the keyframe burst over the GOP so frame sizes,
+    /// and with them queueing delay, stay even. H.264 and HEVC only; a
+    /// joining receiver needs a full refresh cycle for a clean picture.
+    pub intra_refresh: bool,
+    pub rate_control: Option<RateControl>,
+    /// VBV buffer for [`RateControl::Cbr`] and ladder rungs, one second when
+    /// unset. A frame can take up to this long to drain at the bitrate, so
+    /// it bounds the queueing delay the encoder adds; 100 ms or less for
+    /// LAN latencies, at the cost of quality on motion.
+    pub vbv_buffer: Option<Duration>,
+    /// Handed to the ffmpeg encoder as they are, after and therefore over
+    /// the settings of this crate.
+    pub options: Vec<EncoderOption>,
+}
+
+impl VideoSettings {
+    /// Parses comma separated `WxH@KBPS` rungs, `preset=NAME`,
+    /// `zerolatency`, `bframes=N`, `gop=N`, `intra-refresh`, `crf=QUALITY`,
+    /// `cbr=KBPS`, `vbv=MS` and `KEY=VALUE` encoder options.
+    pub fn from_args(args: &str) -> Result<Self> {
+        let mut settings = VideoSettings::default();
+        let mut rungs = Vec::new();
+        for arg in args.split(',') {
+            let Some((key, value)) = arg.split_once('=') else {
+                match arg {
+                    "zerolatency" => settings.zero_latency = true,
+                    "intra-refresh" => settings.intra_refresh = true,
+                    rung => rungs.push(rung),
+                }
+                continue;
+            };
+            let number = || format!("{arg:?} needs a number");
+            match key {
+                "preset" => settings.preset = Some(value.into()),
+                "bframes" => settings.b_frames = Some(value.parse().with_context(number)?),
+                "gop" => settings.gop = Some(value.parse().with_context(number)?),
+                "crf" | "cbr" => settings.rate_control = Some(format!("{key}:{value}").parse()?),
+                "vbv" => {
+                    let ms = value.parse().with_context(number)?;
+                    settings.vbv_buffer = Some(Duration::from_millis(ms));
+                }
+                _ => settings.options.push(arg.parse()?),
+            }
+        }
+        if !rungs.is_empty() {
+            settings.ladder = Some(rungs.join(",").parse()?);
+        }
+        Ok(settings)
+    }
+
+    /// The arguments of [`Self::from_args`], `None` for the defaults.
+    pub fn args(&self) -> Option<String> {
+        let mut args: Vec<String> = self.ladder.iter().map(ToString::to_string).collect();
+        args.extend(self.preset.iter().map(|preset| format!("preset={preset}")));
+        if self.zero_latency {
+            args.push("zerolatency".into());
+        }
+        args.extend(self.b_frames.map(|n| format!("bframes={n}")));
+        args.extend(self.gop.map(|n| format!("gop={n}")));
+        if self.intra_refresh {
+            args.push("intra-refresh".into());
+        }
+        args.extend(
+            self.rate_control
+                .map(|rc| rc.to_string().replacen(':', "=", 1)),
+        );
+        args.extend(
+            self.vbv_buffer
+                .map(|vbv| format!("vbv={}", vbv.as_millis())),
+        );
+        args.extend(self.options.iter().map(ToString::to_string));
+        (!args.is_empty()).then(|| args.join(","))
+    }
+
+    /// Options carrying the typed settings, in the dialect of `encoder`.
+    /// Bitrates are left to [`EncoderSetup::open`].
+    fn encoder_options(&self, encoder: &str) -> Result<Vec<EncoderOption>> {
+        let x26x = matches!(encoder, "libx264" | "libx265");
+        let mut options = Vec::new();
+        if let Some(preset) = &self.preset {
+            let key = match encoder {
+                "libvpx-vp9" | "libaom-av1" => "cpu-used",
+                _ => "preset",
+            };
+            options.push(EncoderOption::new(key, preset));
+        }
+        if self.zero_latency {
+            options.extend(match encoder {
+                "libx264" | "libx265" => vec![EncoderOption::new("tune", "zerolatency")],
+                "libvpx-vp9" | "libaom-av1" => vec![EncoderOption::new("lag-in-frames", 0)],
+                "libsvtav1" => vec![EncoderOption::new("svtav1-params", "pred-struct=1")],
+                _ => Vec::new(),
+            });
+        }
+        if let Some(b_frames) = self.b_frames {
+            options.push(EncoderOption::new("bf", b_frames));
+        }
+        if let Some(gop) = self.gop {
+            options.push(EncoderOption::new("g", gop));
+        }
+        if self.intra_refresh {
+            options.push(match encoder {
+                "libx264" => EncoderOption::new("intra-refresh", 1),
+                "libx265" => EncoderOption::new("x265-params", "intra-refresh=1"),
+                _ => bail!("{encoder} has no periodic intra refresh, shorten the GOP instead"),
+            });
+        }
+        match self.rate_control {
+            Some(RateControl::Crf(crf)) => {
+                if x26x && crf > 51 {
+                    bail!("{encoder} takes a CRF between 0 and 51");
+                }
+                options.push(EncoderOption::new("crf", crf));
+            }
+            Some(RateControl::Cbr(_)) if encoder == "libx264" => {
+                // Padded to the rate, so the transport stream is true CBR
+                options.push(EncoderOption::new("nal-hrd", "cbr"));
+            }
+            _ => {}
+        }
+        Ok(options)
+    }
+}
+
+/// What ffmpeg's encoders need to keep up with live video, the defaults of
+/// most are tuned for files.
+fn live_options(encoder: &str) -> Vec<EncoderOption> {
+    match encoder {
+        // Forced keyframes are IDR frames, so a joining receiver can start
+        // decoding there
+        "libx264" | "libx265" => vec![EncoderOption::new("forced-idr", 1)],
+        "libvpx-vp9" => vec![
+            EncoderOption::new("deadline", "realtime"),
+            EncoderOption::new("cpu-used", 8),
+            EncoderOption::new("row-mt", 1),
+        ],
+        "libaom-av1" => vec![
+            EncoderOption::new("usage", "realtime"),
+            EncoderOption::new("cpu-used", 8),
+        ],
+        "libsvtav1" => vec![EncoderOption::new("preset", 10)],
+        _ => Vec::new(),
+    }
+}
+
+/// Reads `CODEC`, one of `h264`, `hevc`, `vp9` and `av1`, H.264 when unset.
+pub fn codec_from_env() -> Result<VideoCodec> {
+    match std::env::var("CODEC") {
+        Ok(codec) => codec.parse().context("CODEC"),
+        Err(_) => Ok(VideoCodec::H264),
+    }
+}
+
+/// Reads `ENCODER_TUNING`, e.g. `zerolatency,bframes=0,gop=60`, see
+/// [`VideoSettings::from_args`], then the ladder from `H264_LADDER` and
+/// options from `ENCODER_OPTS`.
+pub fn settings_from_env() -> Result<VideoSettings> {
+    let mut settings = match std::env::var("ENCODER_TUNING") {
+        Ok(args) => VideoSettings::from_args(&args).context("ENCODER_TUNING")?,
+        Err(_) => VideoSettings::default(),
+    };
+    if let Some(ladder) = rate::ladder_from_env()? {
+        settings.ladder = Some(ladder);
+    }
+    settings.options.extend(options_from_env()?);
+    Ok(settings)
+}
+
+/// Reads `ENCODER_OPTS`, space separated `KEY=VALUE` encoder options.
+pub fn options_from_env() -> Result<Vec<EncoderOption>> {
+    let options = std::env::var("ENCODER_OPTS").unwrap_or_default();
+    options
+        .split_whitespace()
+        .map(|option| option.parse().context("ENCODER_OPTS"))
+        .collect()
+}
+
+/// Encodes BGR frames with one of ffmpeg's software encoders, see
+/// [`VideoCodec`]. Frames are timestamped by their index at the source
+/// frame rate.
+///
+/// With a [`Ladder`] the output switches between its rungs as a
+/// [`LadderController`] asks. A switch drains the encoder and starts a new
+/// one at the rung's size and bitrate, so the new rung begins with a
+/// keyframe and new codec parameters, and the frames are scaled to fit.
+///
+/// The frame after a [`KeyframeTrigger`] request is encoded as a keyframe,
+/// an IDR frame for H.264 and HEVC.
+pub struct CodecEncoder {
+    setup: EncoderSetup,
+    encoder: VideoEncoder,
+    converter: MatConverter,
+    frame_index: i64,
+    // Metadata of frames still inside the encoder, by pts
+    pending: BTreeMap<i64, FrameMeta>,
+    codec_sent: bool,
+    ladder: Option<(Ladder, RungKnob, usize)>,
+    keyframe: KeyframeTrigger,
+}
+
+impl CodecEncoder {
+    /// Takes `width`x`height` frames, a ladder starts on its first, best
+    /// rung.
+    pub fn new(
+        codec: VideoCodec,
+        settings: &VideoSettings,
+        width: usize,
+        height: usize,
+        fps: f64,
+    ) -> Result<Self> {
+        let candidates = codec.encoders();
+        let Some(&name) = candidates
+            .iter()
+            .find(|name| VideoEncoder::builder(name).is_ok())
+        else {
+            bail!(
+                "the linked ffmpeg has no {codec} encoder, looked for {}",
+                candidates.join(", ")
+            );
+        };
+        println!("Encoding {codec} with {name}");
+
+        let cbr = match settings.rate_control {
+            Some(RateControl::Cbr(kbps)) => Some(kbps),
+            _ => None,
+        };
+        if settings.ladder.is_some() && codec.container() != Container::MpegTs {
+            bail!("a ladder needs {codec} in MPEG-TS, a rung switch restarts the muxer");
+        }
+        if cbr.is_some() && settings.ladder.is_some() {
+            bail!("constant bitrate and a ladder both set the bitrate, pick one");
+        }
+        if settings.vbv_buffer.is_some() && cbr.is_none() && settings.ladder.is_none() {
+            bail!("a VBV buffer needs a bitrate, from cbr or a ladder");
+        }
+        // 4:2:0 needs even dimensions, a ladder scales to its even rungs
+        if settings.ladder.is_none() && (width % 2 != 0 || height % 2 != 0) {
+            bail!("{codec} needs an even frame size, not {width}x{height}; crop or resize to one");
+        }
+
+        let mut options = live_options(name);
+        if codec.container() == Container::Matroska {
+            // Matroska wants the codec configuration up front
+            options.push(EncoderOption::new("flags", "+global_header"));
+        }
+        options.extend(settings.encoder_options(name)?);
+        let setup = EncoderSetup {
+            name,
+            options,
+            user_options: settings.options.clone(),
+            cbr,
+            vbv_buffer: settings.vbv_buffer.unwrap_or(DEFAULT_VBV_BUFFER),
+            width,
+            height,
+            time_base: TimeBase::new(1, fps.round().max(1.0) as i32),
+        };
+        let ladder = settings
+            .ladder
+            .clone()
+            .map(|ladder| (ladder, RungKnob::new(), 0));
+        let rung = ladder.as_ref().map(|(ladder, _, _)| ladder.rungs()[0]);
+        let (encoder, converter) = setup.open(rung)?;
+
+        Ok(Self {
+            setup,
+            encoder,
+            converter,
+            frame_index: 0,
+            pending: BTreeMap::new(),
+            codec_sent: false,
+            ladder,
+            keyframe: KeyframeTrigger::new(),
+        })
+    }
+
+    /// Parameters for the muxer stream carrying the output.
+    pub fn codec_parameters(&self) -> CodecParameters {
+        self.encoder.codec_parameters().into()
+    }
+
+    /// Moves to the rung the controller picked, returning what the old
+    /// encoder still held.
+    fn switch_rung(&mut self) -> Result<Vec<EncodedPacket>> {
+        let Some((ladder, knob, current)) = &self.ladder else {
+            return Ok(Vec::new());
+        };
+        let wanted = knob.get().min(ladder.rungs().len() - 1);
+        if wanted == *current {
+            return Ok(Vec::new());
+        }
+        let rung = ladder.rungs()[wanted];
+
+        self.encoder.flush()?;
+        let drained = self.take()?;
+        (self.encoder, self.converter) = self.setup.open(Some(rung))?;
+        self.codec_sent = false;
+        if let Some((_, _, current)) = &mut self.ladder {
+            *current = wanted;
+        }
+        println!(
+            "{} switched to {}x{} at {} kbit/s from frame #{}",
+            self.setup.name, rung.width, rung.height, rung.kbps, self.frame_index
+        );
+        Ok(drained)
+    }
+
+    fn take(&mut self) -> Result<Vec<EncodedPacket>> {
+        let mut packets = Vec::new();
+        while let Some(packet) = self.encoder.take()? {
+            let pts = packet.pts();
+            // Frames leave in decode order, so look up by pts and drop
+            // whatever the encoder skipped
+            let meta = match self.pending.remove(&pts.timestamp()) {
+                Some(meta) => meta,
+                None => match self.pending.pop_first() {
+                    Some((_, meta)) => meta,
+                    None => continue,
+                },
+            };
+            let codec = (!self.codec_sent).then(|| self.codec_parameters());
+            self.codec_sent = true;
+            packets.push(EncodedPacket {
+                meta,
+                dts: decode_time(&packet),
+                keyframe: packet.is_key(),
+                payload: Payload::Packet(packet),
+                codec,
+            });
+        }
+        Ok(packets)
+    }
+}
+
+impl Encoder for CodecEncoder {
+    fn encode(&mut self, frame: Frame) -> Result<Vec<EncodedPacket>> {
+        let mut packets = self.switch_rung()?;
+        let pts = Timestamp::new(self.frame_index, self.setup.time_base);
+        self.pending.insert(self.frame_index, frame.meta);
+        self.frame_index += 1;
+        let mut converted = self.converter.convert(&frame.image)?.with_pts(pts);
+        if self.keyframe.take() {
+            converted = converted.with_picture_type(PictureType::I);
+        }
+        self.encoder.push(converted)?;
+        packets.extend(self.take()?);
+        Ok(packets)
+    }
+
+    fn flush(&mut self) -> Result<Vec<EncodedPacket>> {
+        self.encoder.flush()?;
+        self.take()
+    }
+
+    fn ladder_control(&mut self) -> Option<LadderController> {
+        let (ladder, knob, _) = self.ladder.as_ref()?;
+        Some(LadderController::new(knob.clone(), ladder.clone()))
+    }
+
+    fn keyframe_control(&mut self) -> Option<KeyframeTrigger> {
+        Some(self.keyframe.clone())
+    }
+}
+
+/// The DTS of `packet`, or its PTS for muxers and encoders that leave the
+/// DTS unset.
+fn decode_time(packet: &Packet) -> Option<Timestamp> {
+    [packet.dts(), packet.pts()]
+        .into_iter()
+        .find(|time| !time.is_null())
+}
+
+/// What every encoder of a [`CodecEncoder`] is opened with, the first one
+/// and those of later ladder rungs.
+struct EncoderSetup {
+    name: &'static str,
+    options: Vec<EncoderOption>,
+    user_options: Vec<EncoderOption>,
+    // Constant bitrate in kbit/s, rungs bring their own
+    cbr: Option<u64>,
+    vbv_buffer: Duration,
+    width: usize,
+    height: usize,
+    time_base: TimeBase,
+}
+
+impl EncoderSetup {
+    /// An encoder for `width`x`height` input, scaled to the rung and held to
+    /// its bitrate or the constant one, if there is one.
+    fn open(&self, rung: Option<Rung>) -> Result<(VideoEncoder, MatConverter)> {
+        let pixel_format = get_pixel_format("yuv420p");
+        let (out_width, out_height) =
+            rung.map_or((self.width, self.height), |r| (r.width, r.height));
+        let mut builder = VideoEncoder::builder(self.name)?
+            .pixel_format(pixel_format)
+            .width(out_width)
+            .height(out_height)
+            .time_base(self.time_base);
+        for option in &self.options {
+            builder = builder.set_option(&option.key, &option.value);
+        }
+        if let Some(kbps) = rung.map(|rung| rung.kbps).or(self.cbr) {
+            // Capped at the rate over the VBV buffer, so the stream stays
+            // within what the link was judged to carry
+            let bit_rate = kbps * 1000;
+            let buffer = bit_rate * self.vbv_buffer.as_millis() as u64 / 1000;
+            builder = builder
+                .bit_rate(bit_rate)
+                .set_option("maxrate", bit_rate)
+                .set_option("bufsize", buffer.max(1));
+            if self.cbr.is_some() {
+                builder = builder.set_option("minrate", bit_rate);
+            }
+        }
+        for option in &self.user_options {
+            builder = builder.set_option(&option.key, &option.value);
+        }
+        let converter =
+            MatConverter::scaling(self.width, self.height, out_width, out_height, pixel_format)?;
+        let encoder = builder
+            .build()
+            .with_context(|| format!("cannot open {}", self.name))?;
+        Ok((encoder, converter))
+    }
+}
+
+/// JPEG packets for muxing into a transport stream: the JPEGs are fed to
+/// ffmpeg's MJPEG demuxer, which splits them into packets, and each packet
+/// is stamped with the capture time of its frame. The demuxer reads ahead
+/// to find frame boundaries, so it runs on its own thread.
+pub struct MjpegEncoder {
+    jpeg: JpegEncoder,
+    // Dropped at flush, which ends the demuxer input
+    input: Option<mpsc::Sender<Bytes>>,
+    packets: mpsc::Receiver<Result<(Packet, Option<CodecParameters>)>>,
+    pending: VecDeque<FrameMeta>,
+    // Capture time of the first frame, timestamps count from there
+    first_captured: Option<SystemTime>,
+    last_pts: Option<i64>,
+}
+
+impl MjpegEncoder {
+    /// `fps` is the nominal rate declared to the demuxer, the timestamps
+    /// follow the actual capture times.
+    pub fn new(quality: Option<i32>, fps: f64) -> Self {
+        let (input, input_recv) = mpsc::channel();
+        let (packet_send, packets) = mpsc::channel();
+        thread::spawn(move || {
+            if let Err(e) = demux_jpegs(ChannelReader::new(input_recv), fps, &packet_send) {
+                let _ = packet_send.send(Err(e));
+            }
+        });
+
+        Self {
+            jpeg: JpegEncoder::new(quality),
+            input: Some(input),
+            packets,
+            pending: VecDeque::new(),
+            first_captured: None,
+            last_pts: None,
+        }
+    }
+
+    /// See [`JpegEncoder::max_frame_bytes`].
+    pub fn max_frame_bytes(mut self, bytes: Option<usize>) -> Self {
+        self.jpeg = self.jpeg.max_frame_bytes(bytes);
+        self
+    }
+
+    /// Collects demuxed packets, waiting for the demuxer to finish if
+    /// `wait` is set.
+    fn collect(&mut self, wait: bool) -> Result<Vec<EncodedPacket>> {
+        let mut encoded = Vec::new();
+        loop {
+            let received = if wait {
+                self.packets.recv().ok()
+            } else {
+                self.packets.try_recv().ok()
+            };
+            let Some(received) = received else {
+                return Ok(encoded);
+            };
+            let (packet, codec) = received?;
+            // One packet per JPEG, in order
+            let meta = self
+                .pending
+                .pop_front()
+                .context("demuxer returned more packets than JPEGs")?;
+            let packet = self.stamp(packet, meta.captured);
+            encoded.push(EncodedPacket {
+                meta,
+                dts: decode_time(&packet),
+                keyframe: true,
+                payload: Payload::Packet(packet),
+                codec,
+            });
+        }
+    }
+
+    /// Sets the PTS and DTS of `packet` to when its frame was captured.
+    /// Every JPEG is a keyframe, so both are the same.
+    fn stamp(&mut self, packet: Packet, captured: SystemTime) -> Packet {
+        let first = *self.first_captured.get_or_insert(captured);
+        let elapsed = captured.duration_since(first).unwrap_or_default();
+        // Strictly increasing, even if the wall clock steps back
+        let pts = match self.last_pts {
+            Some(last) => (elapsed.as_micros() as i64).max(last + 1),
+            None => elapsed.as_micros() as i64,
+        };
+        self.last_pts = Some(pts);
+        let pts = Timestamp::new(pts, TimeBase::MICROSECONDS);
+        packet
+            .with_time_base(TimeBase::MICROSECONDS)
+            .with_pts(pts)
+            .with_dts(pts)
+    }
+}
+
+impl Encoder for MjpegEncoder {
+    fn encode(&mut self, frame: Frame) -> Result<Vec<EncodedPacket>> {
+        let jpeg = self.jpeg.encode(&frame.image)?;
+        let input = self.input.as_ref().context("encoder already flushed")?;
+        if input.send(jpeg).is_err() {
+            // The demuxer stopped, its error is waiting in `packets`
+            return self.collect(true);
+        }
+        self.pending.push_back(frame.meta);
+        self.collect(false)
+    }
+
+    fn flush(&mut self) -> Result<Vec<EncodedPacket>> {
+        self.input = None;
+        self.collect(true)
+    }
+
+    fn quality_control(&mut self) -> Option<QualityKnob> {
+        Some(self.jpeg.knob())
+    }
+}
+
+fn demux_jpegs(
+    reader: ChannelReader,
+    fps: f64,
+    packets: &mpsc::Sender<Result<(Packet, Option<CodecParameters>)>>,
+) -> Result<()> {
+    // Named rather than probed: probing waits for several JPEGs and may
+    // settle on image2pipe's default of 25 fps. The stream info still needs
+    // the frame size, the smallest probe without a frame rate estimate stops
+    // after the first JPEG instead of reading ahead.
+    let format = InputFormat::find_by_name("mjpeg").context("ffmpeg has no mjpeg demuxer")?;
+    let mut demuxer = Demuxer::builder()
+        .input_format(Some(format))
+        .set_option("framerate", fps.max(1.0))
+        .set_option("probesize", 32)
+        .set_option("fpsprobesize", 0)
+        .build(IO::from_read_stream(reader))?
+        .find_stream_info(None)
+        .map_err(|(_, err)| err)?;
+
+    let mut codec = demuxer
+        .streams()
+        .first()
+        .map(|stream| stream.codec_parameters());
+    while let Some(packet) = demuxer.take()? {
+        if packets.send(Ok((packet, codec.take()))).is_err() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Converts packed BGR `Mat`s into ffmpeg frames of the encoder's pixel
+/// format and size, the reverse of [`crate::ts_decode::BgrConverter`].
+pub struct MatConverter {
+    width: usize,
+    height: usize,
+    scaler: VideoFrameScaler,
+}
+
+impl MatConverter {
+    pub fn new(width: usize, height: usize, target: PixelFormat) -> Result<Self> {
+        Self::scaling(width, height, width, height, target)
+    }
+
+    /// Also scales `width`x`height` frames to `target_width`x`target_height`.
+    pub fn scaling(
+        width: usize,
+        height: usize,
+        target_width: usize,
+        target_height: usize,
+        target: PixelFormat,
+    ) -> Result<Self> {
+        let scaler = VideoFrameScaler::builder()
+            .source_pixel_format(get_pixel_format("bgr24"))
+            .source_width(width)
+            .source_height(height)
+            .target_pixel_format(target)
+            .target_width(target_width)
+            .target_height(target_height)
+            .build()?;
+        Ok(Self {
+            width,
+            height,
+            scaler,
+        })
+    }
+
+    pub fn convert(&mut self, frame: &Mat) -> Result<VideoFrame> {
+        if frame.typ() != CV_8UC3
+            || frame.cols() as usize != self.width
+            || frame.rows() as usize != self.height
+        {
+            bail!(
+                "expected a {}x{} BGR frame, got {}x{} of type {}",
+                self.width,
+                self.height,
+                frame.cols(),
+                frame.rows(),
+                frame.typ()
+            );
+        }
+
+        let mut bgr = VideoFrameMut::black(get_pixel_format("bgr24"), self.width, self.height);
+        let row_len = self.width * 3;
+        let src = frame.data_bytes()?;
+        {
+            let mut planes = bgr.planes_mut();
+            let plane = &mut planes[0];
+            let stride = plane.line_size();
+            let dst = plane.data_mut();
+            for row in 0..self.height {
+                dst[row * stride..row * stride + row_len]
+                    .copy_from_slice(&src[row * row_len..(row + 1) * row_len]);
+            }
+        }
+
+        Ok(self.scaler.scale(&bgr.freeze())?)
+    }
+}
@@ -0,0 +1,358 @@
+//! Frame filters for the sender [`crate::pipeline`]. Besides the timecode
+//! they preprocess frames to cut bandwidth before any encoder sees them:
+//! [`Crop`] to a region of interest, [`Resize`] to an output resolution,
+//! [`Grayscale`] and [`Decimate`] to a lower frame rate.
+//!
+//! The sender binaries read them from the environment, see
+//! [`filters_from_env`].
+
+use std::{fmt, str::FromStr};
+
+use anyhow::{bail, Context, Result};
+use opencv::{
+    core::{self, Mat, Rect, Scalar, Size},
+    imgproc,
+    prelude::*,
+};
+
+use crate::{
+    pipeline::{parse_size, Filter, FilterSpec, Frame, StreamInfo},
+    timecode,
+};
+
+/// How [`Resize`] deals with a different aspect ratio.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Fit {
+    /// Scales to the size, distorting the picture.
+    #[default]
+    Stretch,
+    /// Scales to fit inside the size and pads with black bars.
+    Letterbox,
+    /// Scales to cover the size and cuts off the overhang, centered.
+    Crop,
+}
+
+impl fmt::Display for Fit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Fit::Stretch => "stretch",
+            Fit::Letterbox => "letterbox",
+            Fit::Crop => "crop",
+        })
+    }
+}
+
+impl FromStr for Fit {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "stretch" => Ok(Fit::Stretch),
+            "letterbox" => Ok(Fit::Letterbox),
+            "crop" => Ok(Fit::Crop),
+            _ => bail!("unknown fit {s:?}, expected stretch, letterbox or crop"),
+        }
+    }
+}
+
+/// Output size of a [`Resize`], written as `WxH[,FIT]`, e.g.
+/// `640x360,letterbox`. Stretches unless a [`Fit`] is given.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Scale {
+    pub width: usize,
+    pub height: usize,
+    pub fit: Fit,
+}
+
+impl fmt::Display for Scale {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{}", self.width, self.height)?;
+        match self.fit {
+            Fit::Stretch => Ok(()),
+            fit => write!(f, ",{fit}"),
+        }
+    }
+}
+
+impl FromStr for Scale {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (size, fit) = match s.split_once(',') {
+            Some((size, fit)) => (size, fit.parse()?),
+            None => (s, Fit::Stretch),
+        };
+        let (width, height) = parse_size(size)?;
+        Ok(Self { width, height, fit })
+    }
+}
+
+/// A region of a frame, written as `WxH+X+Y` from the top left corner.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Region {
+    fn rect(self) -> Rect {
+        Rect::new(
+            self.x as i32,
+            self.y as i32,
+            self.width as i32,
+            self.height as i32,
+        )
+    }
+}
+
+impl fmt::Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{}+{}+{}", self.width, self.height, self.x, self.y)
+    }
+}
+
+impl FromStr for Region {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parts = s.split('+');
+        let (Some(size), Some(x), Some(y), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            bail!("{s:?} is not a region like 640x360+0+60 (WxH+X+Y)");
+        };
+        let (width, height) = parse_size(size)?;
+        let offset = |n: &str| n.parse().with_context(|| format!("{n:?} is not an offset"));
+        Ok(Self {
+            x: offset(x)?,
+            y: offset(y)?,
+            width,
+            height,
+        })
+    }
+}
+
+/// Reads `FILTERS`, pipeline filter stages applied in order before the
+/// encoder and separated by `!`, e.g. `crop:640x360+0+60 ! grayscale ! fps:5`.
+/// No filters when unset.
+pub fn filters_from_env() -> Result<Vec<FilterSpec>> {
+    match std::env::var("FILTERS") {
+        Ok(filters) => filters
+            .split('!')
+            .map(|filter| filter.trim().parse().context("FILTERS"))
+            .collect(),
+        Err(_) => Ok(Vec::new()),
+    }
+}
+
+/// Scales every frame to a fixed size, by default ignoring the aspect
+/// ratio.
+pub struct Resize {
+    width: usize,
+    height: usize,
+    fit: Fit,
+}
+
+impl Resize {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            fit: Fit::Stretch,
+        }
+    }
+
+    /// Keeps the aspect ratio by padding or cropping, see [`Fit`].
+    pub fn fit(mut self, fit: Fit) -> Self {
+        self.fit = fit;
+        self
+    }
+
+    /// Size the picture is scaled to before padding or cropping.
+    fn scaled_size(&self, width: usize, height: usize) -> Size {
+        let (x_scale, y_scale) = (
+            self.width as f64 / width as f64,
+            self.height as f64 / height as f64,
+        );
+        let scale = match self.fit {
+            Fit::Stretch => return Size::new(self.width as i32, self.height as i32),
+            Fit::Letterbox => x_scale.min(y_scale),
+            Fit::Crop => x_scale.max(y_scale),
+        };
+        // Rounding must not leave the picture a pixel short of the output
+        let side = |input: usize, output: usize| match self.fit {
+            Fit::Crop => ((input as f64 * scale).round() as usize).max(output),
+            _ => ((input as f64 * scale).round() as usize).clamp(1, output),
+        };
+        Size::new(
+            side(width, self.width) as i32,
+            side(height, self.height) as i32,
+        )
+    }
+}
+
+impl Filter for Resize {
+    fn info(&self, input: StreamInfo) -> StreamInfo {
+        StreamInfo {
+            width: self.width,
+            height: self.height,
+            ..input
+        }
+    }
+
+    fn apply(&mut self, frame: Frame) -> Result<Option<Frame>> {
+        if frame.image.cols() as usize == self.width && frame.image.rows() as usize == self.height {
+            return Ok(Some(frame));
+        }
+        let size = self.scaled_size(frame.image.cols() as usize, frame.image.rows() as usize);
+        let mut resized = Mat::default();
+        imgproc::resize(
+            &frame.image,
+            &mut resized,
+            size,
+            0.0,
+            0.0,
+            imgproc::INTER_AREA,
+        )?;
+        let (width, height) = (self.width as i32, self.height as i32);
+        let image = match self.fit {
+            Fit::Stretch => resized,
+            Fit::Letterbox => {
+                let (left, top) = ((width - size.width) / 2, (height - size.height) / 2);
+                let mut boxed = Mat::default();
+                core::copy_make_border(
+                    &resized,
+                    &mut boxed,
+                    top,
+                    height - size.height - top,
+                    left,
+                    width - size.width - left,
+                    core::BORDER_CONSTANT,
+                    Scalar::all(0.),
+                )?;
+                boxed
+            }
+            Fit::Crop => {
+                let (x, y) = ((size.width - width) / 2, (size.height - height) / 2);
+                resized.roi(Rect::new(x, y, width, height))?.try_clone()?
+            }
+        };
+        Ok(Some(Frame {
+            image,
+            meta: frame.meta,
+        }))
+    }
+}
+
+/// Cuts a fixed region out of every frame, e.g. the part of the scene that
+/// matters.
+pub struct Crop {
+    region: Region,
+}
+
+impl Crop {
+    /// Fails if `region` does not fit into `input` frames, when their size
+    /// is known.
+    pub fn new(region: Region, input: StreamInfo) -> Result<Self> {
+        let known = input.width > 0 && input.height > 0;
+        if known
+            && (region.x + region.width > input.width || region.y + region.height > input.height)
+        {
+            bail!(
+                "crop region {region} is outside the {}x{} frame",
+                input.width,
+                input.height
+            );
+        }
+        Ok(Self { region })
+    }
+}
+
+impl Filter for Crop {
+    fn info(&self, input: StreamInfo) -> StreamInfo {
+        StreamInfo {
+            width: self.region.width,
+            height: self.region.height,
+            ..input
+        }
+    }
+
+    fn apply(&mut self, frame: Frame) -> Result<Option<Frame>> {
+        let image = frame.image.roi(self.region.rect())?.try_clone()?;
+        Ok(Some(Frame {
+            image,
+            meta: frame.meta,
+        }))
+    }
+}
+
+/// Drops the color. Frames stay BGR for the encoders, but with neutral
+/// chroma, which JPEG and the video codecs encode in next to no bits.
+pub struct Grayscale;
+
+impl Filter for Grayscale {
+    fn apply(&mut self, frame: Frame) -> Result<Option<Frame>> {
+        let mut gray = Mat::default();
+        imgproc::cvt_color_def(&frame.image, &mut gray, imgproc::COLOR_BGR2GRAY)?;
+        let mut image = Mat::default();
+        imgproc::cvt_color_def(&gray, &mut image, imgproc::COLOR_GRAY2BGR)?;
+        Ok(Some(Frame {
+            image,
+            meta: frame.meta,
+        }))
+    }
+}
+
+/// Lowers the frame rate by dropping frames evenly, e.g. every other one
+/// to go from 30 to 15 fps.
+pub struct Decimate {
+    // Share of the input frames that is kept
+    keep: f64,
+    credit: f64,
+}
+
+impl Decimate {
+    /// Keeps at most `max_fps` of `input_fps`, all frames if the input is
+    /// slower or its rate unknown.
+    pub fn new(input_fps: f64, max_fps: f64) -> Self {
+        let keep = if input_fps > max_fps {
+            max_fps / input_fps
+        } else {
+            1.0
+        };
+        // The first frame always goes through
+        Self { keep, credit: 1.0 }
+    }
+}
+
+impl Filter for Decimate {
+    fn info(&self, input: StreamInfo) -> StreamInfo {
+        StreamInfo {
+            fps: input.fps * self.keep,
+            ..input
+        }
+    }
+
+    fn apply(&mut self, frame: Frame) -> Result<Option<Frame>> {
+        // With a margin for the rounding of `keep`
+        if self.credit < 1.0 - 1e-9 {
+            self.credit += self.keep;
+            return Ok(None);
+        }
+        self.credit += self.keep - 1.0;
+        Ok(Some(frame))
+    }
+}
+
+/// Burns the capture time into every frame, see [`crate::timecode`]. Put it
+/// last so scaling does not blur the pattern.
+pub struct TimecodeFilter;
+
+impl Filter for TimecodeFilter {
+    fn apply(&mut self, mut frame: Frame) -> Result<Option<Frame>> {
+        timecode::stamp(&mut frame.image, frame.meta.captured)?;
+        Ok(Some(frame))
+    }
+}
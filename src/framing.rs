@@ -0,0 +1,71 @@
+//! Length-prefixed framing used by v4: every JPEG is preceded by its size as
+//! a big-endian u32 and the result is split over several SRT messages.
+
+use anyhow::{bail, Result};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+
+/// Upper bound for a single frame, anything larger means we lost sync.
+pub const MAX_FRAME_LEN: usize = 64 * 1024 * 1024;
+
+/// SRT message size the sender splits framed data into.
+pub const CHUNK_LEN: usize = 1200;
+
+/// Prefixes `frame` with its length and splits the result into messages of
+/// at most `chunk_len` bytes.
+pub fn frame_chunks(frame: &[u8], chunk_len: usize) -> Vec<Bytes> {
+    let mut framed = BytesMut::with_capacity(4 + frame.len());
+    framed.put_u32(frame.len() as u32);
+    framed.put_slice(frame);
+
+    let mut framed = framed.freeze();
+    let mut chunks = Vec::with_capacity(framed.len().div_ceil(chunk_len));
+    while !framed.is_empty() {
+        chunks.push(framed.split_to(chunk_len.min(framed.len())));
+    }
+    chunks
+}
+
+#[derive(Default)]
+pub struct Deframer {
+    buffer: Vec<u8>,
+    expected_len: Option<usize>,
+}
+
+impl Deframer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends a received chunk.
+    pub fn push(&mut self, chunk: &[u8]) {
+        self.buffer.extend_from_slice(chunk);
+    }
+
+    /// Bytes received but not yet returned as a frame.
+    pub fn buffered(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Pops the next complete frame, `Ok(None)` while waiting for more data.
+    pub fn next_frame(&mut self) -> Result<Option<Vec<u8>>> {
+        if self.expected_len.is_none() {
+            if self.buffer.len() < 4 {
+                return Ok(None);
+            }
+            let len = (&self.buffer[..4]).get_u32() as usize;
+            if len > MAX_FRAME_LEN {
+                bail!("frame length {len} exceeds {MAX_FRAME_LEN} bytes, stream out of sync");
+            }
+            self.expected_len = Some(len);
+            self.buffer.drain(0..4);
+        }
+
+        match self.expected_len {
+            Some(len) if self.buffer.len() >= len => {
+                self.expected_len = None;
+                Ok(Some(self.buffer.drain(0..len).collect()))
+            }
+            _ => Ok(None),
+        }
+    }
+}
@@ -0,0 +1,97 @@
+//! Forced keyframes for encoders with long GOPs. A receiver that joins a
+//! stream mid-GOP, or loses a reference frame, shows garbage or nothing
+//! until the next keyframe, so the sender forces one:
+//!
+//! - when a caller joins a [`crate::transport::SrtFanOut`] stream,
+//! - when a receiver sends [`KEYFRAME_REQUEST`] back over its socket.
+//!
+//! Keyframes are several times the size of other frames, so requests pass
+//! through a [`KeyframeLimiter`] before they reach the encoder's
+//! [`KeyframeTrigger`].
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+/// The message a receiver sends back to ask for a keyframe.
+pub const KEYFRAME_REQUEST: &[u8] = b"srt-playground keyframe request";
+
+/// Shortest time between two forced keyframes.
+pub const MIN_KEYFRAME_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Shared between an encoder and whoever asks it for keyframes.
+#[derive(Debug, Clone, Default)]
+pub struct KeyframeTrigger(Arc<AtomicBool>);
+
+impl KeyframeTrigger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Makes the next encoded frame a keyframe.
+    pub fn request(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether a keyframe was requested since the last call.
+    pub fn take(&self) -> bool {
+        self.0.swap(false, Ordering::Relaxed)
+    }
+}
+
+/// Lets at most one keyframe through per interval. Requests arriving in
+/// between are merged and granted once the interval has passed, so a late
+/// joiner still gets its keyframe, only a little later.
+#[derive(Debug)]
+pub struct KeyframeLimiter {
+    min_interval: Duration,
+    last_forced: Option<Instant>,
+    // Reason of the first waiting request, and how many more came since
+    pending: Option<(String, u32)>,
+}
+
+impl KeyframeLimiter {
+    pub fn new(min_interval: Duration) -> Self {
+        Self {
+            min_interval,
+            last_forced: None,
+            pending: None,
+        }
+    }
+
+    /// Records a request, `reason` ends up in the log line.
+    pub fn request(&mut self, reason: impl Into<String>) {
+        match &mut self.pending {
+            Some((_, merged)) => *merged += 1,
+            None => self.pending = Some((reason.into(), 0)),
+        }
+    }
+
+    /// Why a keyframe is due at `now`, `None` if nothing is waiting or the
+    /// last one is too recent.
+    pub fn poll(&mut self, now: Instant) -> Option<String> {
+        if self.pending.is_none()
+            || self
+                .last_forced
+                .is_some_and(|last| now.saturating_duration_since(last) < self.min_interval)
+        {
+            return None;
+        }
+        self.last_forced = Some(now);
+        let (reason, merged) = self.pending.take()?;
+        Some(match merged {
+            0 => reason,
+            n => format!("{reason}, {n} later requests merged"),
+        })
+    }
+}
+
+impl Default for KeyframeLimiter {
+    fn default() -> Self {
+        Self::new(MIN_KEYFRAME_INTERVAL)
+    }
+}
@@ -0,0 +1,46 @@
+//! Video over SRT: the streaming stack behind the `srt-playground` CLI and
+//! the `v1`..`v5` sender and receiver binaries, usable from other crates.
+//!
+//! - [`sender::VideoSender`] captures from a [`source::FrameSource`],
+//!   encodes in one of the [`sender::Mode`]s and sends over an SRT socket.
+//! - [`receiver::VideoReceiver`] decodes any of those streams back into
+//!   frames, optionally paced by a [`playout::PlayoutBuffer`].
+//! - [`output::FrameOutput`] shows, dumps and republishes received frames.
+//!
+//! The sender is a fixed [`pipeline::Pipeline`] per mode. Pipelines can also
+//! be put together from their stages, in code or from a text description:
+//! sources in [`source`], filters in [`filter`], encoders in [`encode`],
+//! packetizers in [`packetize`] and transports in [`transport`]. MPEG-TS
+//! streams can carry an audio track from [`audio`].
+//!
+//! The lower level pieces are public as well: length-prefixed framing in
+//! [`framing`], MPEG-TS muxing and decoding in [`mux`] and [`ts_decode`],
+//! timestamp pacing in [`pacing`], link-driven JPEG quality in [`rate`] and
+//! forced keyframes in [`keyframe`].
+//!
+//! Sockets are plain `srt_tokio::SrtSocket`s, so the caller decides who
+//! listens and who calls; [`transport::Connector`] sets one up in any role,
+//! including rendezvous.
+
+pub mod audio;
+pub mod detect;
+pub mod dump;
+pub mod encode;
+pub mod filter;
+pub mod framing;
+pub mod keyframe;
+pub mod mjpeg;
+pub mod mux;
+pub mod output;
+pub mod pacing;
+pub mod packetize;
+pub mod pipeline;
+pub mod playout;
+pub mod rate;
+pub mod receiver;
+pub mod sender;
+pub mod source;
+pub mod timecode;
+pub mod transport;
+pub mod ts_analyzer;
+pub mod ts_decode;
@@ -0,0 +1,179 @@
+//! Embedded HTTP server that republishes JPEG frames as an MJPEG stream.
+//!
+//! `GET /` serves a small status page, `GET /stream` serves a
+//! `multipart/x-mixed-replace` stream. Every viewer gets its own broadcast
+//! subscription, so a slow browser tab skips frames without stalling the
+//! receiver or the other tabs.
+
+use std::{
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use bytes::Bytes;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::broadcast::{self, error::RecvError},
+};
+
+const BOUNDARY: &str = "srtframe";
+
+// Kept small on purpose: a lagging viewer jumps to the newest frames instead
+// of replaying a long backlog.
+const VIEWER_BACKLOG: usize = 2;
+
+// Pause after a failed accept, errors like running out of file descriptors
+// persist and would otherwise spin the loop.
+const ACCEPT_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+#[derive(Default)]
+struct Stats {
+    published: AtomicU64,
+    viewers: AtomicUsize,
+    dropped: AtomicU64,
+}
+
+#[derive(Clone)]
+pub struct MjpegServer {
+    frames: broadcast::Sender<Bytes>,
+    stats: Arc<Stats>,
+    started: Instant,
+}
+
+impl MjpegServer {
+    /// Binds `addr` (e.g. `0.0.0.0:8080`) and starts accepting viewers in the
+    /// background.
+    pub async fn bind(addr: &str) -> Result<Self> {
+        let listener = TcpListener::bind(addr).await?;
+        let (frames, _) = broadcast::channel(VIEWER_BACKLOG);
+        let server = Self {
+            frames,
+            stats: Arc::new(Stats::default()),
+            started: Instant::now(),
+        };
+
+        let acceptor = server.clone();
+        tokio::spawn(async move {
+            loop {
+                match listener.accept().await {
+                    Ok((socket, peer)) => {
+                        let server = acceptor.clone();
+                        tokio::spawn(async move {
+                            if let Err(e) = server.handle(socket).await {
+                                eprintln!("MJPEG viewer {peer} disconnected: {e}");
+                            }
+                        });
+                    }
+                    Err(e) => {
+                        eprintln!("MJPEG accept failed: {e}");
+                        tokio::time::sleep(ACCEPT_RETRY_DELAY).await;
+                    }
+                }
+            }
+        });
+
+        Ok(server)
+    }
+
+    /// Hands a complete JPEG image to every connected viewer.
+    pub fn publish(&self, jpeg: Bytes) {
+        self.stats.published.fetch_add(1, Ordering::Relaxed);
+        // No subscribers is not an error, the frame is simply not watched.
+        let _ = self.frames.send(jpeg);
+    }
+
+    async fn handle(&self, mut socket: TcpStream) -> Result<()> {
+        let mut request = Vec::with_capacity(1024);
+        let mut buf = [0u8; 1024];
+        while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+            let n = socket.read(&mut buf).await?;
+            if n == 0 || request.len() > 8 * 1024 {
+                return Ok(());
+            }
+            request.extend_from_slice(&buf[..n]);
+        }
+
+        let head = String::from_utf8_lossy(&request);
+        let path = head.split_whitespace().nth(1).unwrap_or("/");
+
+        match path {
+            "/" | "/index.html" => self.status_page(&mut socket).await,
+            "/stream" | "/stream.mjpg" => self.stream(&mut socket).await,
+            _ => {
+                socket
+                    .write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+                    .await?;
+                Ok(())
+            }
+        }
+    }
+
+    async fn status_page(&self, socket: &mut TcpStream) -> Result<()> {
+        let body = format!(
+            "<!DOCTYPE html>\n<html><head><title>SRT Receiver</title></head><body>\n\
+             <h1>SRT Receiver</h1>\n\
+             <ul>\n\
+             <li>uptime: {}s</li>\n\
+             <li>frames published: {}</li>\n\
+             <li>viewers: {}</li>\n\
+             <li>frames dropped for slow viewers: {}</li>\n\
+             </ul>\n\
+             <img src=\"/stream\">\n\
+             </body></html>\n",
+            self.started.elapsed().as_secs(),
+            self.stats.published.load(Ordering::Relaxed),
+            self.stats.viewers.load(Ordering::Relaxed),
+            self.stats.dropped.load(Ordering::Relaxed),
+        );
+        let header = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n",
+            body.len()
+        );
+        socket.write_all(header.as_bytes()).await?;
+        socket.write_all(body.as_bytes()).await?;
+        Ok(())
+    }
+
+    async fn stream(&self, socket: &mut TcpStream) -> Result<()> {
+        let mut frames = self.frames.subscribe();
+        let header = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: multipart/x-mixed-replace; boundary={BOUNDARY}\r\nCache-Control: no-cache\r\nPragma: no-cache\r\nConnection: close\r\n\r\n"
+        );
+        socket.write_all(header.as_bytes()).await?;
+
+        self.stats.viewers.fetch_add(1, Ordering::Relaxed);
+        let result = self.forward(&mut frames, socket).await;
+        self.stats.viewers.fetch_sub(1, Ordering::Relaxed);
+        result
+    }
+
+    async fn forward(
+        &self,
+        frames: &mut broadcast::Receiver<Bytes>,
+        socket: &mut TcpStream,
+    ) -> Result<()> {
+        loop {
+            let jpeg = match frames.recv().await {
+                Ok(jpeg) => jpeg,
+                Err(RecvError::Lagged(skipped)) => {
+                    self.stats.dropped.fetch_add(skipped, Ordering::Relaxed);
+                    continue;
+                }
+                Err(RecvError::Closed) => return Ok(()),
+            };
+
+            let part = format!(
+                "--{BOUNDARY}\r\nContent-Type: image/jpeg\r\nContent-Length: {}\r\n\r\n",
+                jpeg.len()
+            );
+            socket.write_all(part.as_bytes()).await?;
+            socket.write_all(&jpeg).await?;
+            socket.write_all(b"\r\n").await?;
+        }
+    }
+}
@@ -0,0 +1,122 @@
+//! Muxing for the senders of encoded streams: MPEG-TS, or Matroska for
+//! codecs MPEG-TS cannot carry. The muxer writes into memory and the output
+//! is handed out as SRT sized messages after every packet, so the caller
+//! decides how to pace and send it.
+
+use std::{
+    fmt,
+    io::{self, Write},
+    sync::{Arc, Mutex},
+};
+
+use ac_ffmpeg::{
+    codec::CodecParameters,
+    format::{
+        io::IO,
+        muxer::{Muxer, OutputFormat},
+    },
+    packet::Packet,
+};
+use anyhow::{Context, Result};
+use bytes::{Bytes, BytesMut};
+
+/// Seven TS packets, the usual SRT payload for transport streams.
+pub const TS_MESSAGE_LEN: usize = 7 * 188;
+
+// Longest Matroska cluster. Without a seekable output the muxer holds a
+// cluster back until it is complete, so this is added latency.
+const MATROSKA_CLUSTER_MS: u32 = 100;
+
+/// How encoded packets are laid out in the byte stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Container {
+    /// For H.264, HEVC and MJPEG.
+    MpegTs,
+    /// Live Matroska (WebM) for VP9 and AV1.
+    Matroska,
+}
+
+impl Container {
+    /// Name of the ffmpeg muxer and demuxer.
+    pub fn format_name(self) -> &'static str {
+        match self {
+            Container::MpegTs => "mpegts",
+            Container::Matroska => "matroska",
+        }
+    }
+}
+
+impl fmt::Display for Container {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Container::MpegTs => "MPEG-TS",
+            Container::Matroska => "Matroska",
+        })
+    }
+}
+
+/// Collects everything the muxer writes.
+struct WriteBridge(Arc<Mutex<BytesMut>>);
+
+impl Write for WriteBridge {
+    fn write(&mut self, w: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(w);
+        Ok(w.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+pub struct StreamMuxer {
+    muxer: Muxer<WriteBridge>,
+    written: Arc<Mutex<BytesMut>>,
+}
+
+impl StreamMuxer {
+    /// Creates a muxer with one stream per entry of `streams`, packets are
+    /// routed by their stream index.
+    pub fn new(container: Container, streams: &[CodecParameters]) -> Result<Self> {
+        let written = Arc::new(Mutex::new(BytesMut::new()));
+
+        let mut builder = Muxer::builder();
+        for params in streams {
+            builder.add_stream(params)?;
+        }
+        if container == Container::Matroska {
+            builder = builder
+                .set_option("live", "1")
+                .set_option("cluster_time_limit", MATROSKA_CLUSTER_MS);
+        }
+        let name = container.format_name();
+        let format = OutputFormat::find_by_name(name)
+            .with_context(|| format!("ffmpeg has no {name} muxer"))?;
+        let muxer = builder.build(IO::from_write_stream(WriteBridge(written.clone())), format)?;
+
+        Ok(Self { muxer, written })
+    }
+
+    /// Muxes `packet` and returns the messages ready to send, often none
+    /// while ffmpeg buffers output.
+    pub fn push(&mut self, packet: Packet) -> Result<Vec<Bytes>> {
+        self.muxer.push(packet)?;
+        Ok(self.take_messages())
+    }
+
+    /// Flushes and closes the muxer, returning the remaining messages.
+    pub fn finish(mut self) -> Result<Vec<Bytes>> {
+        self.muxer.flush()?;
+        self.muxer.close()?;
+        Ok(self.take_messages())
+    }
+
+    fn take_messages(&self) -> Vec<Bytes> {
+        let mut written = self.written.lock().unwrap().split().freeze();
+        let mut messages = Vec::with_capacity(written.len().div_ceil(TS_MESSAGE_LEN));
+        while !written.is_empty() {
+            messages.push(written.split_to(TS_MESSAGE_LEN.min(written.len())));
+        }
+        messages
+    }
+}
@@ -0,0 +1,95 @@
+//! What the receivers do with a decoded frame: measure the burned-in
+//! timecode, dump it to disk, republish it to MJPEG viewers and show it.
+//!
+//! Configured from the environment:
+//! - `HEADLESS`: skip the OpenCV window, e.g. on servers and in CI
+//! - `TIMECODE`: read the sender's burned-in capture time
+//! - `MJPEG_ADDR`: browser viewer address, e.g. `0.0.0.0:8080`
+//! - `DUMP_*`: see [`crate::dump`]
+
+use anyhow::Result;
+use opencv::{highgui, prelude::*};
+
+use crate::{
+    dump::FrameDumper, encode::JpegEncoder, mjpeg::MjpegServer, receiver::ReceivedFrame,
+    timecode::LatencyStats,
+};
+
+pub struct FrameOutput {
+    window: Option<&'static str>,
+    pub dumper: Option<FrameDumper>,
+    pub mjpeg: Option<MjpegServer>,
+    pub latency: Option<LatencyStats>,
+}
+
+impl FrameOutput {
+    /// Shows frames in `window`, or nowhere when `window` is `None`.
+    pub fn new(window: Option<&'static str>) -> Result<Self> {
+        if let Some(window) = window {
+            highgui::named_window(window, highgui::WINDOW_AUTOSIZE)?;
+        }
+        Ok(Self {
+            window,
+            dumper: None,
+            mjpeg: None,
+            latency: None,
+        })
+    }
+
+    pub async fn from_env(window: &'static str) -> Result<Self> {
+        let headless = std::env::var_os("HEADLESS").is_some();
+        let mut output = Self::new((!headless).then_some(window))?;
+        output.dumper = FrameDumper::from_env()?;
+        output.latency = std::env::var_os("TIMECODE").map(|_| LatencyStats::new());
+
+        if let Ok(addr) = std::env::var("MJPEG_ADDR") {
+            output.mjpeg = Some(MjpegServer::bind(&addr).await?);
+            println!("MJPEG viewer on http://{addr}/");
+        }
+        Ok(output)
+    }
+
+    /// Returns `false` once the user pressed ESC or 'q'.
+    pub fn present(&mut self, frame: &ReceivedFrame) -> Result<bool> {
+        let number = frame.number;
+        if let Some(latency) = &mut self.latency {
+            match latency.measure(&frame.image)? {
+                Some(reading) => println!(
+                    "Frame #{} timecode {} us, glass-to-glass latency {:.1} ms",
+                    number,
+                    reading.micros(),
+                    reading.latency.as_secs_f64() * 1000.0
+                ),
+                None => println!("Frame #{} has no readable timecode", number),
+            }
+        }
+
+        if let Some(dumper) = &mut self.dumper {
+            if let Some(path) = dumper.offer(number, &frame.image)? {
+                println!("Frame #{} saved to {}", number, path.display());
+            }
+        }
+
+        // Republish the original JPEG when there is one, no need to re-encode
+        if let Some(mjpeg) = &self.mjpeg {
+            match &frame.jpeg {
+                Some(jpeg) => mjpeg.publish(jpeg.clone()),
+                None => mjpeg.publish(JpegEncoder::new(None).encode(&frame.image)?),
+            }
+        }
+
+        let Some(window) = self.window else {
+            return Ok(true);
+        };
+        highgui::imshow(window, &frame.image)?;
+        let key = highgui::wait_key(1)?;
+        Ok(key != 27 && key != 'q' as i32)
+    }
+
+    /// Prints the end of session statistics.
+    pub fn summary(&self) {
+        if let Some(latency) = &self.latency {
+            println!("Glass-to-glass: {}", latency.summary());
+        }
+    }
+}
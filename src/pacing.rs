@@ -0,0 +1,116 @@
+//! Sends encoded packets at the pace of their decode timestamps instead of
+//! as fast as the encoder produces them.
+//!
+//! Packets leave the encoder in decode order, so [`DtsPacer`] follows the
+//! DTS, which keeps increasing while B-frames reorder the PTS. Timestamps
+//! are not always a clean clock though:
+//!
+//! - a jump of more than [`MAX_TIMESTAMP_JUMP`] either way, e.g. a source
+//!   that restarted, re-anchors the pacer instead of stalling or bursting,
+//! - steps are taken modulo the 33-bit MPEG clock, so its wraparound after
+//!   about 26.5 hours is just another step forward,
+//! - a source slightly slower than its timestamps, e.g. a camera under its
+//!   nominal rate, makes every packet a little late. The pacer slews its
+//!   anchor towards the late packets so the lag does not pile up into a
+//!   burst once the source catches up, and re-anchors outright beyond
+//!   [`MAX_LATENESS`].
+
+use std::time::Duration;
+
+use ac_ffmpeg::time::{TimeBase, Timestamp};
+use tokio::time::{sleep_until, Instant};
+
+/// The MPEG-TS clock, timestamps are compared in its ticks.
+const MPEG_CLOCK: u32 = 90_000;
+
+/// MPEG-TS timestamps are 33 bits wide.
+const MPEG_WRAP: i64 = 1 << 33;
+
+/// Largest step between two packets still taken as the same timeline.
+pub const MAX_TIMESTAMP_JUMP: Duration = Duration::from_secs(2);
+
+/// How far behind its timestamp a packet may be before the pacer gives up
+/// catching up and re-anchors.
+pub const MAX_LATENESS: Duration = Duration::from_millis(500);
+
+/// Share of a late packet's lateness the anchor absorbs, as a divisor.
+const DRIFT_SLEW: u32 = 16;
+
+#[derive(Debug, Default)]
+pub struct DtsPacer {
+    // Unwrapped ticks and the instant they were due
+    anchor: Option<(i64, Instant)>,
+    // Unwrapped ticks of the latest packet
+    last: i64,
+}
+
+impl DtsPacer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Waits until the packet with `dts` is due. The first packet is due
+    /// immediately, as is one stepping back a little; packets sharing a
+    /// timestamp go out together.
+    pub async fn pace(&mut self, dts: Timestamp) {
+        let now = Instant::now();
+        let ticks = dts
+            .with_time_base(TimeBase::new(1, MPEG_CLOCK as i32))
+            .timestamp();
+        let Some((anchor_ticks, anchor_at)) = self.anchor else {
+            self.reanchor(ticks, now);
+            return;
+        };
+
+        let step = wrapping_step(self.last, ticks);
+        if step.unsigned_abs() > duration_to_ticks(MAX_TIMESTAMP_JUMP) {
+            println!(
+                "Timestamp jumped by {:.3}s, re-anchoring the pacing",
+                step as f64 / MPEG_CLOCK as f64
+            );
+            self.reanchor(self.last + step, now);
+            return;
+        }
+        if step < 0 {
+            return;
+        }
+        self.last += step;
+
+        let deadline = anchor_at + ticks_to_duration(self.last - anchor_ticks);
+        let late = now.saturating_duration_since(deadline);
+        if late > MAX_LATENESS {
+            println!(
+                "Packets are {}ms behind their timestamps, re-anchoring the pacing",
+                late.as_millis()
+            );
+            self.reanchor(self.last, now);
+        } else if !late.is_zero() {
+            self.anchor = Some((anchor_ticks, anchor_at + late / DRIFT_SLEW));
+        } else {
+            sleep_until(deadline).await;
+        }
+    }
+
+    fn reanchor(&mut self, ticks: i64, now: Instant) {
+        self.anchor = Some((ticks, now));
+        self.last = ticks;
+    }
+}
+
+/// Step from `last` to the 33-bit `ticks`, the shorter way around the wrap.
+fn wrapping_step(last: i64, ticks: i64) -> i64 {
+    let step = (ticks - last).rem_euclid(MPEG_WRAP);
+    if step > MPEG_WRAP / 2 {
+        step - MPEG_WRAP
+    } else {
+        step
+    }
+}
+
+fn ticks_to_duration(ticks: i64) -> Duration {
+    Duration::from_nanos(ticks.max(0) as u64 * 100_000 / 9)
+}
+
+fn duration_to_ticks(duration: Duration) -> u64 {
+    duration.as_millis() as u64 * MPEG_CLOCK as u64 / 1000
+}
@@ -0,0 +1,128 @@
+//! Packetizers for the sender [`crate::pipeline`]: how encoded frames are
+//! laid out in SRT messages.
+
+use anyhow::Result;
+use bytes::Bytes;
+
+use crate::{
+    audio::AudioTrack,
+    framing,
+    mux::{Container, StreamMuxer},
+    pipeline::{unsupported, EncodedPacket, Message, Packetizer, Payload},
+};
+
+/// One message per encoded frame, as v1 and v5 send JPEGs. Frames must fit
+/// into a single SRT message.
+pub struct MessagePacketizer;
+
+impl Packetizer for MessagePacketizer {
+    fn packetize(&mut self, packet: EncodedPacket) -> Result<Vec<Message>> {
+        let data = match packet.payload {
+            Payload::Image(data) => data,
+            Payload::Packet(packet) => Bytes::copy_from_slice(packet.data()),
+        };
+        Ok(vec![Message { data, dts: None }])
+    }
+}
+
+/// Length-prefixed frames split into fixed size messages, the v4 format.
+pub struct ChunkPacketizer {
+    chunk_len: usize,
+}
+
+impl ChunkPacketizer {
+    /// Panics if `chunk_len` is zero.
+    pub fn new(chunk_len: usize) -> Self {
+        assert!(chunk_len > 0, "chunk length must be positive");
+        Self { chunk_len }
+    }
+}
+
+impl Packetizer for ChunkPacketizer {
+    fn packetize(&mut self, packet: EncodedPacket) -> Result<Vec<Message>> {
+        Ok(framing::frame_chunks(packet.payload.data(), self.chunk_len)
+            .into_iter()
+            .map(|data| Message { data, dts: None })
+            .collect())
+    }
+}
+
+/// A muxed byte stream, MPEG-TS as v2 and v3 send it or Matroska. Needs
+/// ffmpeg packets; the muxer is (re)created whenever a packet announces new
+/// codec parameters.
+pub struct MuxPacketizer {
+    container: Container,
+    muxer: Option<StreamMuxer>,
+    audio: Option<AudioTrack>,
+}
+
+impl MuxPacketizer {
+    pub fn new(container: Container) -> Self {
+        Self {
+            container,
+            muxer: None,
+            audio: None,
+        }
+    }
+
+    /// Muxes an audio track alongside the video, kept on the video
+    /// timeline, see [`crate::audio`].
+    pub fn audio(mut self, track: Option<AudioTrack>) -> Self {
+        self.audio = track;
+        self
+    }
+}
+
+impl Packetizer for MuxPacketizer {
+    fn packetize(&mut self, packet: EncodedPacket) -> Result<Vec<Message>> {
+        let Payload::Packet(ffmpeg_packet) = packet.payload else {
+            return Err(unsupported(format!(
+                "{} needs an ffmpeg encoder such as h264 or mjpeg",
+                self.container.format_name()
+            )));
+        };
+        let dts = packet.dts;
+
+        let mut data = Vec::new();
+        if let Some(codec) = packet.codec {
+            if let Some(old) = self.muxer.take() {
+                data.extend(old.finish()?);
+            }
+            let mut streams = vec![codec];
+            streams.extend(self.audio.as_ref().map(AudioTrack::codec_parameters));
+            self.muxer = Some(StreamMuxer::new(self.container, &streams)?);
+        }
+        let Some(muxer) = &mut self.muxer else {
+            return Err(unsupported("first packet carries no codec parameters"));
+        };
+        // Audio goes first, up to where the video packet starts decoding
+        if let (Some(audio), Some(dts)) = (&mut self.audio, dts) {
+            for audio_packet in audio.packets_until(dts)? {
+                data.extend(muxer.push(audio_packet)?);
+            }
+        }
+        data.extend(muxer.push(ffmpeg_packet)?);
+
+        Ok(data.into_iter().map(|data| Message { data, dts }).collect())
+    }
+
+    fn finish(&mut self) -> Result<Vec<Message>> {
+        let data = match self.muxer.take() {
+            Some(mut muxer) => {
+                let mut data = Vec::new();
+                if let Some(audio) = &mut self.audio {
+                    for audio_packet in audio.finish()? {
+                        data.extend(muxer.push(audio_packet)?);
+                    }
+                }
+                data.extend(muxer.finish()?);
+                data
+            }
+            None => Vec::new(),
+        };
+        Ok(data
+            .into_iter()
+            .map(|data| Message { data, dts: None })
+            .collect())
+    }
+}
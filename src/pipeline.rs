@@ -0,0 +1,870 @@
+//! Sender pipelines assembled from stages at runtime:
+//!
+//! ```text
+//! Source -> Filter* -> Encoder -> Packetizer -> Transport
+//! ```
+//!
+//! The source and its filters run together in one blocking task, the
+//! encoder and the packetizer in one each, and the transport on the async
+//! side. They are connected by small bounded channels, so a slow transport
+//! backs up into the encoder and the source instead of queueing frames.
+//! Messages carrying a timestamp are paced before they reach the transport.
+//!
+//! A pipeline can be described as text, stages separated by `!`:
+//!
+//! ```text
+//! file:clip.mp4 ! resize:640x480 ! h264 ! mpegts ! srt-call:127.0.0.1:1234
+//! ```
+//!
+//! - sources: `camera[:INDEX]`, `synthetic[:WxH[@FPS]]`, `file:PATH`
+//! - filters: `resize:WxH[,letterbox|,crop]` (stretching by default),
+//!   `crop:WxH+X+Y` (region of interest), `grayscale`, `fps:RATE` (drops
+//!   frames down to the rate), `timecode`
+//! - encoders: `jpeg[:QUALITY][,max=BUDGET]`, `mjpeg[:QUALITY][,max=BUDGET]`
+//!   (JPEG packets for muxing), `h264|hevc|vp9|av1[:ARGS]`. `BUDGET` caps
+//!   every JPEG at a byte count or a bitrate like `2mbps`, see
+//!   [`crate::encode::FrameBudget`]. `ARGS` are comma separated `WxH@KBPS`
+//!   rungs the encoder switches between with the link, see
+//!   [`crate::rate::Ladder`], latency settings like `zerolatency` and
+//!   `gop=30`, and `KEY=VALUE` options for the ffmpeg encoder, see
+//!   [`crate::encode::VideoSettings::from_args`]
+//! - packetizers: `message` (one per SRT message), `chunked[:BYTES]`
+//!   (length-prefixed), `mpegts[:AUDIO]` (H.264, HEVC, MJPEG), `matroska`
+//!   (VP9, AV1). `AUDIO` adds an AAC or Opus track, e.g. `aac` for a test
+//!   tone or `opus,wav=clip.wav`, see [`crate::audio::AudioSettings`]
+//! - transports: `srt-listen:ADDR`, `srt-call:ADDR`, `srt-rendezvous:ADDR`,
+//!   `srt-serve:ADDR` (any number of callers, joining at a forced keyframe)
+
+use std::{
+    fmt,
+    str::FromStr,
+    time::{Instant, SystemTime},
+};
+
+use ac_ffmpeg::{codec::CodecParameters, packet::Packet, time::Timestamp};
+use anyhow::{anyhow, bail, Context, Result};
+use bytes::Bytes;
+use futures::future::BoxFuture;
+use opencv::{core::Mat, prelude::*};
+use tokio::{sync::mpsc, task::JoinHandle};
+
+use crate::{
+    audio::{AudioSettings, AudioTrack},
+    encode::{CodecEncoder, FrameBudget, JpegEncoder, MjpegEncoder, VideoCodec, VideoSettings},
+    filter::{Crop, Decimate, Grayscale, Region, Resize, Scale, TimecodeFilter},
+    framing::CHUNK_LEN,
+    keyframe::{KeyframeLimiter, KeyframeTrigger},
+    mux::Container,
+    pacing::DtsPacer,
+    packetize::{ChunkPacketizer, MessagePacketizer, MuxPacketizer},
+    rate::{LadderController, LinkStats, QualityController, QualityKnob, QualityRange},
+    source::{self, FrameSource},
+    transport::{self, Connector, Role, SrtTransport},
+};
+
+// Channel capacities between the stages. Kept small: a frame waiting here
+// is latency.
+const FRAME_BACKLOG: usize = 2;
+const PACKET_BACKLOG: usize = 8;
+const MESSAGE_BACKLOG: usize = 64;
+
+/// Video format at some point of the pipeline.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StreamInfo {
+    pub width: usize,
+    pub height: usize,
+    pub fps: f64,
+}
+
+impl StreamInfo {
+    pub fn of(source: &dyn FrameSource) -> Self {
+        Self {
+            width: source.width(),
+            height: source.height(),
+            fps: source.fps(),
+        }
+    }
+}
+
+/// Travels with a frame through every stage.
+#[derive(Debug, Clone, Copy)]
+pub struct FrameMeta {
+    /// Position in the source, frames dropped by filters leave gaps.
+    pub index: u64,
+    pub captured: SystemTime,
+}
+
+pub struct Frame {
+    /// Packed BGR.
+    pub image: Mat,
+    pub meta: FrameMeta,
+}
+
+pub enum Payload {
+    /// A standalone image, e.g. a JPEG.
+    Image(Bytes),
+    /// An ffmpeg packet, ready for muxing.
+    Packet(Packet),
+}
+
+impl Payload {
+    pub fn data(&self) -> &[u8] {
+        match self {
+            Payload::Image(data) => data,
+            Payload::Packet(packet) => packet.data(),
+        }
+    }
+}
+
+pub struct EncodedPacket {
+    pub meta: FrameMeta,
+    pub payload: Payload,
+    /// Decode time, packets leave the encoder in this order.
+    pub dts: Option<Timestamp>,
+    pub keyframe: bool,
+    /// Set on the first packet and whenever the encoder parameters change,
+    /// for packetizers that have to describe the stream.
+    pub codec: Option<CodecParameters>,
+}
+
+/// One SRT message worth of data.
+pub struct Message {
+    pub data: Bytes,
+    /// Decode time of the packet the data belongs to, the message is paced
+    /// on it when set.
+    pub dts: Option<Timestamp>,
+}
+
+pub trait Source: Send {
+    fn info(&self) -> StreamInfo;
+
+    /// Blocks until the next frame is available, `None` at the end.
+    fn next_frame(&mut self) -> Result<Option<Frame>>;
+}
+
+pub trait Filter: Send {
+    /// Format of the output for `input`.
+    fn info(&self, input: StreamInfo) -> StreamInfo {
+        input
+    }
+
+    /// Transforms a frame, `None` drops it.
+    fn apply(&mut self, frame: Frame) -> Result<Option<Frame>>;
+}
+
+pub trait Encoder: Send {
+    /// Encodes a frame, returning whatever packets are ready. Encoders with
+    /// a delay return packets of earlier frames.
+    fn encode(&mut self, frame: Frame) -> Result<Vec<EncodedPacket>>;
+
+    /// Drains the encoder at the end of the stream.
+    fn flush(&mut self) -> Result<Vec<EncodedPacket>> {
+        Ok(Vec::new())
+    }
+
+    /// Handle to change the quality while encoding, for encoders that have
+    /// one.
+    fn quality_control(&mut self) -> Option<QualityKnob> {
+        None
+    }
+
+    /// Controller for encoders configured with a bitrate ladder, the
+    /// pipeline feeds it the link statistics.
+    fn ladder_control(&mut self) -> Option<LadderController> {
+        None
+    }
+
+    /// Handle to force a keyframe, for encoders that do not make every
+    /// frame one.
+    fn keyframe_control(&mut self) -> Option<KeyframeTrigger> {
+        None
+    }
+}
+
+pub trait Packetizer: Send {
+    fn packetize(&mut self, packet: EncodedPacket) -> Result<Vec<Message>>;
+
+    /// Returns what is still buffered at the end of the stream.
+    fn finish(&mut self) -> Result<Vec<Message>> {
+        Ok(Vec::new())
+    }
+}
+
+pub trait Transport: Send {
+    /// Resolves once the transport accepted the message, which is where
+    /// backpressure comes from.
+    fn send(&mut self, message: Message) -> BoxFuture<'_, Result<()>>;
+
+    /// Flushes and closes the connection.
+    fn close(&mut self) -> BoxFuture<'_, Result<()>>;
+
+    /// The latest link statistics, if new ones arrived since the last call.
+    fn stats(&mut self) -> Option<LinkStats> {
+        None
+    }
+
+    /// Why a receiver needs a keyframe, if one joined or asked for it since
+    /// the last call, see [`crate::keyframe`].
+    fn keyframe_request(&mut self) -> Option<String> {
+        None
+    }
+}
+
+/// Adapts a [`FrameSource`] to the pipeline.
+pub struct CaptureSource {
+    source: Box<dyn FrameSource>,
+    index: u64,
+}
+
+impl CaptureSource {
+    pub fn new(source: Box<dyn FrameSource>) -> Self {
+        Self { source, index: 0 }
+    }
+}
+
+impl Source for CaptureSource {
+    fn info(&self) -> StreamInfo {
+        StreamInfo::of(&*self.source)
+    }
+
+    fn next_frame(&mut self) -> Result<Option<Frame>> {
+        let mut image = Mat::default();
+        if !self.source.read(&mut image)? || image.empty() {
+            return Ok(None);
+        }
+        let meta = FrameMeta {
+            index: self.index,
+            captured: SystemTime::now(),
+        };
+        self.index += 1;
+        Ok(Some(Frame { image, meta }))
+    }
+}
+
+/// What a finished pipeline did.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PipelineStats {
+    /// Frames handed to the encoder.
+    pub frames: u64,
+    pub packets: u64,
+    pub messages: u64,
+    pub bytes: u64,
+}
+
+pub struct Pipeline {
+    source: Box<dyn Source>,
+    filters: Vec<Box<dyn Filter>>,
+    encoder: Box<dyn Encoder>,
+    packetizer: Box<dyn Packetizer>,
+    transport: Box<dyn Transport>,
+    frame_limit: Option<u64>,
+    adaptive_quality: Option<QualityRange>,
+}
+
+impl Pipeline {
+    pub fn new(
+        source: Box<dyn Source>,
+        encoder: Box<dyn Encoder>,
+        packetizer: Box<dyn Packetizer>,
+        transport: Box<dyn Transport>,
+    ) -> Self {
+        Self {
+            source,
+            filters: Vec::new(),
+            encoder,
+            packetizer,
+            transport,
+            frame_limit: None,
+            adaptive_quality: None,
+        }
+    }
+
+    /// Appends a filter, filters run in the order they were added.
+    pub fn filter(mut self, filter: Box<dyn Filter>) -> Self {
+        self.filters.push(filter);
+        self
+    }
+
+    /// Stops after `limit` source frames.
+    pub fn frame_limit(mut self, limit: Option<u64>) -> Self {
+        self.frame_limit = limit;
+        self
+    }
+
+    /// Moves the encoder quality within `range` with the transport's link
+    /// statistics, see [`QualityController`]. Needs an encoder with a
+    /// quality control, i.e. a JPEG one.
+    pub fn adaptive_quality(mut self, range: Option<QualityRange>) -> Self {
+        self.adaptive_quality = range;
+        self
+    }
+
+    /// Opens the source, builds the stages and connects the transport.
+    pub async fn from_spec(spec: &PipelineSpec) -> Result<Self> {
+        let source = spec.source.open()?;
+        let (filters, info) = build_filters(&spec.filters, source.info())?;
+        let encoder = spec.encoder.build(info)?;
+        let packetizer = spec.packetizer.build()?;
+        let transport = spec.transport.connect().await?;
+
+        let mut pipeline = Self::new(source, encoder, packetizer, transport);
+        pipeline.filters = filters;
+        Ok(pipeline)
+    }
+
+    /// Format the encoder sees, after all filters.
+    pub fn info(&self) -> StreamInfo {
+        self.filters
+            .iter()
+            .fold(self.source.info(), |info, filter| filter.info(info))
+    }
+
+    /// Runs until the source ends or a stage fails, then closes the
+    /// transport.
+    pub async fn run(self) -> Result<PipelineStats> {
+        let Self {
+            mut source,
+            mut filters,
+            mut encoder,
+            mut packetizer,
+            mut transport,
+            frame_limit,
+            adaptive_quality,
+        } = self;
+
+        let mut quality = match adaptive_quality {
+            Some(range) => {
+                let knob = encoder
+                    .quality_control()
+                    .context("adaptive quality needs an encoder with a quality setting")?;
+                println!("Adapting JPEG quality within {range}");
+                Some(QualityController::new(knob, range))
+            }
+            None => None,
+        };
+        let mut ladder = encoder.ladder_control();
+        let keyframe = encoder.keyframe_control();
+        let mut keyframe_limiter = KeyframeLimiter::default();
+
+        let (frame_send, mut frames) = mpsc::channel::<Frame>(FRAME_BACKLOG);
+        let (packet_send, mut packets) = mpsc::channel::<EncodedPacket>(PACKET_BACKLOG);
+        let (message_send, mut messages) = mpsc::channel::<Message>(MESSAGE_BACKLOG);
+
+        // A failed send means a later stage has stopped, its error wins
+        let capture: JoinHandle<Result<u64>> = tokio::task::spawn_blocking(move || {
+            let mut sent = 0;
+            let mut read = 0;
+            while frame_limit.is_none_or(|limit| read < limit) {
+                let Some(frame) = source.next_frame()? else {
+                    break;
+                };
+                read += 1;
+
+                let mut frame = Some(frame);
+                for filter in &mut filters {
+                    frame = match frame {
+                        Some(frame) => filter.apply(frame)?,
+                        None => break,
+                    };
+                }
+                let Some(frame) = frame else {
+                    continue;
+                };
+                if frame_send.blocking_send(frame).is_err() {
+                    break;
+                }
+                sent += 1;
+            }
+            Ok(sent)
+        });
+
+        let encode: JoinHandle<Result<u64>> = tokio::task::spawn_blocking(move || {
+            let mut count = 0;
+            let mut forward = |encoded: Vec<EncodedPacket>| {
+                for packet in encoded {
+                    if packet_send.blocking_send(packet).is_err() {
+                        return false;
+                    }
+                    count += 1;
+                }
+                true
+            };
+            while let Some(frame) = frames.blocking_recv() {
+                if !forward(encoder.encode(frame)?) {
+                    return Ok(count);
+                }
+            }
+            forward(encoder.flush()?);
+            Ok(count)
+        });
+
+        let packetize: JoinHandle<Result<()>> = tokio::task::spawn_blocking(move || {
+            let forward = |messages: Vec<Message>| {
+                messages
+                    .into_iter()
+                    .all(|message| message_send.blocking_send(message).is_ok())
+            };
+            while let Some(packet) = packets.blocking_recv() {
+                if !forward(packetizer.packetize(packet)?) {
+                    return Ok(());
+                }
+            }
+            forward(packetizer.finish()?);
+            Ok(())
+        });
+
+        let mut stats = PipelineStats::default();
+        let mut pacer = DtsPacer::new();
+        while let Some(message) = messages.recv().await {
+            if let Some(dts) = message.dts {
+                pacer.pace(dts).await;
+            }
+            stats.messages += 1;
+            stats.bytes += message.data.len() as u64;
+            transport.send(message).await?;
+            while let Some(reason) = transport.keyframe_request() {
+                keyframe_limiter.request(reason);
+            }
+            // Encoders without a keyframe control make every frame one
+            if let Some(keyframe) = &keyframe {
+                if let Some(reason) = keyframe_limiter.poll(Instant::now()) {
+                    println!("Forcing a keyframe: {reason}");
+                    keyframe.request();
+                }
+            }
+            if quality.is_some() || ladder.is_some() {
+                if let Some(stats) = transport.stats() {
+                    if let Some(quality) = &mut quality {
+                        quality.update(&stats);
+                    }
+                    if let Some(ladder) = &mut ladder {
+                        ladder.update(&stats);
+                    }
+                }
+            }
+        }
+
+        // Report the stage that failed first in the chain
+        stats.frames = capture.await??;
+        stats.packets = encode.await??;
+        packetize.await??;
+        transport.close().await?;
+        Ok(stats)
+    }
+}
+
+/// Builds `specs` in order for frames of `input`. Returns the filters and
+/// the format they put out, which is what the encoder gets.
+pub fn build_filters(
+    specs: &[FilterSpec],
+    input: StreamInfo,
+) -> Result<(Vec<Box<dyn Filter>>, StreamInfo)> {
+    let mut info = input;
+    let mut filters = Vec::new();
+    for spec in specs {
+        let filter = spec.build(info)?;
+        info = filter.info(info);
+        filters.push(filter);
+    }
+    Ok((filters, info))
+}
+
+/// Splits `kind:argument`.
+fn split_stage(stage: &str) -> (&str, Option<&str>) {
+    match stage.split_once(':') {
+        Some((kind, arg)) => (kind, Some(arg)),
+        None => (stage, None),
+    }
+}
+
+/// `WxH`, both sides above zero.
+pub(crate) fn parse_size(size: &str) -> Result<(usize, usize)> {
+    size.split_once('x')
+        .and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)))
+        .filter(|&(w, h)| w > 0 && h > 0)
+        .with_context(|| format!("{size:?} is not WIDTHxHEIGHT"))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SourceSpec {
+    Camera(i32),
+    Synthetic {
+        width: usize,
+        height: usize,
+        fps: f64,
+    },
+    File(String),
+}
+
+impl SourceSpec {
+    pub fn open(&self) -> Result<Box<dyn Source>> {
+        Ok(Box::new(CaptureSource::new(self.capture()?)))
+    }
+
+    /// Opens the source without the pipeline adapter, e.g. for a
+    /// [`crate::sender::VideoSender`].
+    pub fn capture(&self) -> Result<Box<dyn FrameSource>> {
+        Ok(match self {
+            SourceSpec::Camera(index) => Box::new(source::CameraSource::open(*index)?),
+            SourceSpec::Synthetic { width, height, fps } => {
+                Box::new(source::SyntheticSource::new(*width, *height, *fps))
+            }
+            SourceSpec::File(path) => Box::new(source::FileSource::open(path)?),
+        })
+    }
+}
+
+impl fmt::Display for SourceSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SourceSpec::Camera(index) => write!(f, "camera:{index}"),
+            SourceSpec::Synthetic { width, height, fps } => {
+                write!(f, "synthetic:{width}x{height}@{fps}")
+            }
+            SourceSpec::File(path) => write!(f, "file:{path}"),
+        }
+    }
+}
+
+impl FromStr for SourceSpec {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match split_stage(s) {
+            ("camera", index) => Ok(SourceSpec::Camera(
+                index.map_or(Ok(0), str::parse).context("camera index")?,
+            )),
+            ("synthetic", arg) => {
+                let (size, fps) = match arg.map(|a| a.split_once('@').unwrap_or((a, ""))) {
+                    Some((size, fps)) => (size, fps),
+                    None => ("640x480", ""),
+                };
+                let (width, height) = parse_size(size)?;
+                let fps = if fps.is_empty() {
+                    30.0
+                } else {
+                    fps.parse().context("synthetic frame rate")?
+                };
+                Ok(SourceSpec::Synthetic { width, height, fps })
+            }
+            ("file", Some(path)) => Ok(SourceSpec::File(path.into())),
+            _ => bail!("unknown source {s:?}, expected camera, synthetic or file:PATH"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FilterSpec {
+    Resize(Scale),
+    Crop(Region),
+    Grayscale,
+    /// Highest frame rate, see [`Decimate`].
+    MaxFps(f64),
+    Timecode,
+}
+
+impl FilterSpec {
+    /// The filter for frames of the `input` format.
+    pub fn build(&self, input: StreamInfo) -> Result<Box<dyn Filter>> {
+        Ok(match self {
+            FilterSpec::Resize(scale) => {
+                Box::new(Resize::new(scale.width, scale.height).fit(scale.fit))
+            }
+            FilterSpec::Crop(region) => Box::new(Crop::new(*region, input)?),
+            FilterSpec::Grayscale => Box::new(Grayscale),
+            FilterSpec::MaxFps(fps) => Box::new(Decimate::new(input.fps, *fps)),
+            FilterSpec::Timecode => Box::new(TimecodeFilter),
+        })
+    }
+}
+
+impl fmt::Display for FilterSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FilterSpec::Resize(scale) => write!(f, "resize:{scale}"),
+            FilterSpec::Crop(region) => write!(f, "crop:{region}"),
+            FilterSpec::Grayscale => write!(f, "grayscale"),
+            FilterSpec::MaxFps(fps) => write!(f, "fps:{fps}"),
+            FilterSpec::Timecode => write!(f, "timecode"),
+        }
+    }
+}
+
+impl FromStr for FilterSpec {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match split_stage(s) {
+            ("resize", Some(scale)) => Ok(FilterSpec::Resize(scale.parse()?)),
+            ("crop", Some(region)) => Ok(FilterSpec::Crop(region.parse()?)),
+            ("grayscale" | "gray", None) => Ok(FilterSpec::Grayscale),
+            ("fps", Some(fps)) => match fps.parse() {
+                Ok(fps) if fps > 0.0 => Ok(FilterSpec::MaxFps(fps)),
+                _ => bail!("frame rate {fps:?} is not a positive number"),
+            },
+            ("timecode", None) => Ok(FilterSpec::Timecode),
+            _ => bail!(
+                "unknown filter {s:?}, expected resize:WxH[,FIT], crop:WxH+X+Y, grayscale, \
+                 fps:RATE or timecode"
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EncoderSpec {
+    Jpeg {
+        quality: Option<i32>,
+        budget: Option<FrameBudget>,
+    },
+    Mjpeg {
+        quality: Option<i32>,
+        budget: Option<FrameBudget>,
+    },
+    Video {
+        codec: VideoCodec,
+        settings: VideoSettings,
+    },
+}
+
+impl EncoderSpec {
+    pub fn build(&self, info: StreamInfo) -> Result<Box<dyn Encoder>> {
+        let frame_bytes = |budget: &Option<FrameBudget>| budget.map(|b| b.frame_bytes(info.fps));
+        Ok(match self {
+            EncoderSpec::Jpeg { quality, budget } => {
+                Box::new(JpegEncoder::new(*quality).max_frame_bytes(frame_bytes(budget)))
+            }
+            EncoderSpec::Mjpeg { quality, budget } => {
+                Box::new(MjpegEncoder::new(*quality, info.fps).max_frame_bytes(frame_bytes(budget)))
+            }
+            EncoderSpec::Video { codec, settings } => Box::new(CodecEncoder::new(
+                *codec,
+                settings,
+                info.width,
+                info.height,
+                info.fps,
+            )?),
+        })
+    }
+}
+
+impl fmt::Display for EncoderSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (kind, quality, budget) = match self {
+            EncoderSpec::Jpeg { quality, budget } => ("jpeg", quality, budget),
+            EncoderSpec::Mjpeg { quality, budget } => ("mjpeg", quality, budget),
+            EncoderSpec::Video { codec, settings } => {
+                return match settings.args() {
+                    Some(args) => write!(f, "{codec}:{args}"),
+                    None => write!(f, "{codec}"),
+                };
+            }
+        };
+        match (quality, budget) {
+            (Some(q), Some(b)) => write!(f, "{kind}:{q},max={b}"),
+            (Some(q), None) => write!(f, "{kind}:{q}"),
+            (None, Some(b)) => write!(f, "{kind}:max={b}"),
+            (None, None) => write!(f, "{kind}"),
+        }
+    }
+}
+
+impl FromStr for EncoderSpec {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        // [QUALITY][,max=BUDGET], either part optional
+        let jpeg = |args: Option<&str>| -> Result<(Option<i32>, Option<FrameBudget>)> {
+            let (mut quality, mut budget) = (None, None);
+            for arg in args.into_iter().flat_map(|args| args.split(',')) {
+                match arg.strip_prefix("max=") {
+                    Some(max) => budget = Some(max.parse()?),
+                    None => quality = Some(arg.parse().context("JPEG quality must be a number")?),
+                }
+            }
+            Ok((quality, budget))
+        };
+        match split_stage(s) {
+            ("jpeg", args) => {
+                let (quality, budget) = jpeg(args)?;
+                Ok(EncoderSpec::Jpeg { quality, budget })
+            }
+            ("mjpeg", args) => {
+                let (quality, budget) = jpeg(args)?;
+                Ok(EncoderSpec::Mjpeg { quality, budget })
+            }
+            (kind, args) => {
+                let codec: VideoCodec = kind.parse().map_err(|_| {
+                    anyhow!("unknown encoder {s:?}, expected jpeg, mjpeg, h264, hevc, vp9 or av1")
+                })?;
+                let settings = args
+                    .map(VideoSettings::from_args)
+                    .transpose()?
+                    .unwrap_or_default();
+                Ok(EncoderSpec::Video { codec, settings })
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PacketizerSpec {
+    Message,
+    /// Messages of at most this many bytes.
+    Chunked(usize),
+    /// With an audio track when set.
+    MpegTs(Option<AudioSettings>),
+    Matroska,
+}
+
+impl PacketizerSpec {
+    pub fn build(&self) -> Result<Box<dyn Packetizer>> {
+        Ok(match self {
+            PacketizerSpec::Message => Box::new(MessagePacketizer),
+            PacketizerSpec::Chunked(len) => Box::new(ChunkPacketizer::new(*len)),
+            PacketizerSpec::MpegTs(audio) => Box::new(
+                MuxPacketizer::new(Container::MpegTs)
+                    .audio(audio.as_ref().map(AudioTrack::new).transpose()?),
+            ),
+            PacketizerSpec::Matroska => Box::new(MuxPacketizer::new(Container::Matroska)),
+        })
+    }
+}
+
+impl fmt::Display for PacketizerSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PacketizerSpec::Message => write!(f, "message"),
+            PacketizerSpec::Chunked(len) => write!(f, "chunked:{len}"),
+            PacketizerSpec::MpegTs(None) => write!(f, "mpegts"),
+            PacketizerSpec::MpegTs(Some(audio)) => write!(f, "mpegts:{audio}"),
+            PacketizerSpec::Matroska => write!(f, "matroska"),
+        }
+    }
+}
+
+impl FromStr for PacketizerSpec {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match split_stage(s) {
+            ("message", None) => Ok(PacketizerSpec::Message),
+            ("chunked", len) => {
+                let len = len
+                    .map_or(Ok(CHUNK_LEN), str::parse)
+                    .context("chunk size")?;
+                if len == 0 {
+                    bail!("chunk size must be at least one byte");
+                }
+                Ok(PacketizerSpec::Chunked(len))
+            }
+            ("mpegts", audio) => Ok(PacketizerSpec::MpegTs(
+                audio.map(str::parse).transpose().context("audio track")?,
+            )),
+            ("matroska" | "mkv", None) => Ok(PacketizerSpec::Matroska),
+            _ => bail!(
+                "unknown packetizer {s:?}, expected message, chunked[:BYTES], mpegts[:AUDIO] \
+                 or matroska"
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TransportSpec {
+    SrtListen(String),
+    SrtCall(String),
+    SrtRendezvous(String),
+    /// Listens for any number of callers, see [`crate::transport::SrtFanOut`].
+    SrtServe(String),
+}
+
+impl TransportSpec {
+    pub async fn connect(&self) -> Result<Box<dyn Transport>> {
+        Ok(match self {
+            TransportSpec::SrtListen(addr) => Box::new(SrtTransport::listen(addr).await?),
+            TransportSpec::SrtCall(addr) => Box::new(SrtTransport::call(addr).await?),
+            TransportSpec::SrtRendezvous(addr) => Box::new(SrtTransport::new(
+                transport::connect(Role::Rendezvous, addr, None).await?,
+            )),
+            TransportSpec::SrtServe(addr) => {
+                Box::new(Connector::new(Role::Listener, addr).fan_out().await?)
+            }
+        })
+    }
+}
+
+impl fmt::Display for TransportSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransportSpec::SrtListen(addr) => write!(f, "srt-listen:{addr}"),
+            TransportSpec::SrtCall(addr) => write!(f, "srt-call:{addr}"),
+            TransportSpec::SrtRendezvous(addr) => write!(f, "srt-rendezvous:{addr}"),
+            TransportSpec::SrtServe(addr) => write!(f, "srt-serve:{addr}"),
+        }
+    }
+}
+
+impl FromStr for TransportSpec {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match split_stage(s) {
+            ("srt-listen", Some(addr)) => Ok(TransportSpec::SrtListen(addr.into())),
+            ("srt-call", Some(addr)) => Ok(TransportSpec::SrtCall(addr.into())),
+            ("srt-rendezvous", Some(addr)) => Ok(TransportSpec::SrtRendezvous(addr.into())),
+            ("srt-serve", Some(addr)) => Ok(TransportSpec::SrtServe(addr.into())),
+            _ => bail!(
+                "unknown transport {s:?}, expected srt-listen:ADDR, srt-call:ADDR, \
+                 srt-rendezvous:ADDR or srt-serve:ADDR"
+            ),
+        }
+    }
+}
+
+/// A parsed pipeline description, see the module documentation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PipelineSpec {
+    pub source: SourceSpec,
+    pub filters: Vec<FilterSpec>,
+    pub encoder: EncoderSpec,
+    pub packetizer: PacketizerSpec,
+    pub transport: TransportSpec,
+}
+
+impl FromStr for PipelineSpec {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let stages: Vec<&str> = s.split('!').map(str::trim).collect();
+        let [source, filters @ .., encoder, packetizer, transport] = stages.as_slice() else {
+            bail!("a pipeline needs at least source ! encoder ! packetizer ! transport");
+        };
+        Ok(Self {
+            source: source.parse()?,
+            filters: filters.iter().map(|f| f.parse()).collect::<Result<_>>()?,
+            encoder: encoder.parse()?,
+            packetizer: packetizer.parse()?,
+            transport: transport.parse()?,
+        })
+    }
+}
+
+impl fmt::Display for PipelineSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.source)?;
+        for filter in &self.filters {
+            write!(f, " ! {filter}")?;
+        }
+        write!(
+            f,
+            " ! {} ! {} ! {}",
+            self.encoder, self.packetizer, self.transport
+        )
+    }
+}
+
+/// Error for a pipeline that cannot work, e.g. `jpeg ! mpegts`.
+pub(crate) fn unsupported(what: impl fmt::Display) -> anyhow::Error {
+    anyhow!("unsupported pipeline: {what}")
+}
@@ -0,0 +1,132 @@
+//! Timestamp driven playout buffer for the JPEG receivers.
+//!
+//! Every frame is scheduled at its sender timestamp plus a playout delay.
+//! The delay starts at the configured target and grows with the observed
+//! interarrival jitter (RFC 3550 style estimate), so a jittery link trades
+//! latency for smooth playback instead of stuttering. Frames that show up
+//! after their deadline are dropped.
+
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+// Headroom above the target, expressed in jitter estimates.
+const JITTER_MULTIPLIER: f64 = 3.0;
+// Fraction of the gap to the desired delay closed per frame, keeps the
+// adaptation from causing visible speed changes.
+const ADAPT_RATE: f64 = 0.05;
+
+pub struct PlayoutBuffer<T> {
+    target: Duration,
+    max: Duration,
+    delay: Duration,
+    jitter: f64,
+    last_transit: Option<f64>,
+    queue: VecDeque<(Instant, T)>,
+    played: u64,
+    late: u64,
+}
+
+impl<T> PlayoutBuffer<T> {
+    /// `target` is the minimum delay, adaptation never goes beyond `max`.
+    pub fn new(target: Duration, max: Duration) -> Self {
+        Self {
+            target,
+            max: max.max(target),
+            delay: target,
+            jitter: 0.0,
+            last_transit: None,
+            queue: VecDeque::new(),
+            played: 0,
+            late: 0,
+        }
+    }
+
+    /// Reads `PLAYOUT_DELAY_MS` and `PLAYOUT_MAX_DELAY_MS` (default 4x the
+    /// target), `None` when playout pacing is not requested.
+    pub fn from_env() -> Option<Self> {
+        let target: u64 = std::env::var("PLAYOUT_DELAY_MS").ok()?.parse().ok()?;
+        let max = std::env::var("PLAYOUT_MAX_DELAY_MS")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(target * 4);
+        Some(Self::new(
+            Duration::from_millis(target),
+            Duration::from_millis(max),
+        ))
+    }
+
+    /// Schedules `item` sent at `sent_at`, which arrived (and was decoded) at
+    /// `arrived`. Returns `false` if it missed its deadline and was dropped.
+    pub fn push(&mut self, sent_at: Instant, arrived: Instant, item: T) -> bool {
+        self.update_jitter(sent_at, arrived);
+
+        let deadline = sent_at + self.delay;
+        if deadline < arrived {
+            self.late += 1;
+            return false;
+        }
+
+        // Keep the queue ordered by deadline, out of order arrivals are rare
+        let index = self.queue.partition_point(|(d, _)| *d <= deadline);
+        self.queue.insert(index, (deadline, item));
+        true
+    }
+
+    /// When the next frame is due, `None` if the buffer is empty.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.queue.front().map(|(deadline, _)| *deadline)
+    }
+
+    /// Pops the next frame if it is due at `now`.
+    pub fn pop_due(&mut self, now: Instant) -> Option<T> {
+        match self.queue.front() {
+            Some((deadline, _)) if *deadline <= now => {
+                self.played += 1;
+                self.queue.pop_front().map(|(_, item)| item)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn delay(&self) -> Duration {
+        self.delay
+    }
+
+    pub fn jitter(&self) -> Duration {
+        Duration::from_secs_f64(self.jitter)
+    }
+
+    pub fn buffered(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn played(&self) -> u64 {
+        self.played
+    }
+
+    pub fn late(&self) -> u64 {
+        self.late
+    }
+
+    fn update_jitter(&mut self, sent_at: Instant, arrived: Instant) {
+        // Only differences between transit times matter, so the unknown
+        // offset between sender timestamps and our clock cancels out.
+        let transit = if arrived >= sent_at {
+            (arrived - sent_at).as_secs_f64()
+        } else {
+            -(sent_at - arrived).as_secs_f64()
+        };
+        if let Some(last) = self.last_transit {
+            let d = (transit - last).abs();
+            self.jitter += (d - self.jitter) / 16.0;
+        }
+        self.last_transit = Some(transit);
+
+        let desired = (self.target.as_secs_f64() + JITTER_MULTIPLIER * self.jitter)
+            .min(self.max.as_secs_f64());
+        let current = self.delay.as_secs_f64();
+        self.delay = Duration::from_secs_f64(current + (desired - current) * ADAPT_RATE);
+    }
+}
@@ -0,0 +1,449 @@
+//! Adapts the encoding to the link. Both controllers watch the SRT send
+//! buffer, loss and round-trip time:
+//!
+//! - [`QualityController`] moves the JPEG quality of a running encoder
+//!   within a [`QualityRange`].
+//! - [`LadderController`] switches an H.264 encoder between the rungs of a
+//!   [`Ladder`], each a resolution and bitrate.
+//!
+//! Congestion shows up in the send buffer first, so the controllers back
+//! off quickly when it grows and creep back up once the link has been clean
+//! for a while.
+
+use std::{
+    fmt,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicI32, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use anyhow::{bail, Context, Result};
+
+/// Send buffer occupancy above which the link counts as congested.
+pub const DEFAULT_TARGET_BUFFER: Duration = Duration::from_millis(100);
+
+/// Share of lost packets above which the link counts as congested.
+pub const DEFAULT_MAX_LOSS: f64 = 0.02;
+
+/// Clean samples in a row before the quality goes up again.
+const CLEAN_SAMPLES: u32 = 3;
+/// Troubled samples in a row before the ladder steps down, unless the link
+/// is congested outright.
+const LADDER_DOWN_SAMPLES: u32 = 2;
+/// Clean samples in a row before the ladder steps up again. Longer than for
+/// the quality: a switch costs an IDR frame.
+const LADDER_UP_SAMPLES: u32 = 10;
+const STEP_UP: i32 = 2;
+const STEP_DOWN: i32 = 5;
+/// Used instead of [`STEP_DOWN`] when the link is far beyond its targets.
+const STEP_DOWN_SEVERE: i32 = 15;
+
+/// One sample of the sender side of the link.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct LinkStats {
+    /// How much of the stream is waiting to be sent or acknowledged.
+    pub send_buffer: Duration,
+    /// Share of the packets sent since the last sample that were lost.
+    pub loss: f64,
+    pub rtt: Duration,
+}
+
+/// JPEG quality shared between an encoder and whoever adjusts it.
+#[derive(Debug, Clone)]
+pub struct QualityKnob(Arc<AtomicI32>);
+
+impl QualityKnob {
+    pub fn new(quality: i32) -> Self {
+        Self(Arc::new(AtomicI32::new(quality)))
+    }
+
+    pub fn get(&self) -> i32 {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    pub fn set(&self, quality: i32) {
+        self.0.store(quality, Ordering::Relaxed);
+    }
+}
+
+/// Bounds of the adaptive quality, written `MIN-MAX`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QualityRange {
+    pub min: i32,
+    pub max: i32,
+}
+
+impl QualityRange {
+    pub fn new(min: i32, max: i32) -> Result<Self> {
+        if !(1..=100).contains(&min) || !(1..=100).contains(&max) {
+            bail!("JPEG quality bounds must be between 1 and 100");
+        }
+        if min > max {
+            bail!("the lower JPEG quality bound {min} is above the upper one {max}");
+        }
+        Ok(Self { min, max })
+    }
+}
+
+impl fmt::Display for QualityRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.min, self.max)
+    }
+}
+
+impl FromStr for QualityRange {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (min, max) = s
+            .split_once('-')
+            .and_then(|(min, max)| Some((min.trim().parse().ok()?, max.trim().parse().ok()?)))
+            .with_context(|| format!("{s:?} is not MIN-MAX"))?;
+        Self::new(min, max)
+    }
+}
+
+/// Reads `ADAPTIVE_QUALITY`, e.g. `30-90`, the range the JPEG quality
+/// follows the link in. `None` keeps the quality fixed.
+pub fn range_from_env() -> Result<Option<QualityRange>> {
+    match std::env::var("ADAPTIVE_QUALITY") {
+        Ok(range) => Ok(Some(range.parse().context("ADAPTIVE_QUALITY")?)),
+        Err(_) => Ok(None),
+    }
+}
+
+/// How the link looks in one sample.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LinkState {
+    Clean,
+    /// Above a target, or the RTT is growing.
+    Building,
+    /// Far above a target.
+    Congested,
+}
+
+impl LinkState {
+    fn describe(self) -> &'static str {
+        match self {
+            LinkState::Clean => "link clean",
+            LinkState::Building => "congestion building",
+            LinkState::Congested => "congested",
+        }
+    }
+}
+
+/// Judges samples against the targets shared by both controllers.
+struct LinkMonitor {
+    target_buffer: Duration,
+    max_loss: f64,
+    // Lowest RTT seen, the baseline for queueing delay on the path
+    min_rtt: Option<Duration>,
+}
+
+impl LinkMonitor {
+    fn new() -> Self {
+        Self {
+            target_buffer: DEFAULT_TARGET_BUFFER,
+            max_loss: DEFAULT_MAX_LOSS,
+            min_rtt: None,
+        }
+    }
+
+    fn assess(&mut self, stats: &LinkStats) -> LinkState {
+        // Zero until the first acknowledgement came back
+        if !stats.rtt.is_zero() {
+            self.min_rtt = Some(self.min_rtt.map_or(stats.rtt, |min| min.min(stats.rtt)));
+        }
+
+        let buffer = stats.send_buffer.as_secs_f64() / self.target_buffer.as_secs_f64();
+        let loss = stats.loss / self.max_loss;
+        // Queues building up along the path before the send buffer notices,
+        // with some slack for jitter on short paths
+        let rtt_growing = self
+            .min_rtt
+            .is_some_and(|min| stats.rtt > min * 2 + Duration::from_millis(20));
+
+        if buffer > 2.0 || loss > 2.0 {
+            LinkState::Congested
+        } else if buffer > 1.0 || loss > 1.0 || rtt_growing {
+            LinkState::Building
+        } else {
+            LinkState::Clean
+        }
+    }
+}
+
+fn describe_stats(stats: &LinkStats) -> String {
+    format!(
+        "send buffer {} ms, loss {:.1}%, RTT {} ms",
+        stats.send_buffer.as_millis(),
+        stats.loss * 100.0,
+        stats.rtt.as_millis()
+    )
+}
+
+/// Moves the quality of a [`QualityKnob`] with the link statistics.
+pub struct QualityController {
+    knob: QualityKnob,
+    range: QualityRange,
+    monitor: LinkMonitor,
+    clean: u32,
+}
+
+impl QualityController {
+    /// Starts from the knob's current quality, clamped into `range`.
+    pub fn new(knob: QualityKnob, range: QualityRange) -> Self {
+        knob.set(knob.get().clamp(range.min, range.max));
+        Self {
+            knob,
+            range,
+            monitor: LinkMonitor::new(),
+            clean: 0,
+        }
+    }
+
+    /// Send buffer occupancy to stay below, [`DEFAULT_TARGET_BUFFER`] by
+    /// default. Keep it well below the SRT latency.
+    pub fn target_buffer(mut self, target: Duration) -> Self {
+        self.monitor.target_buffer = target;
+        self
+    }
+
+    /// Loss to stay below, [`DEFAULT_MAX_LOSS`] by default.
+    pub fn max_loss(mut self, loss: f64) -> Self {
+        self.monitor.max_loss = loss;
+        self
+    }
+
+    pub fn quality(&self) -> i32 {
+        self.knob.get()
+    }
+
+    /// Adjusts the quality for a new sample, returning it when it changed.
+    pub fn update(&mut self, stats: &LinkStats) -> Option<i32> {
+        let state = self.monitor.assess(stats);
+        let step = match state {
+            LinkState::Congested => -STEP_DOWN_SEVERE,
+            LinkState::Building => -STEP_DOWN,
+            LinkState::Clean => {
+                self.clean += 1;
+                if self.clean < CLEAN_SAMPLES {
+                    return None;
+                }
+                STEP_UP
+            }
+        };
+        self.clean = 0;
+
+        let old = self.knob.get();
+        let new = (old + step).clamp(self.range.min, self.range.max);
+        if new == old {
+            return None;
+        }
+        self.knob.set(new);
+        println!(
+            "JPEG quality {old} -> {new} ({}: {})",
+            state.describe(),
+            describe_stats(stats)
+        );
+        Some(new)
+    }
+}
+
+/// One step of a [`Ladder`], written `WxH@KBPS`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rung {
+    pub width: usize,
+    pub height: usize,
+    pub kbps: u64,
+}
+
+impl fmt::Display for Rung {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{}@{}", self.width, self.height, self.kbps)
+    }
+}
+
+impl FromStr for Rung {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (size, kbps) = s
+            .split_once('@')
+            .with_context(|| format!("rung {s:?} is not WIDTHxHEIGHT@KBPS"))?;
+        let (width, height) = size
+            .split_once('x')
+            .and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)))
+            .with_context(|| format!("rung {s:?} is not WIDTHxHEIGHT@KBPS"))?;
+        let kbps = kbps
+            .parse()
+            .with_context(|| format!("rung {s:?} has no bitrate in kbit/s"))?;
+        // 4:2:0 needs even dimensions
+        if width < 2 || height < 2 || width % 2 != 0 || height % 2 != 0 {
+            bail!("rung {s:?} needs an even width and height");
+        }
+        if kbps == 0 {
+            bail!("rung {s:?} needs a bitrate above zero");
+        }
+        Ok(Self {
+            width,
+            height,
+            kbps,
+        })
+    }
+}
+
+/// Resolution and bitrate steps for an H.264 encoder, best first. Written
+/// as comma separated rungs, e.g. `1280x720@2500,854x480@1200,640x360@600`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ladder(Vec<Rung>);
+
+impl Ladder {
+    /// Orders the rungs by bitrate, highest first.
+    pub fn new(mut rungs: Vec<Rung>) -> Result<Self> {
+        if rungs.is_empty() {
+            bail!("a ladder needs at least one rung");
+        }
+        rungs.sort_by(|a, b| b.kbps.cmp(&a.kbps));
+        Ok(Self(rungs))
+    }
+
+    pub fn rungs(&self) -> &[Rung] {
+        &self.0
+    }
+}
+
+impl fmt::Display for Ladder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, rung) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(",")?;
+            }
+            write!(f, "{rung}")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Ladder {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::new(s.split(',').map(str::parse).collect::<Result<_>>()?)
+    }
+}
+
+/// Reads `H264_LADDER`, e.g. `1280x720@2500,640x360@600`, `None` keeps the
+/// encoder at the source resolution and its default bitrate.
+pub fn ladder_from_env() -> Result<Option<Ladder>> {
+    match std::env::var("H264_LADDER") {
+        Ok(ladder) => Ok(Some(ladder.parse().context("H264_LADDER")?)),
+        Err(_) => Ok(None),
+    }
+}
+
+/// Index of the rung an encoder should be on, shared between the encoder
+/// and its [`LadderController`].
+#[derive(Debug, Clone, Default)]
+pub struct RungKnob(Arc<AtomicUsize>);
+
+impl RungKnob {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self) -> usize {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    pub fn set(&self, rung: usize) {
+        self.0.store(rung, Ordering::Relaxed);
+    }
+}
+
+/// Picks the [`Ladder`] rung of a [`RungKnob`] with the link statistics.
+/// Steps down on sustained trouble, or at once when the link is congested,
+/// and back up one rung at a time after a clean stretch.
+pub struct LadderController {
+    knob: RungKnob,
+    ladder: Ladder,
+    monitor: LinkMonitor,
+    troubled: u32,
+    clean: u32,
+}
+
+impl LadderController {
+    /// Starts on the knob's current rung.
+    pub fn new(knob: RungKnob, ladder: Ladder) -> Self {
+        knob.set(knob.get().min(ladder.0.len() - 1));
+        Self {
+            knob,
+            ladder,
+            monitor: LinkMonitor::new(),
+            troubled: 0,
+            clean: 0,
+        }
+    }
+
+    /// See [`QualityController::target_buffer`].
+    pub fn target_buffer(mut self, target: Duration) -> Self {
+        self.monitor.target_buffer = target;
+        self
+    }
+
+    /// See [`QualityController::max_loss`].
+    pub fn max_loss(mut self, loss: f64) -> Self {
+        self.monitor.max_loss = loss;
+        self
+    }
+
+    pub fn rung(&self) -> usize {
+        self.knob.get()
+    }
+
+    /// Picks the rung for a new sample, returning it when it changed. The
+    /// encoder switches at its next frame.
+    pub fn update(&mut self, stats: &LinkStats) -> Option<usize> {
+        let state = self.monitor.assess(stats);
+        let old = self.knob.get();
+        let new = match state {
+            LinkState::Clean => {
+                self.troubled = 0;
+                self.clean += 1;
+                if self.clean < LADDER_UP_SAMPLES {
+                    return None;
+                }
+                old.saturating_sub(1)
+            }
+            LinkState::Building | LinkState::Congested => {
+                self.clean = 0;
+                self.troubled += 1;
+                if state == LinkState::Building && self.troubled < LADDER_DOWN_SAMPLES {
+                    return None;
+                }
+                (old + 1).min(self.ladder.0.len() - 1)
+            }
+        };
+        self.troubled = 0;
+        self.clean = 0;
+        if new == old {
+            return None;
+        }
+
+        self.knob.set(new);
+        println!(
+            "Ladder rung {} -> {}, {} kbit/s at {}x{} ({}: {})",
+            old + 1,
+            new + 1,
+            self.ladder.0[new].kbps,
+            self.ladder.0[new].width,
+            self.ladder.0[new].height,
+            state.describe(),
+            describe_stats(stats)
+        );
+        Some(new)
+    }
+}
@@ -0,0 +1,350 @@
+//! [`VideoReceiver`] decodes the stream of any sender into frames, with an
+//! optional [`PlayoutBuffer`] pacing them on their sender timestamps.
+//!
+//! ```no_run
+//! # async fn example() -> anyhow::Result<()> {
+//! use rust_srt_playground::receiver::VideoReceiver;
+//! use srt_tokio::SrtSocket;
+//!
+//! let socket = SrtSocket::builder().call("127.0.0.1:1234", None).await?;
+//! let mut receiver = VideoReceiver::detect(socket).await?;
+//! while let Some(frame) = receiver.next_frame().await? {
+//!     println!("frame #{}: {}x{}", frame.number, frame.image.cols(), frame.image.rows());
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Result};
+use bytes::Bytes;
+use futures::{
+    stream::{self, BoxStream, SplitSink},
+    SinkExt, StreamExt, TryStreamExt,
+};
+use opencv::{core::Vector, imgcodecs, prelude::*};
+use srt_tokio::SrtSocket;
+use tokio::{sync::mpsc, time::sleep_until};
+
+use crate::{
+    detect::{self, StreamFormat},
+    framing::Deframer,
+    keyframe::KEYFRAME_REQUEST,
+    mux::Container,
+    playout::PlayoutBuffer,
+    ts_decode::{spawn_decoder, TsInput},
+};
+
+/// How long payloads may arrive without a decoded frame before the
+/// receiver asks for a keyframe, and again after each request.
+const KEYFRAME_RETRY: Duration = Duration::from_secs(1);
+
+pub struct ReceivedFrame {
+    /// Counts decoded frames, starting at 1.
+    pub number: u64,
+    /// Sender timestamp of the frame. For muxed streams this is the
+    /// timestamp of the latest payload handed to the decoder, which trails
+    /// the frame by the decoder delay.
+    pub sent_at: Instant,
+    pub image: Mat,
+    /// The received JPEG, for JPEG based formats.
+    pub jpeg: Option<Bytes>,
+}
+
+enum Decoder {
+    Jpeg,
+    Chunked {
+        deframer: Deframer,
+        // Sender timestamp of the first chunk of every frame not yet complete
+        timestamps: VecDeque<Instant>,
+    },
+    /// MPEG-TS or Matroska, demuxed and decoded by ffmpeg.
+    Demuxed {
+        // `None` once the stream ended, the decoder then drains
+        input: Option<TsInput>,
+        frames: mpsc::Receiver<Result<Mat>>,
+        last_sent: Instant,
+    },
+}
+
+impl Decoder {
+    fn demuxed(container: Container) -> Self {
+        let (input, frames) = spawn_decoder(container);
+        Decoder::Demuxed {
+            input: Some(input),
+            frames,
+            last_sent: Instant::now(),
+        }
+    }
+}
+
+pub struct VideoReceiver {
+    payloads: BoxStream<'static, Result<(Instant, Bytes)>>,
+    format: StreamFormat,
+    decoder: Decoder,
+    keyframes: Option<KeyframeRequester>,
+    playout: Option<PlayoutBuffer<ReceivedFrame>>,
+    ready: VecDeque<ReceivedFrame>,
+    ended: bool,
+    received_bytes: u64,
+    decoded: u64,
+}
+
+impl VideoReceiver {
+    /// Decodes `socket` as `format`.
+    pub fn new(socket: SrtSocket, format: StreamFormat) -> Self {
+        let (requests, payloads) = socket.split();
+        Self::from_payloads(payloads.map_err(Into::into).boxed(), requests, format)
+    }
+
+    /// Reads payloads until the format is known, see [`detect::detect`].
+    /// The probed payloads are decoded like the rest of the stream.
+    pub async fn detect(mut socket: SrtSocket) -> Result<Self> {
+        let mut probed = Vec::new();
+        let format = loop {
+            let Some((sent_at, payload)) = socket.try_next().await? else {
+                bail!("stream closed before its format could be detected");
+            };
+            probed.push((sent_at, payload));
+            let payloads: Vec<Bytes> = probed.iter().map(|(_, p)| p.clone()).collect();
+            if let Some(format) = detect::detect(&payloads) {
+                break format;
+            }
+            if probed.len() >= detect::MAX_PROBE_PAYLOADS {
+                bail!(
+                    "could not detect the stream format from the first {} payloads",
+                    probed.len()
+                );
+            }
+        };
+
+        let (requests, payloads) = socket.split();
+        let payloads = stream::iter(probed.into_iter().map(Ok))
+            .chain(payloads.map_err(Into::into))
+            .boxed();
+        Ok(Self::from_payloads(payloads, requests, format))
+    }
+
+    fn from_payloads(
+        payloads: BoxStream<'static, Result<(Instant, Bytes)>>,
+        requests: SplitSink<SrtSocket, (Instant, Bytes)>,
+        format: StreamFormat,
+    ) -> Self {
+        let decoder = match format {
+            StreamFormat::JpegMessage => Decoder::Jpeg,
+            StreamFormat::ChunkedJpeg => Decoder::Chunked {
+                deframer: Deframer::new(),
+                timestamps: VecDeque::new(),
+            },
+            StreamFormat::MpegTs => Decoder::demuxed(Container::MpegTs),
+            StreamFormat::Matroska => Decoder::demuxed(Container::Matroska),
+        };
+        // Every JPEG is a keyframe
+        let keyframes = matches!(decoder, Decoder::Demuxed { .. }).then(|| KeyframeRequester {
+            requests,
+            last_request: None,
+            last_frame: Instant::now(),
+        });
+
+        Self {
+            payloads,
+            format,
+            decoder,
+            keyframes,
+            playout: None,
+            ready: VecDeque::new(),
+            ended: false,
+            received_bytes: 0,
+            decoded: 0,
+        }
+    }
+
+    /// Holds frames back until their playout deadline instead of returning
+    /// them as soon as they are decoded.
+    pub fn playout(mut self, playout: Option<PlayoutBuffer<ReceivedFrame>>) -> Self {
+        self.playout = playout;
+        self
+    }
+
+    pub fn format(&self) -> StreamFormat {
+        self.format
+    }
+
+    /// Payload bytes received so far.
+    pub fn received_bytes(&self) -> u64 {
+        self.received_bytes
+    }
+
+    pub fn playout_stats(&self) -> Option<&PlayoutBuffer<ReceivedFrame>> {
+        self.playout.as_ref()
+    }
+
+    /// Next frame in display order, `None` once the stream has ended and
+    /// every buffered frame was returned.
+    pub async fn next_frame(&mut self) -> Result<Option<ReceivedFrame>> {
+        loop {
+            let Some(playout) = &mut self.playout else {
+                if let Some(frame) = self.ready.pop_front() {
+                    return Ok(Some(frame));
+                }
+                if self.ended || !self.receive_more().await? {
+                    self.ended = true;
+                    return Ok(None);
+                }
+                continue;
+            };
+
+            for frame in self.ready.drain(..) {
+                let number = frame.number;
+                if !playout.push(frame.sent_at, Instant::now(), frame) {
+                    println!(
+                        "Frame #{} missed its deadline, dropped (delay {:?}, jitter {:?})",
+                        number,
+                        playout.delay(),
+                        playout.jitter()
+                    );
+                }
+            }
+            if let Some(frame) = playout.pop_due(Instant::now()) {
+                return Ok(Some(frame));
+            }
+
+            let deadline = playout.next_deadline();
+            if self.ended {
+                // Play out what is left
+                match deadline {
+                    Some(deadline) => sleep_until(deadline.into()).await,
+                    None => return Ok(None),
+                }
+                continue;
+            }
+
+            tokio::select! {
+                more = self.receive_more() => self.ended = !more?,
+                _ = sleep_until(deadline.unwrap_or_else(Instant::now).into()), if deadline.is_some() => {}
+            }
+        }
+    }
+
+    /// Receives and decodes until there is progress, decoded frames go to
+    /// `ready`. Returns `false` at the end of the stream.
+    async fn receive_more(&mut self) -> Result<bool> {
+        if let Decoder::Demuxed {
+            input,
+            frames,
+            last_sent,
+        } = &mut self.decoder
+        {
+            let frame = loop {
+                tokio::select! {
+                    frame = frames.recv() => break frame,
+                    payload = self.payloads.try_next(), if input.is_some() => match payload? {
+                        Some((sent_at, payload)) => {
+                            self.received_bytes += payload.len() as u64;
+                            *last_sent = sent_at;
+                            // A stopped decoder reports why on `frames`
+                            if input.as_ref().is_some_and(|i| i.push(payload).is_err()) {
+                                *input = None;
+                            }
+                            if let Some(keyframes) = &mut self.keyframes {
+                                keyframes.payload_received().await;
+                            }
+                        }
+                        None => *input = None,
+                    },
+                }
+            };
+            let sent_at = *last_sent;
+            return match frame {
+                Some(image) => {
+                    self.accept(sent_at, image?, None);
+                    Ok(true)
+                }
+                None => Ok(false),
+            };
+        }
+
+        let Some((sent_at, payload)) = self.payloads.try_next().await? else {
+            return Ok(false);
+        };
+        self.received_bytes += payload.len() as u64;
+
+        let mut complete = Vec::new();
+        match &mut self.decoder {
+            Decoder::Chunked {
+                deframer,
+                timestamps,
+            } => {
+                // Frames always start on a chunk boundary
+                if deframer.buffered() == 0 {
+                    timestamps.push_back(sent_at);
+                }
+                deframer.push(&payload);
+                while let Some(jpeg) = deframer.next_frame()? {
+                    let frame_sent = timestamps.pop_front().unwrap_or(sent_at);
+                    complete.push((frame_sent, Bytes::from(jpeg)));
+                }
+            }
+            _ => complete.push((sent_at, payload)),
+        }
+
+        for (sent_at, jpeg) in complete {
+            match decode_jpeg(&jpeg)? {
+                Some(image) => self.accept(sent_at, image, Some(jpeg)),
+                None => println!("Frame of {} bytes failed to decode, skipping", jpeg.len()),
+            }
+        }
+        Ok(true)
+    }
+
+    fn accept(&mut self, sent_at: Instant, image: Mat, jpeg: Option<Bytes>) {
+        if let Some(keyframes) = &mut self.keyframes {
+            keyframes.last_frame = Instant::now();
+        }
+        self.decoded += 1;
+        self.ready.push_back(ReceivedFrame {
+            number: self.decoded,
+            sent_at,
+            image,
+            jpeg,
+        });
+    }
+}
+
+/// Asks the sender for a keyframe while payloads arrive but no frame
+/// decodes, e.g. after joining mid-GOP or losing a reference frame. See
+/// [`crate::keyframe`], senders without keyframe control ignore requests.
+struct KeyframeRequester {
+    requests: SplitSink<SrtSocket, (Instant, Bytes)>,
+    last_request: Option<Instant>,
+    last_frame: Instant,
+}
+
+impl KeyframeRequester {
+    async fn payload_received(&mut self) {
+        let now = Instant::now();
+        let quiet_since = self
+            .last_request
+            .map_or(self.last_frame, |request| request.max(self.last_frame));
+        if now.duration_since(quiet_since) < KEYFRAME_RETRY {
+            return;
+        }
+        self.last_request = Some(now);
+        println!("No frame decoded for {KEYFRAME_RETRY:?}, asking the sender for a keyframe");
+        // Losing a request is harmless, the next one follows
+        let request = Bytes::from_static(KEYFRAME_REQUEST);
+        if let Err(error) = self.requests.send((now, request)).await {
+            println!("Keyframe request failed: {error}");
+        }
+    }
+}
+
+fn decode_jpeg(jpeg: &[u8]) -> Result<Option<Mat>> {
+    let buf = Vector::from_slice(jpeg);
+    let frame = imgcodecs::imdecode(&buf, imgcodecs::IMREAD_COLOR)?;
+    Ok((!frame.empty()).then_some(frame))
+}
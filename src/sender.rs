@@ -0,0 +1,344 @@
+//! [`VideoSender`] captures frames from a [`FrameSource`], encodes them in
+//! one of the wire formats of the `v1`..`v5` binaries and sends them over a
+//! connected SRT socket.
+//!
+//! ```no_run
+//! # async fn example() -> anyhow::Result<()> {
+//! use rust_srt_playground::{
+//!     sender::{Mode, VideoSender},
+//!     source::SyntheticSource,
+//! };
+//! use srt_tokio::SrtSocket;
+//!
+//! let socket = SrtSocket::builder().listen_on(":1234").await?;
+//! let frames = VideoSender::new(socket, Mode::TsH264)
+//!     .frame_limit(Some(300))
+//!     .run(Box::new(SyntheticSource::new(640, 480, 30.0)))
+//!     .await?;
+//! println!("sent {frames} frames");
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{fmt, str::FromStr};
+
+use anyhow::{bail, Result};
+use srt_tokio::SrtSocket;
+
+use crate::{
+    audio::AudioSettings,
+    detect::StreamFormat,
+    encode::{EncoderOption, FrameBudget, VideoCodec, VideoSettings},
+    filter::TimecodeFilter,
+    framing::CHUNK_LEN,
+    mux::Container,
+    pipeline::{
+        self, CaptureSource, EncoderSpec, FilterSpec, PacketizerSpec, Pipeline, StreamInfo,
+        Transport,
+    },
+    rate::{Ladder, QualityRange},
+    source::FrameSource,
+    transport::SrtTransport,
+};
+
+/// Wire format of the stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// One JPEG per SRT message (v1, v5).
+    JpegMessage,
+    /// Length-prefixed JPEG split over several messages (v4).
+    JpegChunked,
+    /// MJPEG in an MPEG transport stream (v2).
+    TsMjpeg,
+    /// H.264 in an MPEG transport stream (v3).
+    TsH264,
+    /// HEVC in an MPEG transport stream (v3).
+    TsHevc,
+    /// VP9 in Matroska (v3).
+    MkvVp9,
+    /// AV1 in Matroska (v3).
+    MkvAv1,
+}
+
+impl Mode {
+    pub const ALL: [Mode; 7] = [
+        Mode::JpegMessage,
+        Mode::JpegChunked,
+        Mode::TsMjpeg,
+        Mode::TsH264,
+        Mode::TsHevc,
+        Mode::MkvVp9,
+        Mode::MkvAv1,
+    ];
+
+    /// The mode carrying `codec`, in the container it goes with.
+    pub fn for_codec(codec: VideoCodec) -> Self {
+        match codec {
+            VideoCodec::H264 => Mode::TsH264,
+            VideoCodec::Hevc => Mode::TsHevc,
+            VideoCodec::Vp9 => Mode::MkvVp9,
+            VideoCodec::Av1 => Mode::MkvAv1,
+        }
+    }
+
+    /// The codec of the encoded modes, `None` for the JPEG based ones.
+    pub fn codec(self) -> Option<VideoCodec> {
+        match self {
+            Mode::JpegMessage | Mode::JpegChunked | Mode::TsMjpeg => None,
+            Mode::TsH264 => Some(VideoCodec::H264),
+            Mode::TsHevc => Some(VideoCodec::Hevc),
+            Mode::MkvVp9 => Some(VideoCodec::Vp9),
+            Mode::MkvAv1 => Some(VideoCodec::Av1),
+        }
+    }
+
+    /// The encoder and packetizer implementing this mode. `quality` and
+    /// `budget` only matter for the JPEG based modes, `video` only for the
+    /// encoded ones and `chunk_len` only for [`Mode::JpegChunked`].
+    pub fn stages(
+        self,
+        quality: Option<i32>,
+        budget: Option<FrameBudget>,
+        video: VideoSettings,
+        chunk_len: usize,
+    ) -> (EncoderSpec, PacketizerSpec) {
+        if let Some(codec) = self.codec() {
+            let packetizer = match codec.container() {
+                Container::MpegTs => PacketizerSpec::MpegTs(None),
+                Container::Matroska => PacketizerSpec::Matroska,
+            };
+            let settings = video;
+            return (EncoderSpec::Video { codec, settings }, packetizer);
+        }
+        match self {
+            Mode::JpegMessage => (
+                EncoderSpec::Jpeg { quality, budget },
+                PacketizerSpec::Message,
+            ),
+            Mode::JpegChunked => (
+                EncoderSpec::Jpeg { quality, budget },
+                PacketizerSpec::Chunked(chunk_len),
+            ),
+            Mode::TsMjpeg => (
+                EncoderSpec::Mjpeg { quality, budget },
+                PacketizerSpec::MpegTs(None),
+            ),
+            _ => unreachable!("{self} has a codec"),
+        }
+    }
+
+    /// What a [`crate::receiver::VideoReceiver`] has to decode.
+    pub fn format(self) -> StreamFormat {
+        match self {
+            Mode::JpegMessage => StreamFormat::JpegMessage,
+            Mode::JpegChunked => StreamFormat::ChunkedJpeg,
+            Mode::TsMjpeg | Mode::TsH264 | Mode::TsHevc => StreamFormat::MpegTs,
+            Mode::MkvVp9 | Mode::MkvAv1 => StreamFormat::Matroska,
+        }
+    }
+
+    /// Whether a receiver can start on any message, as it has to when it
+    /// joins a running stream. Chunked JPEG frames span several messages
+    /// and Matroska describes its tracks only at the start.
+    pub fn is_joinable(self) -> bool {
+        !matches!(self, Mode::JpegChunked | Mode::MkvVp9 | Mode::MkvAv1)
+    }
+
+    /// Whether the JPEG quality and budget settings apply.
+    pub fn is_jpeg(self) -> bool {
+        self.codec().is_none()
+    }
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Mode::JpegMessage => "jpeg-message",
+            Mode::JpegChunked => "jpeg-chunked",
+            Mode::TsMjpeg => "ts-mjpeg",
+            Mode::TsH264 => "ts-h264",
+            Mode::TsHevc => "ts-hevc",
+            Mode::MkvVp9 => "mkv-vp9",
+            Mode::MkvAv1 => "mkv-av1",
+        })
+    }
+}
+
+impl FromStr for Mode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match Mode::ALL.into_iter().find(|mode| mode.to_string() == s) {
+            Some(mode) => Ok(mode),
+            None => bail!(
+                "unknown mode {s:?}, expected jpeg-message, jpeg-chunked, ts-mjpeg, ts-h264, \
+                 ts-hevc, mkv-vp9 or mkv-av1"
+            ),
+        }
+    }
+}
+
+pub struct VideoSender {
+    transport: Box<dyn Transport>,
+    mode: Mode,
+    jpeg_quality: Option<i32>,
+    adaptive_quality: Option<QualityRange>,
+    frame_budget: Option<FrameBudget>,
+    video: VideoSettings,
+    audio: Option<AudioSettings>,
+    filters: Vec<FilterSpec>,
+    chunk_len: usize,
+    burn_timecode: bool,
+    frame_limit: Option<u64>,
+}
+
+impl VideoSender {
+    /// Wraps a connected socket, either side of the connection may have
+    /// been the listener.
+    pub fn new(socket: SrtSocket, mode: Mode) -> Self {
+        Self::with_transport(Box::new(SrtTransport::new(socket)), mode)
+    }
+
+    /// Sends over any transport, e.g. a [`crate::transport::SrtFanOut`] for
+    /// receivers that join while the stream runs.
+    pub fn with_transport(transport: Box<dyn Transport>, mode: Mode) -> Self {
+        Self {
+            transport,
+            mode,
+            jpeg_quality: None,
+            adaptive_quality: None,
+            frame_budget: None,
+            video: VideoSettings::default(),
+            audio: None,
+            filters: Vec::new(),
+            chunk_len: CHUNK_LEN,
+            burn_timecode: false,
+            frame_limit: None,
+        }
+    }
+
+    /// JPEG quality for the JPEG based modes, the OpenCV default otherwise.
+    pub fn jpeg_quality(mut self, quality: i32) -> Self {
+        self.jpeg_quality = Some(quality);
+        self
+    }
+
+    /// Lets the JPEG quality follow the link within `range`, starting from
+    /// [`Self::jpeg_quality`]. Every change is logged, see [`crate::rate`].
+    pub fn adaptive_quality(mut self, range: Option<QualityRange>) -> Self {
+        self.adaptive_quality = range;
+        self
+    }
+
+    /// Caps every JPEG at a byte count or a share of a bitrate: each frame
+    /// gets the highest quality that fits, up to [`Self::jpeg_quality`],
+    /// and is downscaled if even the lowest does not. See
+    /// [`crate::encode::JpegEncoder::max_frame_bytes`].
+    pub fn frame_budget(mut self, budget: Option<FrameBudget>) -> Self {
+        self.frame_budget = budget;
+        self
+    }
+
+    /// Lets ts-h264 and ts-hevc switch between the resolutions and bitrates
+    /// of `ladder` with the link, starting on the best rung. Every switch is
+    /// logged and lands on a keyframe.
+    pub fn ladder(mut self, ladder: Option<Ladder>) -> Self {
+        self.video.ladder = ladder;
+        self
+    }
+
+    /// Everything about the encoder of the encoded modes at once, including
+    /// the latency tuning. Replaces what [`Self::ladder`] and
+    /// [`Self::encoder_options`] set.
+    pub fn video_settings(mut self, settings: VideoSettings) -> Self {
+        self.video = settings;
+        self
+    }
+
+    /// Options for the ffmpeg encoder of the encoded modes, such as
+    /// `preset=veryfast` for libx264. They override the defaults.
+    pub fn encoder_options(mut self, options: Vec<EncoderOption>) -> Self {
+        self.video.options = options;
+        self
+    }
+
+    /// Adds an audio track to the MPEG-TS modes, a test tone or a WAV file
+    /// kept in sync with the video, see [`crate::audio`].
+    pub fn audio(mut self, audio: Option<AudioSettings>) -> Self {
+        self.audio = audio;
+        self
+    }
+
+    /// Preprocesses frames before any encoder sees them, in the given order:
+    /// crop, resize, grayscale or a lower frame rate to cut the bitrate,
+    /// see [`crate::filter`]. The timecode is burned in after them.
+    pub fn filters(mut self, filters: Vec<FilterSpec>) -> Self {
+        self.filters = filters;
+        self
+    }
+
+    /// Largest SRT message in [`Mode::JpegChunked`], [`CHUNK_LEN`] by default.
+    pub fn chunk_len(mut self, len: usize) -> Self {
+        self.chunk_len = len;
+        self
+    }
+
+    /// Burns the capture time into every frame, see [`crate::timecode`].
+    pub fn burn_timecode(mut self, burn: bool) -> Self {
+        self.burn_timecode = burn;
+        self
+    }
+
+    /// Stops after `limit` frames, `None` streams until the source runs dry.
+    pub fn frame_limit(mut self, limit: Option<u64>) -> Self {
+        self.frame_limit = limit;
+        self
+    }
+
+    /// Streams until the source ends or the frame limit is reached, then
+    /// closes the socket. Returns the number of frames sent.
+    ///
+    /// This is a fixed [`Pipeline`] per mode, build one directly for
+    /// anything else.
+    pub async fn run(self, source: Box<dyn FrameSource>) -> Result<u64> {
+        if self.adaptive_quality.is_some() && !self.mode.is_jpeg() {
+            bail!("adaptive quality only applies to the JPEG based modes");
+        }
+        if self.frame_budget.is_some() && !self.mode.is_jpeg() {
+            bail!("a frame budget only applies to the JPEG based modes");
+        }
+        if self.mode.is_jpeg() && self.video != VideoSettings::default() {
+            bail!("video encoder settings only apply to the encoded modes");
+        }
+        let (filters, info) = pipeline::build_filters(&self.filters, StreamInfo::of(&*source))?;
+        let (encoder, mut packetizer) = self.mode.stages(
+            self.jpeg_quality,
+            self.frame_budget,
+            self.video,
+            self.chunk_len,
+        );
+        if let Some(audio) = self.audio {
+            match &mut packetizer {
+                PacketizerSpec::MpegTs(track) => *track = Some(audio),
+                _ => bail!("audio only applies to the MPEG-TS modes, not {}", self.mode),
+            }
+        }
+
+        let mut pipeline = Pipeline::new(
+            Box::new(CaptureSource::new(source)),
+            encoder.build(info)?,
+            packetizer.build()?,
+            self.transport,
+        )
+        .frame_limit(self.frame_limit)
+        .adaptive_quality(self.adaptive_quality);
+        for filter in filters {
+            pipeline = pipeline.filter(filter);
+        }
+        if self.burn_timecode {
+            pipeline = pipeline.filter(Box::new(TimecodeFilter));
+        }
+
+        Ok(pipeline.run().await?.frames)
+    }
+}
@@ -0,0 +1,240 @@
+//! Frame sources for the senders: the default camera, a video file or a
+//! synthetic test pattern, so senders can run without capture hardware.
+//!
+//! Selected from the environment:
+//! - `SOURCE`: `camera` (default), `synthetic` or `file:PATH`
+//! - `SYNTHETIC_SIZE`: pattern size, default `640x480`
+//! - `SYNTHETIC_FPS`: pattern frame rate, default 30
+//! - `FRAME_LIMIT`: frames to send before the sender closes, unlimited by default
+
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context, Result};
+use opencv::{
+    core::{Mat, Point, Rect, Scalar, CV_8UC3},
+    imgproc,
+    prelude::*,
+    videoio::{self, VideoCapture},
+};
+
+pub trait FrameSource: Send {
+    /// Reads the next frame like `VideoCapture::read`, blocking until it is
+    /// available. An empty `frame` means nothing was captured.
+    fn read(&mut self, frame: &mut Mat) -> Result<bool>;
+
+    fn width(&self) -> usize;
+
+    fn height(&self) -> usize;
+
+    fn fps(&self) -> f64;
+}
+
+pub struct CameraSource {
+    cam: VideoCapture,
+}
+
+impl CameraSource {
+    pub fn open(index: i32) -> Result<Self> {
+        let cam = VideoCapture::new(index, videoio::CAP_ANY)?;
+        if !cam.is_opened()? {
+            bail!("cannot open camera {index}");
+        }
+        Ok(Self { cam })
+    }
+}
+
+impl FrameSource for CameraSource {
+    fn read(&mut self, frame: &mut Mat) -> Result<bool> {
+        Ok(self.cam.read(frame)?)
+    }
+
+    fn width(&self) -> usize {
+        self.cam.get(videoio::CAP_PROP_FRAME_WIDTH).unwrap_or(0.) as usize
+    }
+
+    fn height(&self) -> usize {
+        self.cam.get(videoio::CAP_PROP_FRAME_HEIGHT).unwrap_or(0.) as usize
+    }
+
+    fn fps(&self) -> f64 {
+        self.cam.get(videoio::CAP_PROP_FPS).unwrap_or(0.)
+    }
+}
+
+/// Frames of a video file, paced at the file's frame rate like a camera.
+pub struct FileSource {
+    cap: VideoCapture,
+    ticker: Ticker,
+}
+
+impl FileSource {
+    pub fn open(path: &str) -> Result<Self> {
+        let cap = VideoCapture::from_file(path, videoio::CAP_ANY)?;
+        if !cap.is_opened()? {
+            bail!("cannot open video file {path}");
+        }
+        let fps = cap.get(videoio::CAP_PROP_FPS).unwrap_or(0.);
+        // Some containers don't declare a rate
+        let fps = if fps > 0. { fps } else { 30. };
+        Ok(Self {
+            cap,
+            ticker: Ticker::new(fps),
+        })
+    }
+}
+
+impl FrameSource for FileSource {
+    fn read(&mut self, frame: &mut Mat) -> Result<bool> {
+        self.ticker.wait();
+        Ok(self.cap.read(frame)?)
+    }
+
+    fn width(&self) -> usize {
+        self.cap.get(videoio::CAP_PROP_FRAME_WIDTH).unwrap_or(0.) as usize
+    }
+
+    fn height(&self) -> usize {
+        self.cap.get(videoio::CAP_PROP_FRAME_HEIGHT).unwrap_or(0.) as usize
+    }
+
+    fn fps(&self) -> f64 {
+        self.ticker.fps
+    }
+}
+
+/// Paces reads at a fixed rate.
+struct Ticker {
+    fps: f64,
+    next_frame: Option<Instant>,
+}
+
+impl Ticker {
+    fn new(fps: f64) -> Self {
+        Self {
+            fps,
+            next_frame: None,
+        }
+    }
+
+    fn wait(&mut self) {
+        let interval = Duration::from_secs_f64(1.0 / self.fps);
+        let now = Instant::now();
+        let due = *self.next_frame.get_or_insert(now);
+        if due > now {
+            std::thread::sleep(due - now);
+        }
+        // Don't try to catch up after a stall, just like a camera wouldn't
+        self.next_frame = Some(due.max(now) + interval);
+    }
+}
+
+/// Moving bar over a slowly cycling background with the frame number drawn
+/// in, paced at `fps` like a real camera.
+pub struct SyntheticSource {
+    width: usize,
+    height: usize,
+    frame_index: u64,
+    ticker: Ticker,
+}
+
+impl SyntheticSource {
+    pub fn new(width: usize, height: usize, fps: f64) -> Self {
+        Self {
+            width,
+            height,
+            frame_index: 0,
+            ticker: Ticker::new(fps),
+        }
+    }
+
+    fn draw(&self) -> Result<Mat> {
+        let i = self.frame_index as f64;
+        let background = Scalar::new(
+            64. + 48. * (i / 50.).sin(),
+            64. + 48. * (i / 70.).sin(),
+            64. + 48. * (i / 90.).sin(),
+            0.,
+        );
+        let mut frame = Mat::new_rows_cols_with_default(
+            self.height as i32,
+            self.width as i32,
+            CV_8UC3,
+            background,
+        )?;
+
+        let bar_width = (self.width / 16).max(1) as i32;
+        let x = ((self.frame_index * 4) % self.width as u64) as i32;
+        imgproc::rectangle(
+            &mut frame,
+            Rect::new(x, 0, bar_width, self.height as i32),
+            Scalar::new(240., 240., 240., 0.),
+            imgproc::FILLED,
+            imgproc::LINE_8,
+            0,
+        )?;
+        imgproc::put_text(
+            &mut frame,
+            &format!("frame {}", self.frame_index),
+            Point::new(16, self.height as i32 - 16),
+            imgproc::FONT_HERSHEY_SIMPLEX,
+            1.0,
+            Scalar::new(255., 255., 255., 0.),
+            2,
+            imgproc::LINE_AA,
+            false,
+        )?;
+        Ok(frame)
+    }
+}
+
+impl FrameSource for SyntheticSource {
+    fn read(&mut self, frame: &mut Mat) -> Result<bool> {
+        self.ticker.wait();
+        *frame = self.draw()?;
+        self.frame_index += 1;
+        Ok(true)
+    }
+
+    fn width(&self) -> usize {
+        self.width
+    }
+
+    fn height(&self) -> usize {
+        self.height
+    }
+
+    fn fps(&self) -> f64 {
+        self.ticker.fps
+    }
+}
+
+/// Reads `FRAME_LIMIT`, `None` means stream until the source runs dry.
+pub fn frame_limit_from_env() -> Result<Option<u64>> {
+    match std::env::var("FRAME_LIMIT") {
+        Ok(limit) => Ok(Some(limit.parse().context("FRAME_LIMIT must be a number")?)),
+        Err(_) => Ok(None),
+    }
+}
+
+/// Opens the source selected by `SOURCE`.
+pub fn open_from_env() -> Result<Box<dyn FrameSource>> {
+    match std::env::var("SOURCE").as_deref() {
+        Err(_) | Ok("camera") => Ok(Box::new(CameraSource::open(0)?)),
+        Ok("synthetic") => {
+            let size = std::env::var("SYNTHETIC_SIZE").unwrap_or_else(|_| "640x480".into());
+            let (width, height) = size
+                .split_once('x')
+                .and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)))
+                .with_context(|| format!("SYNTHETIC_SIZE {size:?} is not WIDTHxHEIGHT"))?;
+            let fps = match std::env::var("SYNTHETIC_FPS") {
+                Ok(fps) => fps.parse().context("SYNTHETIC_FPS must be a number")?,
+                Err(_) => 30.0,
+            };
+            Ok(Box::new(SyntheticSource::new(width, height, fps)))
+        }
+        Ok(other) => match other.strip_prefix("file:") {
+            Some(path) => Ok(Box::new(FileSource::open(path)?)),
+            None => bail!("unknown SOURCE {other:?}, expected camera, synthetic or file:PATH"),
+        },
+    }
+}
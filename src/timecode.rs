@@ -0,0 +1,183 @@
+//! Wall-clock timecode burned into the picture, for glass-to-glass latency.
+//!
+//! The sender draws the capture time as a grid of black/white blocks in the
+//! top-left corner of the frame; the receiver reads it back from the decoded
+//! `Mat` and compares against its own clock. Both ends must share a clock
+//! (same host, or NTP/PTP synced). Blocks are large enough to survive JPEG and
+//! H.264 compression at the qualities the senders use.
+//!
+//! Layout: `COLUMNS` x `ROWS` blocks of `BLOCK` pixels inside a black quiet
+//! zone, carrying 64 bits of microseconds since the UNIX epoch followed by a
+//! 16 bit Adler style checksum, most significant bit first.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+use opencv::{
+    core::{self, Mat, Rect, Scalar},
+    imgproc,
+    prelude::*,
+};
+
+const BLOCK: i32 = 12;
+const COLUMNS: i32 = 8;
+const ROWS: i32 = 10;
+const MARGIN: i32 = BLOCK / 2;
+const BITS: usize = (COLUMNS * ROWS) as usize;
+
+/// Pixel size of the stamped area, frames must be at least this large.
+pub const AREA: (i32, i32) = (COLUMNS * BLOCK + 2 * MARGIN, ROWS * BLOCK + 2 * MARGIN);
+
+// Seeded with 1 so all-black and all-white areas never pass as a timecode.
+fn checksum(value: u64) -> u16 {
+    let (mut a, mut b) = (1u16, 0u16);
+    for byte in value.to_be_bytes() {
+        a = (a + u16::from(byte)) % 255;
+        b = (b + a) % 255;
+    }
+    (b << 8) | a
+}
+
+fn block_rect(bit: usize) -> Rect {
+    let bit = bit as i32;
+    Rect::new(
+        MARGIN + (bit % COLUMNS) * BLOCK,
+        MARGIN + (bit / COLUMNS) * BLOCK,
+        BLOCK,
+        BLOCK,
+    )
+}
+
+fn fits(frame: &Mat) -> bool {
+    frame.cols() >= AREA.0 && frame.rows() >= AREA.1
+}
+
+/// Draws `at` into `frame`. Frames too small for the pattern are left alone.
+pub fn stamp(frame: &mut Mat, at: SystemTime) -> Result<()> {
+    if !fits(frame) {
+        return Ok(());
+    }
+    let micros = at.duration_since(UNIX_EPOCH)?.as_micros() as u64;
+    let payload = (u128::from(micros) << 16) | u128::from(checksum(micros));
+
+    imgproc::rectangle(
+        frame,
+        Rect::new(0, 0, AREA.0, AREA.1),
+        Scalar::all(0.),
+        imgproc::FILLED,
+        imgproc::LINE_8,
+        0,
+    )?;
+    for bit in 0..BITS {
+        if (payload >> (BITS - 1 - bit)) & 1 == 1 {
+            imgproc::rectangle(
+                frame,
+                block_rect(bit),
+                Scalar::all(255.),
+                imgproc::FILLED,
+                imgproc::LINE_8,
+                0,
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Reads the timecode back, `None` if there is none or it is damaged.
+pub fn read(frame: &Mat) -> Result<Option<SystemTime>> {
+    if !fits(frame) {
+        return Ok(None);
+    }
+
+    let mut payload = 0u128;
+    for bit in 0..BITS {
+        // Only sample the block center, edges get smeared by the codec
+        let rect = block_rect(bit);
+        let inner = Rect::new(rect.x + BLOCK / 4, rect.y + BLOCK / 4, BLOCK / 2, BLOCK / 2);
+        let block = frame.roi(inner)?;
+        let mean = core::mean(&block, &core::no_array())?;
+        let channels = frame.channels().clamp(1, 4) as usize;
+        let level = (0..channels).map(|c| mean[c]).sum::<f64>() / channels as f64;
+        payload = (payload << 1) | u128::from(level >= 128.);
+    }
+
+    let micros = (payload >> 16) as u64;
+    if checksum(micros) != payload as u16 {
+        return Ok(None);
+    }
+    Ok(Some(UNIX_EPOCH + Duration::from_micros(micros)))
+}
+
+/// A timecode read back from a frame.
+#[derive(Debug, Clone, Copy)]
+pub struct Reading {
+    pub stamped: SystemTime,
+    /// Time since the frame was stamped, by our clock.
+    pub latency: Duration,
+}
+
+impl Reading {
+    /// The stamped time in microseconds since the UNIX epoch, handy as a
+    /// sender side sequence number.
+    pub fn micros(&self) -> u128 {
+        self.stamped
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_micros()
+    }
+}
+
+pub fn latency(frame: &Mat) -> Result<Option<Reading>> {
+    Ok(read(frame)?.map(|stamped| Reading {
+        stamped,
+        latency: SystemTime::now()
+            .duration_since(stamped)
+            .unwrap_or_default(),
+    }))
+}
+
+/// Collects per-frame latencies for an end of session summary.
+#[derive(Default)]
+pub struct LatencyStats {
+    samples: Vec<Duration>,
+    missing: u64,
+}
+
+impl LatencyStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Measures `frame`, records the result and returns it.
+    pub fn measure(&mut self, frame: &Mat) -> Result<Option<Reading>> {
+        let reading = latency(frame)?;
+        match reading {
+            Some(reading) => self.samples.push(reading.latency),
+            None => self.missing += 1,
+        }
+        Ok(reading)
+    }
+
+    pub fn percentile(&self, p: f64) -> Option<Duration> {
+        let mut sorted = self.samples.clone();
+        sorted.sort_unstable();
+        let index = ((sorted.len() as f64 - 1.0) * p / 100.0).round() as usize;
+        sorted.get(index).copied()
+    }
+
+    pub fn summary(&self) -> String {
+        if self.samples.is_empty() {
+            return format!("no timecode found in {} frames", self.missing);
+        }
+        let ms = |d: Option<Duration>| d.unwrap_or_default().as_secs_f64() * 1000.0;
+        format!(
+            "{} frames, latency min {:.1} ms, p50 {:.1} ms, p95 {:.1} ms, max {:.1} ms ({} frames without timecode)",
+            self.samples.len(),
+            ms(self.percentile(0.0)),
+            ms(self.percentile(50.0)),
+            ms(self.percentile(95.0)),
+            ms(self.percentile(100.0)),
+            self.missing
+        )
+    }
+}
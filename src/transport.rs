@@ -0,0 +1,606 @@
+//! SRT transports for the sender [`crate::pipeline`], to one receiver or
+//! to every caller of a listener, and [`Connector`] for setting up the
+//! socket on either side.
+
+use std::{
+    collections::VecDeque,
+    fmt, io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use futures::{
+    future::{join_all, BoxFuture},
+    FutureExt, SinkExt, StreamExt,
+};
+use srt_tokio::{options::SocketStatistics, SrtIncoming, SrtListener, SrtSocket, SrtSocketBuilder};
+use tokio::{sync::mpsc, task::JoinHandle};
+
+use crate::{
+    keyframe::KEYFRAME_REQUEST,
+    pipeline::{Message, Transport},
+    rate::LinkStats,
+};
+
+/// Which side sets up the SRT connection. Independent of the direction the
+/// video flows in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    /// Connects to a listener.
+    Caller,
+    /// Waits for a caller.
+    Listener,
+    /// Both peers call each other, e.g. to get through NATs.
+    Rendezvous,
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Role::Caller => "caller",
+            Role::Listener => "listener",
+            Role::Rendezvous => "rendezvous",
+        })
+    }
+}
+
+impl FromStr for Role {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "caller" => Ok(Role::Caller),
+            "listener" => Ok(Role::Listener),
+            "rendezvous" => Ok(Role::Rendezvous),
+            _ => bail!("unknown SRT role {s:?}, expected caller, listener or rendezvous"),
+        }
+    }
+}
+
+/// How long callers and rendezvous peers wait for the handshake by default.
+/// Listeners wait for as long as it takes.
+pub const DEFAULT_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(30);
+
+// Progress is printed this often while a handshake is pending.
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(5);
+
+// Bytes of an unexpected receiver message shown in the log.
+const UNEXPECTED_PREVIEW: usize = 32;
+
+/// Sets up an SRT connection in one of the [`Role`]s, with a progress line
+/// while waiting and an explanation when the handshake fails.
+///
+/// Addresses are `HOST:PORT` with a host name, an IPv4 address or an IPv6
+/// address in brackets, e.g. `[2001:db8::1]:4200`. A listener may also
+/// leave out the host (`:PORT` or `PORT`) to listen on all IPv4 interfaces;
+/// `[::]:PORT` listens on IPv6.
+///
+/// ```no_run
+/// # async fn example() -> anyhow::Result<()> {
+/// use rust_srt_playground::transport::{Connector, Role};
+///
+/// // Both sites run this with the other one's address
+/// let socket = Connector::new(Role::Rendezvous, "203.0.113.7:4200")
+///     .local_port(4200)
+///     .connect()
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Connector {
+    role: Role,
+    addr: String,
+    latency: Option<Duration>,
+    bind: Option<IpAddr>,
+    local_port: Option<u16>,
+    timeout: Option<Duration>,
+}
+
+impl Connector {
+    /// `addr` is the local address for a listener and the peer otherwise.
+    pub fn new(role: Role, addr: &str) -> Self {
+        Self {
+            role,
+            addr: addr.into(),
+            latency: None,
+            bind: None,
+            local_port: None,
+            timeout: match role {
+                Role::Listener => None,
+                Role::Caller | Role::Rendezvous => Some(DEFAULT_HANDSHAKE_TIMEOUT),
+            },
+        }
+    }
+
+    /// Overrides the SRT default latency.
+    pub fn latency(mut self, latency: Option<Duration>) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    /// Local interface to use on hosts with several. A listener listens on
+    /// this address with the port of its own address.
+    pub fn bind(mut self, ip: IpAddr) -> Self {
+        self.bind = Some(ip);
+        self
+    }
+
+    /// Local UDP port of a caller, picked by the OS by default, or of a
+    /// rendezvous peer, the peer's port by default as both sides usually
+    /// use the same one.
+    pub fn local_port(mut self, port: u16) -> Self {
+        self.local_port = Some(port);
+        self
+    }
+
+    /// Gives up when the handshake takes longer, `None` waits forever.
+    pub fn timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub async fn connect(self) -> Result<SrtSocket> {
+        let mut builder = SrtSocket::builder();
+        if let Some(latency) = self.latency {
+            builder = builder.latency(latency);
+        }
+
+        let addr = self.addr.as_str();
+        let started = tokio::time::Instant::now();
+        let failed = |error: io::Error| match error.kind() {
+            // srt-tokio may give up before our own deadline
+            io::ErrorKind::TimedOut => self.timed_out(started.elapsed()),
+            _ => self.diagnose(error),
+        };
+
+        let handshake = async {
+            match self.role {
+                Role::Listener => {
+                    if self.local_port.is_some() {
+                        bail!("a listener's port is part of its address");
+                    }
+                    let mut local = listen_addr(addr).await?;
+                    if let Some(ip) = self.bind {
+                        local.set_ip(ip);
+                    }
+                    println!("Waiting for an SRT caller on {local}...");
+                    builder
+                        .listen_on(local.to_string().as_str())
+                        .await
+                        .map_err(failed)
+                }
+                Role::Caller => {
+                    let remote = self.remote().await?;
+                    let builder = self.local_side(builder, remote, self.local_port);
+                    println!("Connecting to {addr}{}...", resolved_note(addr, remote));
+                    builder
+                        .call(remote.to_string().as_str(), None)
+                        .await
+                        .map_err(failed)
+                }
+                Role::Rendezvous => {
+                    let remote = self.remote().await?;
+                    let port = self.local_port.unwrap_or(remote.port());
+                    let builder = self.local_side(builder, remote, Some(port));
+                    println!(
+                        "Rendezvous with {addr}{} from local port {port}...",
+                        resolved_note(addr, remote)
+                    );
+                    builder
+                        .rendezvous(remote.to_string().as_str())
+                        .await
+                        .map_err(failed)
+                }
+            }
+        };
+        tokio::pin!(handshake);
+
+        let mut progress = tokio::time::interval(PROGRESS_INTERVAL);
+        progress.tick().await;
+        let deadline = async {
+            match self.timeout {
+                Some(timeout) => tokio::time::sleep(timeout).await,
+                None => std::future::pending().await,
+            }
+        };
+        tokio::pin!(deadline);
+
+        let socket = loop {
+            tokio::select! {
+                result = &mut handshake => break result?,
+                _ = &mut deadline => return Err(self.timed_out(started.elapsed())),
+                _ = progress.tick(), if self.role != Role::Listener => println!(
+                    "Still waiting for the SRT handshake ({}s, {})",
+                    started.elapsed().as_secs(),
+                    self.hint()
+                ),
+            }
+        };
+
+        match self.role {
+            Role::Listener => println!("Connection established with {}", socket.settings().remote),
+            Role::Caller | Role::Rendezvous => println!("Connected to {addr}"),
+        }
+        Ok(socket)
+    }
+
+    /// Listens for any number of callers instead of one and waits for the
+    /// first, see [`SrtFanOut`].
+    pub async fn fan_out(self) -> Result<SrtFanOut> {
+        if self.role != Role::Listener {
+            bail!("only a listener can send to several callers");
+        }
+        let mut local = listen_addr(&self.addr).await?;
+        if let Some(ip) = self.bind {
+            local.set_ip(ip);
+        }
+        let mut builder = SrtListener::builder();
+        if let Some(latency) = self.latency {
+            builder = builder.latency(latency);
+        }
+        let (listener, incoming) = builder
+            .bind(local)
+            .await
+            .map_err(|error| self.diagnose(error))?;
+        println!("Waiting for SRT callers on {local}...");
+        SrtFanOut::start(listener, incoming).await
+    }
+
+    /// Reads the local end from the environment: `SRT_ADDR` replaces
+    /// `addr`, `SRT_BIND` sets the local interface and `SRT_LOCAL_PORT` the
+    /// local port of a caller or rendezvous peer. Every sender and receiver
+    /// binary sets up its socket this way, so they all take these variables.
+    pub fn from_env(role: Role, addr: &str) -> Result<Self> {
+        let addr = std::env::var("SRT_ADDR").unwrap_or_else(|_| addr.into());
+        let mut connector = Self::new(role, &addr);
+        if let Ok(ip) = std::env::var("SRT_BIND") {
+            connector = connector.bind(ip.parse().context("SRT_BIND must be an IP address")?);
+        }
+        if let Ok(port) = std::env::var("SRT_LOCAL_PORT") {
+            connector =
+                connector.local_port(port.parse().context("SRT_LOCAL_PORT must be a port")?);
+        }
+        Ok(connector)
+    }
+
+    /// The peer's address, of the bind address' family if there is one.
+    async fn remote(&self) -> Result<SocketAddr> {
+        let candidates: Vec<SocketAddr> = tokio::net::lookup_host(&self.addr)
+            .await
+            .with_context(|| format!("cannot resolve {:?}, expected HOST:PORT", self.addr))?
+            .collect();
+        let Some(first) = candidates.first() else {
+            bail!("{} did not resolve to any address", self.addr);
+        };
+        match self.bind {
+            None => Ok(*first),
+            Some(bind) => candidates
+                .iter()
+                .find(|remote| remote.is_ipv6() == bind.is_ipv6())
+                .copied()
+                .with_context(|| {
+                    let family = if bind.is_ipv6() { "IPv6" } else { "IPv4" };
+                    format!("{} has no {family} address to reach from {bind}", self.addr)
+                }),
+        }
+    }
+
+    /// Binds the local end, an IPv6 peer needs an IPv6 socket.
+    fn local_side(
+        &self,
+        builder: SrtSocketBuilder,
+        remote: SocketAddr,
+        port: Option<u16>,
+    ) -> SrtSocketBuilder {
+        let ip = self.bind.unwrap_or(match remote {
+            SocketAddr::V4(_) => Ipv4Addr::UNSPECIFIED.into(),
+            SocketAddr::V6(_) => Ipv6Addr::UNSPECIFIED.into(),
+        });
+        let builder = builder.local_ip(ip);
+        match port {
+            Some(port) => builder.local_port(port),
+            None => builder,
+        }
+    }
+
+    /// What usually keeps this role from connecting.
+    fn hint(&self) -> String {
+        match self.role {
+            Role::Listener => "is the caller pointed at this address?".into(),
+            Role::Caller => format!("is a listener running at {}?", self.addr),
+            Role::Rendezvous => format!(
+                "the peer has to run in rendezvous mode towards local port {} and UDP has to \
+                 pass both ways",
+                self.local_port
+                    .or_else(|| peer_port(&self.addr))
+                    .unwrap_or(0)
+            ),
+        }
+    }
+
+    fn timed_out(&self, waited: Duration) -> anyhow::Error {
+        let waited = Duration::from_secs(waited.as_secs());
+        match self.role {
+            Role::Rendezvous => anyhow!(
+                "no rendezvous handshake with {} within {waited:?}. Both sides have to use \
+                 the rendezvous role, each calling the other's public address and port, \
+                 and start within the timeout. The local port ({}) must be the port the \
+                 peer calls; behind NAT it has to be forwarded or kept stable by the NAT, and \
+                 firewalls on both sides must let UDP through on these ports",
+                self.addr,
+                self.local_port
+                    .or_else(|| peer_port(&self.addr))
+                    .unwrap_or(0)
+            ),
+            _ => anyhow!(
+                "no SRT handshake with {} within {waited:?}, {}",
+                self.addr,
+                self.hint()
+            ),
+        }
+    }
+
+    fn diagnose(&self, error: io::Error) -> anyhow::Error {
+        let context = match (error.kind(), self.role) {
+            (io::ErrorKind::AddrInUse, Role::Rendezvous) => format!(
+                "local port {} is already in use, pick another one and have the peer call it",
+                self.local_port
+                    .or_else(|| peer_port(&self.addr))
+                    .unwrap_or(0)
+            ),
+            (io::ErrorKind::AddrInUse, _) => format!("{} is already in use", self.addr),
+            (io::ErrorKind::AddrNotAvailable, _) => format!(
+                "cannot bind {}, is it an address of this host?",
+                self.bind
+                    .map_or_else(|| self.addr.clone(), |ip| ip.to_string())
+            ),
+            (io::ErrorKind::ConnectionRefused, _) => format!(
+                "{} rejected the connection, check its role and stream settings",
+                self.addr
+            ),
+            _ => format!("SRT {} setup with {} failed", self.role, self.addr),
+        };
+        anyhow::Error::new(error).context(context)
+    }
+}
+
+fn peer_port(addr: &str) -> Option<u16> {
+    addr.rsplit_once(':')?.1.parse().ok()
+}
+
+/// Listen address: `PORT` and `:PORT` listen on all IPv4 interfaces,
+/// anything else is resolved.
+async fn listen_addr(addr: &str) -> Result<SocketAddr> {
+    if let Ok(port) = addr.strip_prefix(':').unwrap_or(addr).parse::<u16>() {
+        return Ok((Ipv4Addr::UNSPECIFIED, port).into());
+    }
+    tokio::net::lookup_host(addr)
+        .await
+        .with_context(|| format!("cannot resolve {addr:?}, expected [HOST]:PORT"))?
+        .next()
+        .with_context(|| format!("{addr} did not resolve to any address"))
+}
+
+/// ` (ADDRESS)` when `addr` had to be resolved.
+fn resolved_note(addr: &str, resolved: SocketAddr) -> String {
+    if addr == resolved.to_string() {
+        String::new()
+    } else {
+        format!(" ({resolved})")
+    }
+}
+
+/// Shorthand for a [`Connector`] with the default timeout.
+pub async fn connect(role: Role, addr: &str, latency: Option<Duration>) -> Result<SrtSocket> {
+    Connector::new(role, addr).latency(latency).connect().await
+}
+
+pub struct SrtTransport {
+    socket: SrtSocket,
+}
+
+impl SrtTransport {
+    /// Wraps a connected socket.
+    pub fn new(socket: SrtSocket) -> Self {
+        Self { socket }
+    }
+
+    /// Waits for a caller on `addr`.
+    pub async fn listen(addr: &str) -> Result<Self> {
+        Ok(Self::new(connect(Role::Listener, addr, None).await?))
+    }
+
+    /// Connects to a listener at `addr`.
+    pub async fn call(addr: &str) -> Result<Self> {
+        Ok(Self::new(connect(Role::Caller, addr, None).await?))
+    }
+}
+
+impl Transport for SrtTransport {
+    fn send(&mut self, message: Message) -> BoxFuture<'_, Result<()>> {
+        async move {
+            self.socket.send((Instant::now(), message.data)).await?;
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn close(&mut self) -> BoxFuture<'_, Result<()>> {
+        async move {
+            self.socket.close_and_finish().await?;
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn stats(&mut self) -> Option<LinkStats> {
+        latest_stats(&mut self.socket)
+    }
+
+    fn keyframe_request(&mut self) -> Option<String> {
+        keyframe_requested(&mut self.socket).then(|| "the receiver asked for one".into())
+    }
+}
+
+/// Sends to every caller of a listener, for receivers that come and go
+/// while the stream runs. Callers joining late start mid-GOP, so each one
+/// asks for a keyframe, see [`crate::keyframe`].
+///
+/// A caller that fails is dropped and the stream goes on, even without
+/// callers. Every caller gets every message, so the slowest one sets the
+/// pace for all. Set one up with [`Connector::fan_out`].
+pub struct SrtFanOut {
+    callers: Vec<(SocketAddr, SrtSocket)>,
+    joined: mpsc::UnboundedReceiver<(SocketAddr, SrtSocket)>,
+    accept: JoinHandle<()>,
+    requests: VecDeque<String>,
+}
+
+impl SrtFanOut {
+    /// Accepts callers in the background and waits for the first one.
+    async fn start(listener: SrtListener, mut incoming: SrtIncoming) -> Result<Self> {
+        let (joined_send, joined) = mpsc::unbounded_channel();
+        let accept = tokio::spawn(async move {
+            // Dropping the listener stops listening
+            let _listener = listener;
+            while let Some(request) = incoming.incoming().next().await {
+                let addr = request.remote();
+                match request.accept(None).await {
+                    Ok(socket) => {
+                        if joined_send.send((addr, socket)).is_err() {
+                            break;
+                        }
+                    }
+                    Err(error) => println!("Caller {addr} could not connect: {error}"),
+                }
+            }
+        });
+
+        let mut fan_out = Self {
+            callers: Vec::new(),
+            joined,
+            accept,
+            requests: VecDeque::new(),
+        };
+        let first = fan_out
+            .joined
+            .recv()
+            .await
+            .context("the SRT listener stopped")?;
+        println!("Connection established with {}", first.0);
+        fan_out.callers.push(first);
+        Ok(fan_out)
+    }
+
+    /// Adds the callers that joined since the last call.
+    fn admit(&mut self) {
+        while let Ok((addr, socket)) = self.joined.try_recv() {
+            println!("Caller {addr} joined, {} connected", self.callers.len() + 1);
+            self.requests.push_back(format!("caller {addr} joined"));
+            self.callers.push((addr, socket));
+        }
+    }
+}
+
+impl Transport for SrtFanOut {
+    fn send(&mut self, message: Message) -> BoxFuture<'_, Result<()>> {
+        async move {
+            self.admit();
+            let now = Instant::now();
+            let sends = self
+                .callers
+                .iter_mut()
+                .map(|(_, socket)| socket.send((now, message.data.clone())));
+            let mut results = join_all(sends).await.into_iter();
+            self.callers.retain(|(addr, _)| match results.next() {
+                Some(Err(error)) => {
+                    println!("Caller {addr} left: {error}");
+                    false
+                }
+                _ => true,
+            });
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn close(&mut self) -> BoxFuture<'_, Result<()>> {
+        async move {
+            self.accept.abort();
+            for (_, socket) in &mut self.callers {
+                socket.close_and_finish().await?;
+            }
+            Ok(())
+        }
+        .boxed()
+    }
+
+    /// Statistics of the caller with the fullest send buffer, the one
+    /// holding everybody back.
+    fn stats(&mut self) -> Option<LinkStats> {
+        self.callers
+            .iter_mut()
+            .filter_map(|(_, socket)| latest_stats(socket))
+            .max_by_key(|stats| stats.send_buffer)
+    }
+
+    fn keyframe_request(&mut self) -> Option<String> {
+        self.admit();
+        for (addr, socket) in &mut self.callers {
+            if keyframe_requested(socket) {
+                self.requests
+                    .push_back(format!("receiver {addr} asked for one"));
+            }
+        }
+        self.requests.pop_front()
+    }
+}
+
+impl Drop for SrtFanOut {
+    fn drop(&mut self) {
+        self.accept.abort();
+    }
+}
+
+/// The socket reports once per statistics interval, keep the latest
+/// without waiting for the next.
+fn latest_stats(socket: &mut SrtSocket) -> Option<LinkStats> {
+    let mut latest = None;
+    while let Some(Some(stats)) = socket.statistics().next().now_or_never() {
+        latest = Some(stats);
+    }
+    latest.as_ref().map(link_stats)
+}
+
+/// Whether the receiver sent a [`KEYFRAME_REQUEST`] since the last call.
+/// Nothing else flows from receivers to senders, anything else is logged
+/// as a sign of a receiver speaking another protocol.
+fn keyframe_requested(socket: &mut SrtSocket) -> bool {
+    let mut requested = false;
+    while let Some(Some(Ok((_, data)))) = socket.next().now_or_never() {
+        if data.as_ref() == KEYFRAME_REQUEST {
+            requested = true;
+        } else {
+            println!(
+                "Ignoring an unexpected {} byte message from receiver {}: \"{}\"",
+                data.len(),
+                socket.settings().remote,
+                data[..data.len().min(UNEXPECTED_PREVIEW)].escape_ascii()
+            );
+        }
+    }
+    requested
+}
+
+/// Sender side of one statistics interval.
+fn link_stats(stats: &SocketStatistics) -> LinkStats {
+    let sent = stats.tx_unique_data + stats.tx_retransmit_data;
+    LinkStats {
+        send_buffer: stats.tx_buffered_time,
+        loss: stats.tx_loss_data as f64 / sent.max(1) as f64,
+        rtt: stats.rtt,
+    }
+}
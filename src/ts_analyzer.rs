@@ -0,0 +1,406 @@
+//! MPEG-TS health checks on the raw SRT payloads of the v2/v3 streams.
+//!
+//! Payloads are not required to be aligned on 188 byte packets (v3's
+//! `WriteBridge` cuts the muxer output into 1316 byte chunks wherever the
+//! muxer's writes happen to end), so packets are reassembled first. The
+//! analyzer then tracks sync losses, continuity counters per PID, PAT/PMT
+//! contents, PCR interval and jitter and keyframes.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    time::{Duration, Instant},
+};
+
+use crate::detect::{find_ts_sync, TS_PACKET_LEN, TS_SYNC_BYTE};
+
+const PAT_PID: u16 = 0x0000;
+const NULL_PID: u16 = 0x1FFF;
+
+const PCR_HZ: f64 = 27_000_000.0;
+// PCR base is 33 bits at 90 kHz, extended by a 9 bit 27 MHz remainder.
+const PCR_WRAP: u64 = (1 << 33) * 300;
+// A PCR step larger than this is treated as a discontinuity, not an interval.
+const PCR_MAX_STEP: Duration = Duration::from_secs(1);
+
+#[derive(Default)]
+struct PidStats {
+    packets: u64,
+    cc_errors: u64,
+    last_cc: Option<u8>,
+    scrambled: u64,
+    keyframes: u64,
+}
+
+#[derive(Default)]
+struct Program {
+    pmt_pid: u16,
+    pcr_pid: Option<u16>,
+    streams: Vec<(u16, u8)>,
+}
+
+#[derive(Default)]
+struct PcrStats {
+    last: Option<(u64, Instant)>,
+    samples: u64,
+    discontinuities: u64,
+    interval_min: Option<Duration>,
+    interval_max: Duration,
+    interval_sum: Duration,
+    jitter_max: Duration,
+    jitter_sum: Duration,
+}
+
+#[derive(Default)]
+pub struct TsAnalyzer {
+    pending: Vec<u8>,
+    bytes: u64,
+    packets: u64,
+    // Cleared when sync is lost and set again on the next aligned packet, so
+    // payloads arriving while searching for sync don't count again
+    in_sync: bool,
+    sync_losses: u64,
+    skipped_bytes: u64,
+    transport_errors: u64,
+    pids: BTreeMap<u16, PidStats>,
+    // PMT PID -> program number, learned from the PAT
+    pmt_pids: HashMap<u16, u16>,
+    programs: BTreeMap<u16, Program>,
+    // Partially received PSI sections, keyed by PID
+    sections: HashMap<u16, Vec<u8>>,
+    pcr: PcrStats,
+}
+
+impl TsAnalyzer {
+    pub fn new() -> Self {
+        Self {
+            in_sync: true,
+            ..Self::default()
+        }
+    }
+
+    /// Feeds one SRT payload, `arrival` is used for PCR jitter.
+    pub fn push(&mut self, payload: &[u8], arrival: Instant) {
+        self.bytes += payload.len() as u64;
+        self.pending.extend_from_slice(payload);
+
+        let mut offset = 0;
+        while self.pending.len() - offset >= TS_PACKET_LEN {
+            if self.pending[offset] != TS_SYNC_BYTE {
+                if self.in_sync {
+                    self.in_sync = false;
+                    self.sync_losses += 1;
+                }
+                match find_ts_sync(&self.pending[offset..]) {
+                    Some(skip) => {
+                        self.skipped_bytes += skip as u64;
+                        offset += skip;
+                    }
+                    None => {
+                        // No packet starts within the next packet length. Skip
+                        // it, but keep a tail the next payload may complete
+                        // the pattern with
+                        let remaining = self.pending.len() - offset;
+                        let keep = (TS_PACKET_LEN * 3).min(remaining);
+                        let skip = (remaining - keep).min(TS_PACKET_LEN);
+                        self.skipped_bytes += skip as u64;
+                        offset += skip;
+                        if skip < TS_PACKET_LEN {
+                            break;
+                        }
+                    }
+                }
+                continue;
+            }
+
+            self.in_sync = true;
+            let mut packet = [0u8; TS_PACKET_LEN];
+            packet.copy_from_slice(&self.pending[offset..offset + TS_PACKET_LEN]);
+            self.packet(&packet, arrival);
+            offset += TS_PACKET_LEN;
+        }
+        self.pending.drain(..offset);
+    }
+
+    fn packet(&mut self, p: &[u8; TS_PACKET_LEN], arrival: Instant) {
+        self.packets += 1;
+
+        let transport_error = p[1] & 0x80 != 0;
+        let unit_start = p[1] & 0x40 != 0;
+        let pid = (u16::from(p[1] & 0x1F) << 8) | u16::from(p[2]);
+        let scrambling = p[3] >> 6;
+        let has_adaptation = p[3] & 0x20 != 0;
+        let has_payload = p[3] & 0x10 != 0;
+        let cc = p[3] & 0x0F;
+
+        if transport_error {
+            self.transport_errors += 1;
+        }
+
+        let mut discontinuity = false;
+        let mut random_access = false;
+        let mut payload_start = 4;
+        if has_adaptation {
+            let len = p[4] as usize;
+            payload_start = 5 + len;
+            if len > 0 && payload_start <= TS_PACKET_LEN {
+                let flags = p[5];
+                discontinuity = flags & 0x80 != 0;
+                random_access = flags & 0x40 != 0;
+                if flags & 0x10 != 0 && len >= 7 {
+                    let base = (u64::from(p[6]) << 25)
+                        | (u64::from(p[7]) << 17)
+                        | (u64::from(p[8]) << 9)
+                        | (u64::from(p[9]) << 1)
+                        | (u64::from(p[10]) >> 7);
+                    let ext = (u64::from(p[10] & 0x01) << 8) | u64::from(p[11]);
+                    self.pcr(base * 300 + ext, arrival, discontinuity);
+                }
+            }
+        }
+
+        let stream_type = self.stream_type(pid);
+        let stats = self.pids.entry(pid).or_default();
+        stats.packets += 1;
+        if scrambling != 0 {
+            stats.scrambled += 1;
+        }
+
+        if pid != NULL_PID && has_payload {
+            if let Some(last) = stats.last_cc {
+                // A single repeated counter is a legal duplicate packet
+                if cc != last && cc != (last + 1) & 0x0F && !discontinuity {
+                    stats.cc_errors += 1;
+                }
+            }
+            stats.last_cc = Some(cc);
+        }
+
+        if !has_payload || payload_start >= TS_PACKET_LEN {
+            return;
+        }
+        let payload = &p[payload_start..];
+
+        if unit_start && (random_access || stream_type.is_some_and(|t| contains_idr(t, payload))) {
+            stats.keyframes += 1;
+        }
+
+        if pid == PAT_PID || self.pmt_pids.contains_key(&pid) {
+            self.psi(pid, unit_start, payload);
+        }
+    }
+
+    fn pcr(&mut self, pcr: u64, arrival: Instant, discontinuity: bool) {
+        let stats = &mut self.pcr;
+        stats.samples += 1;
+
+        if let Some((last_pcr, last_arrival)) = stats.last {
+            let step = (pcr + PCR_WRAP - last_pcr) % PCR_WRAP;
+            let interval = Duration::from_secs_f64(step as f64 / PCR_HZ);
+            if discontinuity || interval > PCR_MAX_STEP {
+                stats.discontinuities += 1;
+            } else {
+                let elapsed = arrival.saturating_duration_since(last_arrival);
+                let jitter = elapsed.abs_diff(interval);
+                stats.interval_min = Some(stats.interval_min.map_or(interval, |m| m.min(interval)));
+                stats.interval_max = stats.interval_max.max(interval);
+                stats.interval_sum += interval;
+                stats.jitter_max = stats.jitter_max.max(jitter);
+                stats.jitter_sum += jitter;
+            }
+        }
+        stats.last = Some((pcr, arrival));
+    }
+
+    fn psi(&mut self, pid: u16, unit_start: bool, payload: &[u8]) {
+        if unit_start {
+            let pointer = payload[0] as usize;
+            let Some(section) = payload.get(1 + pointer..) else {
+                return;
+            };
+            self.sections.insert(pid, section.to_vec());
+        } else if let Some(buffer) = self.sections.get_mut(&pid) {
+            buffer.extend_from_slice(payload);
+        } else {
+            return;
+        }
+
+        let buffer = &self.sections[&pid];
+        if buffer.len() < 3 {
+            return;
+        }
+        let total = 3 + ((usize::from(buffer[1] & 0x0F) << 8) | usize::from(buffer[2]));
+        if buffer.len() < total {
+            return;
+        }
+
+        let section = self.sections.remove(&pid).unwrap_or_default();
+        // Strip the 4 byte CRC, it is not verified here
+        let Some(section) = section.get(..total.saturating_sub(4)) else {
+            return;
+        };
+        match section.first() {
+            Some(0x00) if pid == PAT_PID => self.pat(section),
+            Some(0x02) => self.pmt(pid, section),
+            _ => {}
+        }
+    }
+
+    fn pat(&mut self, section: &[u8]) {
+        for entry in section.get(8..).unwrap_or_default().chunks_exact(4) {
+            let program = u16::from_be_bytes([entry[0], entry[1]]);
+            let pid = u16::from_be_bytes([entry[2] & 0x1F, entry[3]]);
+            // Program 0 points at the network information table
+            if program != 0 {
+                self.pmt_pids.insert(pid, program);
+                self.programs.entry(program).or_default().pmt_pid = pid;
+            }
+        }
+    }
+
+    fn pmt(&mut self, pid: u16, section: &[u8]) {
+        if section.len() < 12 {
+            return;
+        }
+        let Some(&number) = self.pmt_pids.get(&pid) else {
+            return;
+        };
+        let program = self.programs.entry(number).or_default();
+        program.pcr_pid = Some(u16::from_be_bytes([section[8] & 0x1F, section[9]]));
+        program.streams.clear();
+
+        let info_len = (usize::from(section[10] & 0x0F) << 8) | usize::from(section[11]);
+        let mut es = section.get(12 + info_len..).unwrap_or_default();
+        while es.len() >= 5 {
+            let stream_type = es[0];
+            let es_pid = u16::from_be_bytes([es[1] & 0x1F, es[2]]);
+            let es_info_len = (usize::from(es[3] & 0x0F) << 8) | usize::from(es[4]);
+            program.streams.push((es_pid, stream_type));
+            es = es.get(5 + es_info_len..).unwrap_or_default();
+        }
+    }
+
+    fn stream_type(&self, pid: u16) -> Option<u8> {
+        self.programs
+            .values()
+            .flat_map(|p| p.streams.iter())
+            .find(|(es_pid, _)| *es_pid == pid)
+            .map(|(_, stream_type)| *stream_type)
+    }
+
+    pub fn cc_errors(&self) -> u64 {
+        self.pids.values().map(|s| s.cc_errors).sum()
+    }
+
+    pub fn keyframes(&self) -> u64 {
+        self.pids.values().map(|s| s.keyframes).sum()
+    }
+
+    pub fn sync_losses(&self) -> u64 {
+        self.sync_losses
+    }
+}
+
+/// Looks for an IDR/IRAP NAL unit in the first TS packet of a PES.
+fn contains_idr(stream_type: u8, payload: &[u8]) -> bool {
+    // Skip the PES header: start code, stream id, length, flags, header length
+    if payload.len() < 9 || payload[..3] != [0, 0, 1] {
+        return false;
+    }
+    let es = payload.get(9 + payload[8] as usize..).unwrap_or_default();
+
+    es.windows(4).any(|w| {
+        w[..3] == [0, 0, 1]
+            && match stream_type {
+                // H.264
+                0x1B => w[3] & 0x1F == 5,
+                // HEVC, BLA/IDR/CRA
+                0x24 => (16..=21).contains(&((w[3] >> 1) & 0x3F)),
+                _ => false,
+            }
+    })
+}
+
+fn stream_type_name(stream_type: u8) -> &'static str {
+    match stream_type {
+        0x02 => "MPEG-2 video",
+        0x03 | 0x04 => "MPEG audio",
+        0x06 => "private data",
+        0x0F => "AAC",
+        0x1B => "H.264",
+        0x24 => "HEVC",
+        _ => "unknown",
+    }
+}
+
+fn ms(d: Duration) -> f64 {
+    d.as_secs_f64() * 1000.0
+}
+
+impl fmt::Display for TsAnalyzer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "TS report: {} bytes, {} packets, {} sync losses ({} bytes skipped), {} transport errors",
+            self.bytes, self.packets, self.sync_losses, self.skipped_bytes, self.transport_errors
+        )?;
+
+        if self.programs.is_empty() {
+            writeln!(f, "  no PAT seen yet")?;
+        }
+        for (number, program) in &self.programs {
+            write!(f, "  program {number}: PMT PID 0x{:04x}", program.pmt_pid)?;
+            match program.pcr_pid {
+                Some(pcr_pid) => writeln!(f, ", PCR PID 0x{pcr_pid:04x}")?,
+                None => writeln!(f, ", no PMT seen yet")?,
+            }
+            for (pid, stream_type) in &program.streams {
+                writeln!(
+                    f,
+                    "    PID 0x{pid:04x}: stream type 0x{stream_type:02x} ({})",
+                    stream_type_name(*stream_type)
+                )?;
+            }
+        }
+
+        for (pid, stats) in &self.pids {
+            write!(
+                f,
+                "  PID 0x{pid:04x}: {} packets, {} CC errors",
+                stats.packets, stats.cc_errors
+            )?;
+            if stats.scrambled > 0 {
+                write!(f, ", {} scrambled", stats.scrambled)?;
+            }
+            if stats.keyframes > 0 {
+                write!(f, ", {} keyframes", stats.keyframes)?;
+            }
+            writeln!(f)?;
+        }
+
+        let pcr = &self.pcr;
+        let intervals = pcr.samples.saturating_sub(1 + pcr.discontinuities) as u32;
+        if intervals == 0 {
+            writeln!(f, "  PCR: {} samples", pcr.samples)?;
+        } else {
+            writeln!(
+                f,
+                "  PCR: {} samples, interval avg {:.1} ms (min {:.1}, max {:.1}), jitter avg {:.1} ms (max {:.1}), {} discontinuities",
+                pcr.samples,
+                ms(pcr.interval_sum / intervals),
+                ms(pcr.interval_min.unwrap_or_default()),
+                ms(pcr.interval_max),
+                ms(pcr.jitter_sum / intervals),
+                ms(pcr.jitter_max),
+                pcr.discontinuities
+            )?;
+        }
+
+        write!(
+            f,
+            "  totals: {} CC errors, {} keyframes",
+            self.cc_errors(),
+            self.keyframes()
+        )
+    }
+}
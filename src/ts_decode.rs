@@ -0,0 +1,275 @@
+//! Decodes a muxed byte stream, MPEG-TS or Matroska, into BGR `Mat`s with
+//! ffmpeg, whichever codec it carries. An audio track is not decoded, but
+//! reported along with how far it runs ahead of or behind the video.
+//!
+//! Demuxing and decoding are blocking, so they run on a dedicated thread fed
+//! through a channel. Dropping the [`TsInput`] ends the stream.
+
+use std::{
+    io::{self, Read},
+    sync::mpsc,
+    thread,
+    time::Duration,
+};
+
+use ac_ffmpeg::{
+    codec::{
+        video::{
+            frame::{get_pixel_format, PixelFormat},
+            scaler::VideoFrameScaler,
+            VideoDecoder, VideoFrame,
+        },
+        Decoder,
+    },
+    format::{
+        demuxer::{Demuxer, InputFormat},
+        io::IO,
+    },
+    time::{TimeBase, Timestamp},
+};
+use anyhow::{anyhow, Context, Result};
+use bytes::Bytes;
+use opencv::{
+    core::{Mat, Scalar, CV_8UC3},
+    prelude::*,
+};
+
+use crate::mux::Container;
+
+/// Stream time between two A/V offset reports.
+const AV_REPORT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Feeds SRT payloads into the decoder thread.
+pub struct TsInput(mpsc::Sender<Bytes>);
+
+impl TsInput {
+    pub fn push(&self, payload: Bytes) -> Result<()> {
+        self.0
+            .send(payload)
+            .map_err(|_| anyhow!("decoder thread has stopped"))
+    }
+}
+
+/// Blocking reader over payloads from a channel, ends when the sender is
+/// dropped.
+pub(crate) struct ChannelReader {
+    payloads: mpsc::Receiver<Bytes>,
+    current: Bytes,
+}
+
+impl ChannelReader {
+    pub(crate) fn new(payloads: mpsc::Receiver<Bytes>) -> Self {
+        Self {
+            payloads,
+            current: Bytes::new(),
+        }
+    }
+}
+
+impl Read for ChannelReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.current.is_empty() {
+            match self.payloads.recv() {
+                Ok(payload) => self.current = payload,
+                // Input dropped, report end of stream to the demuxer
+                Err(_) => return Ok(0),
+            }
+        }
+        let len = buf.len().min(self.current.len());
+        buf[..len].copy_from_slice(&self.current.split_to(len));
+        Ok(len)
+    }
+}
+
+/// Starts the decoder thread for a `container` stream. Decoded frames, or
+/// the error that stopped the decoder, arrive on the returned receiver.
+pub fn spawn_decoder(container: Container) -> (TsInput, tokio::sync::mpsc::Receiver<Result<Mat>>) {
+    let (input_send, input_recv) = mpsc::channel();
+    let (frame_send, frame_recv) = tokio::sync::mpsc::channel(16);
+
+    thread::spawn(move || {
+        let reader = ChannelReader::new(input_recv);
+        if let Err(e) = decode_loop(container, reader, &frame_send) {
+            let _ = frame_send.blocking_send(Err(e));
+        }
+    });
+
+    (TsInput(input_send), frame_recv)
+}
+
+fn decode_loop(
+    container: Container,
+    reader: ChannelReader,
+    frames: &tokio::sync::mpsc::Sender<Result<Mat>>,
+) -> Result<()> {
+    let name = container.format_name();
+    let format =
+        InputFormat::find_by_name(name).with_context(|| format!("ffmpeg has no {name} demuxer"))?;
+    let mut demuxer = Demuxer::builder()
+        .input_format(Some(format))
+        .build(IO::from_read_stream(reader))?
+        .find_stream_info(None)
+        .map_err(|(_, err)| err)?;
+
+    let (video_index, stream) = demuxer
+        .streams()
+        .iter()
+        .enumerate()
+        .find(|(_, s)| s.codec_parameters().is_video_codec())
+        .with_context(|| format!("no video stream in the {container} stream"))?;
+    let params = stream.codec_parameters();
+    println!(
+        "Decoding {} from {container}",
+        params.decoder_name().unwrap_or("an unknown codec")
+    );
+    let mut decoder = VideoDecoder::from_stream(stream)?.build()?;
+
+    let audio_index = demuxer
+        .streams()
+        .iter()
+        .position(|s| s.codec_parameters().is_audio_codec());
+    if let Some(index) = audio_index {
+        let params = demuxer.streams()[index].codec_parameters();
+        if let Some(audio) = params.as_audio_codec_parameters() {
+            println!(
+                "Audio: {}, {} Hz, {} channels",
+                params.decoder_name().unwrap_or("an unknown codec"),
+                audio.sample_rate(),
+                audio.channel_layout().channels()
+            );
+        }
+    }
+    let mut av_offset = AvOffset::default();
+
+    let mut converter: Option<BgrConverter> = None;
+
+    while let Some(packet) = demuxer.take()? {
+        if Some(packet.stream_index()) == audio_index {
+            av_offset.audio(packet.pts());
+            continue;
+        }
+        if packet.stream_index() != video_index {
+            continue;
+        }
+        if audio_index.is_some() {
+            av_offset.video(packet.pts());
+        }
+        decoder.push(packet)?;
+        while let Some(frame) = decoder.take()? {
+            let converter = match &mut converter {
+                Some(c) if c.fits(&frame) => c,
+                _ => converter.insert(BgrConverter::new(&frame)?),
+            };
+            if frames.blocking_send(converter.convert(&frame)).is_err() {
+                // Nobody is listening anymore
+                return Ok(());
+            }
+        }
+    }
+
+    decoder.flush()?;
+    while let Some(frame) = decoder.take()? {
+        let converter = match &mut converter {
+            Some(c) if c.fits(&frame) => c,
+            _ => converter.insert(BgrConverter::new(&frame)?),
+        };
+        let _ = frames.blocking_send(converter.convert(&frame));
+    }
+
+    Ok(())
+}
+
+/// Compares the timestamps of the latest audio and video packets as they
+/// come out of the demuxer. A muxer interleaves by timestamp, so the offset
+/// is small for a stream in sync and grows when one track drifts.
+#[derive(Default)]
+struct AvOffset {
+    // Latest audio timestamp and next report, in microseconds
+    audio: Option<i64>,
+    next_report: Option<i64>,
+}
+
+impl AvOffset {
+    fn audio(&mut self, pts: Timestamp) {
+        if !pts.is_null() {
+            self.audio = Some(micros(pts));
+        }
+    }
+
+    fn video(&mut self, pts: Timestamp) {
+        let (Some(audio), false) = (self.audio, pts.is_null()) else {
+            return;
+        };
+        let video = micros(pts);
+        if self.next_report.is_some_and(|next| video < next) {
+            return;
+        }
+        self.next_report = Some(video + AV_REPORT_INTERVAL.as_micros() as i64);
+        let offset = (audio - video) as f64 / 1000.;
+        println!(
+            "A/V offset at {:.1}s: audio {} video by {:.1}ms",
+            video as f64 / 1e6,
+            if offset < 0. { "behind" } else { "ahead of" },
+            offset.abs()
+        );
+    }
+}
+
+fn micros(pts: Timestamp) -> i64 {
+    pts.with_time_base(TimeBase::MICROSECONDS).timestamp()
+}
+
+/// Converts decoder output to packed BGR, the layout OpenCV expects.
+pub struct BgrConverter {
+    scaler: VideoFrameScaler,
+    width: usize,
+    height: usize,
+    pixel_format: PixelFormat,
+}
+
+impl BgrConverter {
+    /// Handles frames of the size and format of `sample`.
+    pub fn new(sample: &VideoFrame) -> Result<Self> {
+        let scaler = VideoFrameScaler::builder()
+            .source_pixel_format(sample.pixel_format())
+            .source_width(sample.width())
+            .source_height(sample.height())
+            .target_pixel_format(get_pixel_format("bgr24"))
+            .target_width(sample.width())
+            .target_height(sample.height())
+            .build()?;
+        Ok(Self {
+            scaler,
+            width: sample.width(),
+            height: sample.height(),
+            pixel_format: sample.pixel_format(),
+        })
+    }
+
+    /// Whether `frame` has the size and format this converter was made for.
+    /// Senders switching resolution, e.g. along a bitrate ladder, change
+    /// them mid-stream.
+    pub fn fits(&self, frame: &VideoFrame) -> bool {
+        frame.width() == self.width
+            && frame.height() == self.height
+            && frame.pixel_format() == self.pixel_format
+    }
+
+    pub fn convert(&mut self, frame: &VideoFrame) -> Result<Mat> {
+        let bgr = self.scaler.scale(frame)?;
+        let (width, height) = (bgr.width(), bgr.height());
+        let plane = &bgr.planes()[0];
+        let stride = plane.line_size();
+        let data = plane.data();
+
+        let mut mat =
+            Mat::new_rows_cols_with_default(height as i32, width as i32, CV_8UC3, Scalar::all(0.))?;
+        let row_len = width * 3;
+        let dst = mat.data_bytes_mut()?;
+        for row in 0..height {
+            dst[row * row_len..(row + 1) * row_len]
+                .copy_from_slice(&data[row * stride..row * stride + row_len]);
+        }
+        Ok(mat)
+    }
+}
@@ -0,0 +1,489 @@
+//! The `srt-playground` binary: every mode through `send` and `recv` on
+//! localhost, argument validation and the profiles in
+//! `srt-playground.toml`.
+
+#[macro_use]
+mod common;
+
+use std::{process::Command, thread, time::Duration};
+
+use common::{
+    assert_all_frames, decoded_frames, run_pair, serial, Process, FRAMES, STARTUP_DELAY, TIMEOUT,
+};
+
+const ADDR: &str = "127.0.0.1:5234";
+const LISTENER: [&str; 4] = ["--role", "listener", "--addr", ADDR];
+const CALLER: [&str; 4] = ["--role", "caller", "--addr", ADDR];
+
+/// `srt` are the connection flags.
+fn send(mode: &str, srt: &[&str]) -> Command {
+    let mut cmd = bin!("srt-playground");
+    cmd.args(["send", "--mode", mode])
+        .args(srt)
+        .args(["--source", "synthetic:320x240@30", "--timecode"])
+        .args(["--frame-limit", &FRAMES.to_string()]);
+    cmd
+}
+
+fn recv(srt: &[&str]) -> Command {
+    let mut cmd = bin!("srt-playground");
+    cmd.arg("recv").args(srt).args(["--headless", "--timecode"]);
+    cmd
+}
+
+fn rejected(args: &[&str]) -> String {
+    let output = bin!("srt-playground")
+        .args(args)
+        .output()
+        .expect("cannot run srt-playground");
+    assert_eq!(output.status.code(), Some(2), "{args:?} was accepted");
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+#[test]
+fn every_mode_sender_listening() {
+    let _ports = serial();
+    for mode in ["jpeg-message", "jpeg-chunked", "ts-mjpeg", "ts-h264"] {
+        let output = run_pair(send(mode, &LISTENER), recv(&CALLER), false);
+        assert!(output.contains("Receiving "), "{mode}: {output}");
+        assert_all_frames(&output);
+    }
+}
+
+#[test]
+fn receiver_listening_with_explicit_mode() {
+    let _ports = serial();
+    let mut sender = send("jpeg-chunked", &CALLER);
+    sender.args(["--chunk-size", "500", "--quality", "60"]);
+    let mut receiver = recv(&LISTENER);
+    receiver.args(["--mode", "jpeg-chunked", "--latency", "200"]);
+
+    let output = run_pair(receiver, sender, true);
+    assert!(
+        output.contains("Receiving length-prefixed chunked JPEG"),
+        "{output}"
+    );
+    assert_all_frames(&output);
+}
+
+#[test]
+fn other_codecs_are_detected_and_decoded() {
+    let _ports = serial();
+    for (mode, format) in [
+        ("ts-hevc", "MPEG-TS"),
+        ("mkv-vp9", "Matroska"),
+        ("mkv-av1", "Matroska"),
+    ] {
+        let output = run_pair(send(mode, &LISTENER), recv(&CALLER), false);
+        assert!(
+            output.contains(&format!("Receiving {format}")),
+            "{mode}: {output}"
+        );
+        assert_all_frames(&output);
+    }
+}
+
+#[test]
+fn late_caller_joins_at_a_keyframe() {
+    let _ports = serial();
+    // No keyframe of its own after the first one within the run
+    let mut sender = bin!("srt-playground");
+    sender
+        .args(["send", "--mode", "ts-h264", "--fan-out", "--gop", "600"])
+        .args(LISTENER)
+        .args(["--source", "synthetic:320x240@30", "--frame-limit", "120"]);
+    let sender = Process::spawn(sender);
+    thread::sleep(STARTUP_DELAY);
+    let first = Process::spawn(recv(&CALLER));
+    thread::sleep(Duration::from_secs(1));
+    let late = Process::spawn(recv(&CALLER));
+
+    let late = late.wait(TIMEOUT);
+    first.wait(TIMEOUT);
+    let sent = sender.wait(TIMEOUT);
+    assert!(sent.contains("Forcing a keyframe: caller"), "{sent}");
+    assert!(decoded_frames(&late) > 0, "{late}");
+}
+
+#[test]
+fn low_latency_h264_tuning() {
+    let _ports = serial();
+    let mut sender = send("ts-h264", &LISTENER);
+    sender.args([
+        "--preset",
+        "ultrafast",
+        "--zero-latency",
+        "--b-frames",
+        "0",
+        "--intra-refresh",
+        "--rate-control",
+        "cbr:2000",
+        "--vbv-buffer",
+        "100",
+    ]);
+
+    let output = run_pair(sender, recv(&CALLER), false);
+    assert_all_frames(&output);
+}
+
+/// A/V offsets the receiver reported, in milliseconds.
+fn av_offsets(output: &str) -> Vec<f64> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let offset = line.split_once(" video by ")?.1.strip_suffix("ms")?;
+            offset.parse().ok()
+        })
+        .collect()
+}
+
+#[test]
+fn audio_tone_in_sync_with_video() {
+    let _ports = serial();
+    let mut sender = send("ts-h264", &LISTENER);
+    sender.args(["--audio", "aac"]);
+
+    let output = run_pair(sender, recv(&CALLER), false);
+    assert_all_frames(&output);
+    assert!(
+        output.contains("Audio: aac, 48000 Hz, 2 channels"),
+        "{output}"
+    );
+    let offsets = av_offsets(&output);
+    assert!(!offsets.is_empty(), "{output}");
+    assert!(offsets.iter().all(|&ms| ms < 100.), "{output}");
+}
+
+#[test]
+fn audio_from_wav_file() {
+    // Half a second of a mono 8 kHz ramp, looped by the sender
+    let samples: Vec<u8> = (0..4000i16).flat_map(|i| (i * 8).to_le_bytes()).collect();
+    let mut wav = Vec::new();
+    wav.extend(b"RIFF");
+    wav.extend((36 + samples.len() as u32).to_le_bytes());
+    wav.extend(b"WAVEfmt ");
+    wav.extend(16u32.to_le_bytes());
+    // PCM, mono, 8000 Hz, 16000 bytes/s, 2 bytes per frame, 16 bits
+    for field in [1u16, 1] {
+        wav.extend(field.to_le_bytes());
+    }
+    wav.extend(8000u32.to_le_bytes());
+    wav.extend(16000u32.to_le_bytes());
+    for field in [2u16, 16] {
+        wav.extend(field.to_le_bytes());
+    }
+    wav.extend(b"data");
+    wav.extend((samples.len() as u32).to_le_bytes());
+    wav.extend(&samples);
+    let path = std::env::temp_dir().join(format!("srt-playground-{}.wav", std::process::id()));
+    std::fs::write(&path, &wav).unwrap();
+
+    let _ports = serial();
+    let mut sender = send("ts-mjpeg", &LISTENER);
+    sender
+        .arg("--audio")
+        .arg(format!("opus,wav={}", path.display()));
+
+    let output = run_pair(sender, recv(&CALLER), false);
+    let _ = std::fs::remove_file(&path);
+    assert_all_frames(&output);
+    assert!(output.contains("48000 Hz, 1 channels"), "{output}");
+    assert!(!av_offsets(&output).is_empty(), "{output}");
+}
+
+#[test]
+fn frame_budget_downscales_oversized_frames() {
+    let _ports = serial();
+    // Far below a 320x240 JPEG even at the lowest quality
+    let mut sender = send("jpeg-message", &LISTENER);
+    sender.args(["--frame-budget", "1000"]);
+
+    let output = run_pair(sender, recv(&CALLER), false);
+    let sizes: Vec<&str> = output
+        .lines()
+        .filter_map(|line| line.split_once(" decoded: ").map(|(_, size)| size))
+        .collect();
+    assert_eq!(sizes.len() as u64, FRAMES, "{output}");
+    assert!(sizes.iter().all(|&size| size != "320x240"), "{output}");
+}
+
+#[test]
+fn preprocessing_before_encode() {
+    let _ports = serial();
+    let mut sender = send("ts-h264", &LISTENER);
+    sender.args([
+        "--max-fps",
+        "15",
+        "--crop",
+        "240x120+40+60",
+        "--resize",
+        "192x144,letterbox",
+        "--grayscale",
+    ]);
+
+    let output = run_pair(sender, recv(&CALLER), false);
+    let sizes: Vec<&str> = output
+        .lines()
+        .filter_map(|line| line.split_once(" decoded: ").map(|(_, size)| size))
+        .collect();
+    // Every other source frame
+    assert_eq!(sizes.len() as u64, FRAMES / 2, "{output}");
+    assert!(sizes.iter().all(|&size| size == "192x144"), "{output}");
+}
+
+#[test]
+fn ladder_scales_to_the_rung() {
+    let _ports = serial();
+    let mut sender = send("ts-h264", &LISTENER);
+    sender.args(["--ladder", "160x120@300"]);
+
+    let output = run_pair(sender, recv(&CALLER), false);
+    assert_eq!(decoded_frames(&output) as u64, FRAMES, "{output}");
+    assert!(
+        output
+            .lines()
+            .filter(|line| line.contains(" decoded: "))
+            .all(|line| line.ends_with(" decoded: 160x120")),
+        "{output}"
+    );
+}
+
+#[test]
+fn ipv6_with_caller_source_port() {
+    let _ports = serial();
+    let sender = send(
+        "jpeg-message",
+        &["--role", "listener", "--addr", "[::1]:5238"],
+    );
+    let receiver = recv(&[
+        "--role",
+        "caller",
+        "--addr",
+        "[::1]:5238",
+        "--local-port",
+        "5239",
+    ]);
+
+    let output = run_pair(sender, receiver, false);
+    assert!(output.contains("Connected to [::1]:5238"), "{output}");
+    assert_all_frames(&output);
+}
+
+#[test]
+fn caller_resolves_host_names() {
+    let _ports = serial();
+    let sender = send(
+        "jpeg-message",
+        &[
+            "--role",
+            "listener",
+            "--addr",
+            ":5240",
+            "--bind",
+            "127.0.0.1",
+        ],
+    );
+    // localhost may resolve to ::1 first, binding picks the IPv4 address
+    let receiver = recv(&[
+        "--role",
+        "caller",
+        "--addr",
+        "localhost:5240",
+        "--bind",
+        "127.0.0.1",
+    ]);
+
+    let output = run_pair(sender, receiver, false);
+    assert!(
+        output.contains("Connecting to localhost:5240 (127.0.0.1:5240)"),
+        "{output}"
+    );
+    assert_all_frames(&output);
+}
+
+#[test]
+fn rendezvous_on_different_ports() {
+    let _ports = serial();
+    let sender = send(
+        "ts-h264",
+        &[
+            "--role",
+            "rendezvous",
+            "--addr",
+            "127.0.0.1:5236",
+            "--local-port",
+            "5235",
+        ],
+    );
+    let receiver = recv(&[
+        "--role",
+        "rendezvous",
+        "--addr",
+        "127.0.0.1:5235",
+        "--local-port",
+        "5236",
+    ]);
+
+    let output = run_pair(receiver, sender, true);
+    assert!(output.contains("Connected to 127.0.0.1:5235"), "{output}");
+    assert_all_frames(&output);
+}
+
+#[test]
+fn rendezvous_without_peer_explains_timeout() {
+    let output = recv(&[
+        "--role",
+        "rendezvous",
+        "--addr",
+        "127.0.0.1:5237",
+        "--connect-timeout",
+        "2",
+    ])
+    .output()
+    .unwrap();
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stdout.contains("from local port 5237"), "{stdout}");
+    assert!(
+        stderr.contains("no rendezvous handshake with 127.0.0.1:5237"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn invalid_arguments_are_rejected() {
+    assert!(rejected(&["send"]).contains("--mode"));
+    assert!(rejected(&["send", "--mode", "vp8"]).contains("possible values"));
+    assert!(rejected(&["send", "--mode", "ts-h264", "--quality", "50"]).contains("--quality"));
+    assert!(
+        rejected(&["send", "--mode", "ts-mjpeg", "--quality", "101"]).contains("between 1 and 100")
+    );
+    assert!(
+        rejected(&["send", "--mode", "ts-mjpeg", "--chunk-size", "500"]).contains("jpeg-chunked")
+    );
+    assert!(
+        rejected(&["send", "--mode", "ts-h264", "--adaptive-quality", "30-90"])
+            .contains("--adaptive-quality")
+    );
+    assert!(rejected(&[
+        "send",
+        "--mode",
+        "jpeg-message",
+        "--adaptive-quality",
+        "90-30"
+    ])
+    .contains("above the upper one"));
+    assert!(
+        rejected(&["send", "--mode", "ts-mjpeg", "--encoder-opt", "preset=fast"])
+            .contains("--encoder-opt")
+    );
+    assert!(
+        rejected(&["send", "--mode", "mkv-vp9", "--encoder-opt", "preset"]).contains("KEY=VALUE")
+    );
+    assert!(rejected(&["send", "--mode", "jpeg-message", "--gop", "30"]).contains("--gop"));
+    assert!(rejected(&["send", "--mode", "jpeg-chunked", "--fan-out"]).contains("midway"));
+    assert!(
+        rejected(&["send", "--mode", "ts-h264", "--fan-out", "--role", "caller"])
+            .contains("listener")
+    );
+    assert!(rejected(&["send", "--mode", "mkv-av1", "--intra-refresh"]).contains("ts-h264"));
+    assert!(
+        rejected(&["send", "--mode", "mkv-vp9", "--ladder", "640x360@600"]).contains("--ladder")
+    );
+    assert!(rejected(&["send", "--mode", "ts-h264", "--vbv-buffer", "100"]).contains("cbr"));
+    assert!(
+        rejected(&["send", "--mode", "ts-hevc", "--rate-control", "vbr:2000"]).contains("crf:")
+    );
+    assert!(rejected(&["send", "--mode", "mkv-vp9", "--audio", "aac"]).contains("--audio"));
+    assert!(rejected(&["send", "--mode", "ts-h264", "--audio", "mp3"]).contains("aac or opus"));
+    assert!(rejected(&["send", "--mode", "ts-h264", "--audio", "aac,tone=0"]).contains("positive"));
+    assert!(rejected(&["send", "--mode", "ts-h264", "--max-fps", "0"]).contains("--max-fps"));
+    assert!(rejected(&["send", "--mode", "ts-h264", "--crop", "640x360"]).contains("WxH+X+Y"));
+    assert!(rejected(&[
+        "send",
+        "--mode",
+        "jpeg-message",
+        "--resize",
+        "640x360,squash"
+    ])
+    .contains("letterbox"));
+    assert!(rejected(&["send", "--mode", "jpeg-message", "--source", "webcam"]).contains("webcam"));
+    assert!(rejected(&["recv", "--role", "rendezvous"]).contains("--addr"));
+    assert!(rejected(&["send", "--mode", "ts-h264", "--local-port", "4000"]).contains("--addr"));
+    assert!(rejected(&["recv", "--bind", "localhost"]).contains("--bind"));
+    assert!(rejected(&["recv", "--dump-every", "5"]).contains("--dump-dir"));
+}
+
+#[test]
+fn help_lists_subcommands() {
+    let output = bin!("srt-playground").arg("--help").output().unwrap();
+    assert!(output.status.success());
+    let help = String::from_utf8_lossy(&output.stdout);
+    assert!(help.contains("send") && help.contains("recv"), "{help}");
+}
+
+fn print_config(args: &[&str]) -> String {
+    let output = bin!("srt-playground")
+        .arg("--print-config")
+        .args(args)
+        .output()
+        .expect("cannot run srt-playground");
+    assert!(output.status.success(), "{args:?}: {output:?}");
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+#[test]
+fn profile_fields_are_overridden_by_flags() {
+    let config = print_config(&["--profile", "lan-lowlatency", "send", "--latency", "25"]);
+    assert!(config.contains("[lan-lowlatency.send]"), "{config}");
+    assert!(config.contains("mode = \"jpeg-message\""), "{config}");
+    assert!(config.contains("quality = 70"), "{config}");
+    assert!(config.contains("latency = 25"), "{config}");
+}
+
+#[test]
+fn preprocessing_in_a_profile() {
+    let config = print_config(&["--profile", "monitoring", "send", "--max-fps", "2"]);
+    assert!(config.contains("max-fps = 2.0"), "{config}");
+    assert!(config.contains("crop = \"1280x540+0+180\""), "{config}");
+    assert!(config.contains("grayscale = true"), "{config}");
+}
+
+#[test]
+fn side_specific_srt_settings() {
+    let send = print_config(&["--profile", "v4", "send"]);
+    assert!(send.contains("role = \"caller\""), "{send}");
+    let recv = print_config(&["--profile", "v4", "recv"]);
+    assert!(recv.contains("role = \"listener\""), "{recv}");
+    assert!(recv.contains("addr = \"0.0.0.0:4200\""), "{recv}");
+}
+
+#[test]
+fn printed_config_loads_as_profile() {
+    let printed = print_config(&[
+        "--profile",
+        "wan-robust",
+        "recv",
+        "--addr",
+        "10.0.0.2:1234",
+        "--headless",
+    ]);
+    let path = std::env::temp_dir().join(format!("srt-playground-{}.toml", std::process::id()));
+    std::fs::write(&path, &printed).unwrap();
+
+    let path_arg = path.to_string_lossy().into_owned();
+    let reloaded = print_config(&["--config", &path_arg, "--profile", "wan-robust", "recv"]);
+    let _ = std::fs::remove_file(&path);
+    assert_eq!(reloaded, printed);
+}
+
+#[test]
+fn unknown_profile_is_an_error() {
+    let output = bin!("srt-playground")
+        .args(["--profile", "nope", "send", "--mode", "ts-h264"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("lan-lowlatency"));
+}
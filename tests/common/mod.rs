@@ -0,0 +1,318 @@
+//! Helpers shared by the loopback tests: process management for the sender
+//! and receiver binaries, output parsing and a lossy UDP proxy.
+#![allow(dead_code)]
+
+use std::{
+    io::{ErrorKind, Read},
+    net::{SocketAddr, UdpSocket},
+    process::{Child, Command, ExitStatus, Stdio},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+/// Frames every sender produces in a test run.
+pub const FRAMES: u64 = 30;
+
+/// How long a pair may run before the test fails.
+pub const TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Gives the listener time to bind before the caller starts.
+pub const STARTUP_DELAY: Duration = Duration::from_millis(500);
+
+/// Command for a binary target of this package.
+#[allow(unused_macros)]
+macro_rules! bin {
+    ($name:literal) => {
+        std::process::Command::new(env!(concat!("CARGO_BIN_EXE_", $name)))
+    };
+}
+
+// The binaries use fixed ports, so pairs must not run concurrently.
+static PORTS: Mutex<()> = Mutex::new(());
+
+pub fn serial() -> MutexGuard<'static, ()> {
+    PORTS.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Configures a sender for synthetic, timecoded, bounded output.
+pub fn sender(mut cmd: Command) -> Command {
+    cmd.env("SOURCE", "synthetic")
+        .env("SYNTHETIC_SIZE", "320x240")
+        .env("SYNTHETIC_FPS", "30")
+        .env("FRAME_LIMIT", FRAMES.to_string())
+        .env("TIMECODE", "1");
+    cmd
+}
+
+/// Configures a receiver to run without a display.
+pub fn receiver(mut cmd: Command) -> Command {
+    cmd.env("HEADLESS", "1").env("TIMECODE", "1");
+    cmd
+}
+
+/// A child process whose output is collected in the background and which is
+/// killed if the test bails out early.
+pub struct Process {
+    name: String,
+    child: Child,
+    stdout: Option<JoinHandle<String>>,
+    stderr: Option<JoinHandle<String>>,
+}
+
+/// How a process ended, `status` is `None` if it had to be killed.
+pub struct Finished {
+    pub status: Option<ExitStatus>,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+fn collect(mut pipe: impl Read + Send + 'static) -> JoinHandle<String> {
+    thread::spawn(move || {
+        let mut output = String::new();
+        let _ = pipe.read_to_string(&mut output);
+        output
+    })
+}
+
+impl Process {
+    pub fn spawn(mut cmd: Command) -> Self {
+        let name = cmd.get_program().to_string_lossy().into_owned();
+        let mut child = cmd
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap_or_else(|e| panic!("cannot start {name}: {e}"));
+
+        let stdout = collect(child.stdout.take().expect("stdout is piped"));
+        let stderr = collect(child.stderr.take().expect("stderr is piped"));
+
+        Self {
+            name,
+            child,
+            stdout: Some(stdout),
+            stderr: Some(stderr),
+        }
+    }
+
+    /// Waits up to `timeout` for the process to exit, killing it otherwise.
+    pub fn finish(mut self, timeout: Duration) -> Finished {
+        let deadline = Instant::now() + timeout;
+        let status = loop {
+            match self.child.try_wait().expect("cannot poll child") {
+                Some(status) => break Some(status),
+                None if Instant::now() >= deadline => {
+                    let _ = self.child.kill();
+                    let _ = self.child.wait();
+                    break None;
+                }
+                None => thread::sleep(Duration::from_millis(50)),
+            }
+        };
+
+        let join = |t: Option<JoinHandle<String>>| {
+            t.map(|t| t.join().unwrap_or_default()).unwrap_or_default()
+        };
+        Finished {
+            status,
+            stdout: join(self.stdout.take()),
+            stderr: join(self.stderr.take()),
+        }
+    }
+
+    /// Waits for the process to exit and returns its stdout. Panics with the
+    /// output so far if it does not exit within `timeout`.
+    pub fn wait(self, timeout: Duration) -> String {
+        let name = self.name.clone();
+        let finished = self.finish(timeout);
+        if finished.status.is_none() {
+            panic!(
+                "{name} did not exit within {timeout:?}, output:\n{}\n{}",
+                finished.stdout, finished.stderr
+            );
+        }
+        finished.stdout
+    }
+}
+
+impl Drop for Process {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Runs a pair to completion and returns the receiver's stdout. `listener`
+/// is started first.
+pub fn run_pair(listener: Command, caller: Command, receiver_listens: bool) -> String {
+    let listener = Process::spawn(listener);
+    thread::sleep(STARTUP_DELAY);
+    let caller = Process::spawn(caller);
+
+    if receiver_listens {
+        caller.wait(TIMEOUT);
+        listener.wait(TIMEOUT)
+    } else {
+        let output = caller.wait(TIMEOUT);
+        drop(listener);
+        output
+    }
+}
+
+/// Sender side timecodes the receiver reported, in arrival order.
+pub fn timecodes(output: &str) -> Vec<u128> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let rest = line.split_once(" timecode ")?.1;
+            rest.split_once(" us")?.0.parse().ok()
+        })
+        .collect()
+}
+
+pub fn decoded_frames(output: &str) -> usize {
+    output.lines().filter(|l| l.contains(" decoded: ")).count()
+}
+
+/// Every frame arrived, decoded, carried a readable timecode and was shown
+/// in capture order.
+pub fn assert_all_frames(output: &str) {
+    assert_eq!(
+        decoded_frames(output) as u64,
+        FRAMES,
+        "decoded frame count, receiver output:\n{output}"
+    );
+
+    let timecodes = timecodes(output);
+    assert_eq!(
+        timecodes.len() as u64,
+        FRAMES,
+        "readable timecodes, receiver output:\n{output}"
+    );
+    assert!(
+        timecodes.windows(2).all(|w| w[0] < w[1]),
+        "frames out of order: {timecodes:?}"
+    );
+}
+
+/// UDP relay in front of a listener that drops a share of the datagrams in
+/// both directions. The first few datagrams always pass so the handshake
+/// completes quickly; SRT has to recover the rest.
+pub struct LossyProxy {
+    pub addr: SocketAddr,
+    stop: Arc<AtomicBool>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+const PROXY_HANDSHAKE_PACKETS: u64 = 8;
+
+impl LossyProxy {
+    pub fn start(upstream: &str, loss_percent: u32) -> Self {
+        let front = UdpSocket::bind("127.0.0.1:0").expect("bind proxy");
+        let back = UdpSocket::bind("127.0.0.1:0").expect("bind proxy upstream");
+        back.connect(upstream).expect("connect proxy upstream");
+        for socket in [&front, &back] {
+            socket
+                .set_read_timeout(Some(Duration::from_millis(100)))
+                .unwrap();
+        }
+
+        let addr = front.local_addr().unwrap();
+        let stop = Arc::new(AtomicBool::new(false));
+        let client: Arc<Mutex<Option<SocketAddr>>> = Arc::default();
+
+        let upstream_thread = {
+            let (front, back) = (front.try_clone().unwrap(), back.try_clone().unwrap());
+            let (stop, client) = (stop.clone(), client.clone());
+            thread::spawn(move || {
+                let mut loss = Loss::new(loss_percent, 1);
+                let mut buf = [0u8; 2048];
+                while !stop.load(Ordering::Relaxed) {
+                    match front.recv_from(&mut buf) {
+                        Ok((len, from)) => {
+                            *client.lock().unwrap() = Some(from);
+                            if !loss.drop_next() {
+                                let _ = back.send(&buf[..len]);
+                            }
+                        }
+                        Err(e) if transient(&e) => {}
+                        Err(_) => break,
+                    }
+                }
+            })
+        };
+
+        let downstream_thread = {
+            let stop = stop.clone();
+            thread::spawn(move || {
+                let mut loss = Loss::new(loss_percent, 2);
+                let mut buf = [0u8; 2048];
+                while !stop.load(Ordering::Relaxed) {
+                    match back.recv(&mut buf) {
+                        Ok(len) => {
+                            let Some(to) = *client.lock().unwrap() else {
+                                continue;
+                            };
+                            if !loss.drop_next() {
+                                let _ = front.send_to(&buf[..len], to);
+                            }
+                        }
+                        Err(e) if transient(&e) => {}
+                        Err(_) => break,
+                    }
+                }
+            })
+        };
+
+        Self {
+            addr,
+            stop,
+            threads: vec![upstream_thread, downstream_thread],
+        }
+    }
+}
+
+// Read timeouts, and ICMP port unreachable while the listener starts up
+fn transient(e: &std::io::Error) -> bool {
+    matches!(
+        e.kind(),
+        ErrorKind::WouldBlock | ErrorKind::TimedOut | ErrorKind::ConnectionRefused
+    )
+}
+
+impl Drop for LossyProxy {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Deterministic xorshift drop decisions, so failures are reproducible.
+struct Loss {
+    percent: u32,
+    state: u64,
+    seen: u64,
+}
+
+impl Loss {
+    fn new(percent: u32, seed: u64) -> Self {
+        Self {
+            percent,
+            state: 0x9E37_79B9_7F4A_7C15 ^ seed,
+            seen: 0,
+        }
+    }
+
+    fn drop_next(&mut self) -> bool {
+        self.seen += 1;
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.seen > PROXY_HANDSHAKE_PACKETS && (self.state % 100) < u64::from(self.percent)
+    }
+}
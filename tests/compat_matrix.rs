@@ -0,0 +1,313 @@
+//! Cross-version compatibility matrix: every sender against every receiver
+//! mode, recording which pairs interoperate and how the others fail.
+//!
+//! Slow (each failing pair runs into a timeout), so it is ignored by default:
+//!
+//! ```text
+//! cargo test --test compat_matrix -- --ignored
+//! ```
+//!
+//! The table is written to `target/compat_matrix.md` and compared with the
+//! checked-in `tests/compat_matrix.md`; a difference fails the test. Set
+//! `UPDATE_COMPAT_MATRIX=1` to accept the new table.
+
+#[macro_use]
+mod common;
+
+use std::{fmt::Write as _, path::Path, process::Command, thread, time::Duration};
+
+use common::{decoded_frames, receiver, sender, serial, timecodes, Finished, Process, FRAMES};
+
+const PAIR_TIMEOUT: Duration = Duration::from_secs(15);
+
+#[derive(Clone, Copy, PartialEq)]
+enum Role {
+    Listener,
+    Caller,
+}
+
+#[derive(Clone, Copy)]
+enum Evidence {
+    /// Only counts SRT messages, cannot judge the content.
+    Messages,
+    /// Runs the TS analyzer.
+    Transport,
+    /// Decodes frames and reads the timecode.
+    Frames,
+}
+
+struct Sender {
+    name: &'static str,
+    role: Role,
+    command: fn() -> Command,
+}
+
+struct Receiver {
+    name: &'static str,
+    role: Role,
+    evidence: Evidence,
+    command: fn() -> Command,
+}
+
+fn senders() -> Vec<Sender> {
+    vec![
+        Sender {
+            name: "v1_sender",
+            role: Role::Listener,
+            command: || bin!("v1_sender"),
+        },
+        Sender {
+            name: "v2_sender",
+            role: Role::Listener,
+            command: || bin!("v2_sender"),
+        },
+        Sender {
+            name: "v3_sender",
+            role: Role::Listener,
+            command: || bin!("v3_sender"),
+        },
+        Sender {
+            name: "v4_sender",
+            role: Role::Caller,
+            command: || bin!("v4_sender"),
+        },
+        Sender {
+            name: "v5_sender",
+            role: Role::Listener,
+            command: || bin!("v5_sender"),
+        },
+    ]
+}
+
+fn receivers() -> Vec<Receiver> {
+    vec![
+        Receiver {
+            name: "v1_receiver",
+            role: Role::Caller,
+            evidence: Evidence::Messages,
+            command: || bin!("v1_receiver"),
+        },
+        Receiver {
+            name: "v2_receiver (ANALYZE)",
+            role: Role::Caller,
+            evidence: Evidence::Transport,
+            command: || {
+                let mut cmd = bin!("v2_receiver");
+                cmd.env("ANALYZE", "1");
+                cmd
+            },
+        },
+        Receiver {
+            name: "v3_receiver (ANALYZE)",
+            role: Role::Caller,
+            evidence: Evidence::Transport,
+            command: || {
+                let mut cmd = bin!("v3_receiver");
+                cmd.env("ANALYZE", "1");
+                cmd
+            },
+        },
+        Receiver {
+            name: "v4_receiver",
+            role: Role::Listener,
+            evidence: Evidence::Frames,
+            command: || bin!("v4_receiver"),
+        },
+        Receiver {
+            name: "v5_receiver",
+            role: Role::Caller,
+            evidence: Evidence::Frames,
+            command: || bin!("v5_receiver"),
+        },
+        Receiver {
+            name: "universal (call :1234)",
+            role: Role::Caller,
+            evidence: Evidence::Frames,
+            command: || bin!("universal_receiver"),
+        },
+        Receiver {
+            name: "universal (call :9999)",
+            role: Role::Caller,
+            evidence: Evidence::Frames,
+            command: || {
+                let mut cmd = bin!("universal_receiver");
+                cmd.env("SRT_CALL", "127.0.0.1:9999");
+                cmd
+            },
+        },
+        Receiver {
+            name: "universal (listen :4200)",
+            role: Role::Listener,
+            evidence: Evidence::Frames,
+            command: || {
+                let mut cmd = bin!("universal_receiver");
+                cmd.env("SRT_LISTEN", "0.0.0.0:4200");
+                cmd
+            },
+        },
+    ]
+}
+
+fn run(s: &Sender, r: &Receiver) -> Finished {
+    let sender_cmd = sender((s.command)());
+    let receiver_cmd = receiver((r.command)());
+
+    // Start the listening side first, callers give up after a while
+    let (sending, receiving) = if r.role == Role::Listener {
+        let receiving = Process::spawn(receiver_cmd);
+        thread::sleep(common::STARTUP_DELAY);
+        (Process::spawn(sender_cmd), receiving)
+    } else {
+        let sending = Process::spawn(sender_cmd);
+        thread::sleep(common::STARTUP_DELAY);
+        (sending, Process::spawn(receiver_cmd))
+    };
+
+    let finished = receiving.finish(PAIR_TIMEOUT);
+    drop(sending);
+    finished
+}
+
+fn last_error(stderr: &str) -> Option<&str> {
+    stderr
+        .lines()
+        .rev()
+        .find(|l| !l.trim().is_empty())
+        .map(str::trim)
+}
+
+/// Short verdict for the table, and whether the pair interoperates.
+fn classify(s: &Sender, r: &Receiver, run: &Finished) -> (String, bool) {
+    let out = &run.stdout;
+    let connected = out.contains("onnected") || out.contains("listener ready");
+
+    if !connected {
+        let why = if s.role == r.role {
+            match s.role {
+                Role::Listener => "no connection: both listen",
+                Role::Caller => "no connection: both call",
+            }
+        } else {
+            "no connection: port mismatch"
+        };
+        return (why.into(), false);
+    }
+
+    let verdict = match r.evidence {
+        Evidence::Messages => {
+            // Not counted, how many messages a TS stream takes depends on the
+            // encoder build and would make the table churn
+            if out.lines().any(|l| l.starts_with("Packet #")) {
+                return ("bytes only".into(), true);
+            }
+            "connected, no data".to_string()
+        }
+        Evidence::Transport => {
+            if out.contains(", 0 sync losses") && out.contains("PMT PID") {
+                let cc_ok = out.contains("totals: 0 CC errors");
+                return (
+                    if cc_ok {
+                        "valid TS".into()
+                    } else {
+                        "TS with CC errors".into()
+                    },
+                    cc_ok,
+                );
+            }
+            "not MPEG-TS".to_string()
+        }
+        Evidence::Frames => {
+            let decoded = decoded_frames(out) as u64;
+            let stamped = timecodes(out);
+            let ordered = stamped.windows(2).all(|w| w[0] < w[1]);
+            if decoded == FRAMES && stamped.len() as u64 == FRAMES && ordered {
+                return ("ok".into(), true);
+            }
+            if decoded > 0 {
+                format!("partial: {decoded}/{FRAMES} decoded")
+            } else if out.contains("failed to decode") || out.contains("Empty frame") {
+                "payload not decodable".to_string()
+            } else {
+                "connected, no frames".to_string()
+            }
+        }
+    };
+
+    let verdict = match (run.status, last_error(&run.stderr)) {
+        (Some(status), Some(err)) if !status.success() => format!("{verdict} ({err})"),
+        (None, _) => format!("{verdict}, timed out"),
+        _ => verdict,
+    };
+    (verdict, false)
+}
+
+fn render(rows: &[(&Sender, Vec<(String, bool)>)], receivers: &[Receiver]) -> String {
+    let mut table = String::new();
+    writeln!(table, "# Sender/receiver compatibility matrix\n").unwrap();
+    writeln!(
+        table,
+        "Generated by `cargo test --test compat_matrix -- --ignored`, {FRAMES} synthetic frames per pair.\n"
+    )
+    .unwrap();
+
+    write!(table, "| sender \\ receiver |").unwrap();
+    for r in receivers {
+        write!(table, " {} |", r.name).unwrap();
+    }
+    writeln!(table).unwrap();
+    writeln!(table, "|---|{}", "---|".repeat(receivers.len())).unwrap();
+
+    for (s, cells) in rows {
+        write!(table, "| {} |", s.name).unwrap();
+        for (verdict, works) in cells {
+            let mark = if *works { "✅" } else { "❌" };
+            write!(table, " {mark} {verdict} |").unwrap();
+        }
+        writeln!(table).unwrap();
+    }
+    table
+}
+
+#[test]
+#[ignore = "slow, runs every sender against every receiver"]
+fn compatibility_matrix() {
+    let _ports = serial();
+    let receivers = receivers();
+    let senders = senders();
+
+    let mut rows = Vec::new();
+    for s in &senders {
+        let mut cells = Vec::new();
+        for r in &receivers {
+            let finished = run(s, r);
+            let cell = classify(s, r, &finished);
+            println!("{} -> {}: {}", s.name, r.name, cell.0);
+            cells.push(cell);
+        }
+        rows.push((s, cells));
+    }
+
+    let table = render(&rows, &receivers);
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let generated = root.join("target").join("compat_matrix.md");
+    std::fs::create_dir_all(generated.parent().unwrap()).unwrap();
+    std::fs::write(&generated, &table).unwrap();
+
+    let checked_in = root.join("tests").join("compat_matrix.md");
+    if std::env::var_os("UPDATE_COMPAT_MATRIX").is_some() {
+        std::fs::write(&checked_in, &table).unwrap();
+        return;
+    }
+    let expected = std::fs::read_to_string(&checked_in).unwrap_or_else(|e| {
+        panic!(
+            "cannot read {}: {e}, rerun with UPDATE_COMPAT_MATRIX=1 to create it",
+            checked_in.display()
+        )
+    });
+    assert_eq!(
+        expected,
+        table,
+        "compatibility changed, see {} and rerun with UPDATE_COMPAT_MATRIX=1 to accept",
+        generated.display()
+    );
+}
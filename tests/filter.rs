@@ -0,0 +1,142 @@
+//! The preprocessing filters on made-up frames.
+
+use std::time::SystemTime;
+
+use opencv::{
+    core::{Mat, Scalar, Vec3b, CV_8UC3},
+    prelude::*,
+};
+use rust_srt_playground::{
+    filter::{Crop, Decimate, Fit, Grayscale, Region, Resize, Scale},
+    pipeline::{Filter, FilterSpec, Frame, FrameMeta, StreamInfo},
+};
+
+const INFO: StreamInfo = StreamInfo {
+    width: 320,
+    height: 240,
+    fps: 30.0,
+};
+
+/// A 320x240 frame in one color.
+fn frame(index: u64, bgr: (f64, f64, f64)) -> Frame {
+    let image = Mat::new_rows_cols_with_default(
+        INFO.height as i32,
+        INFO.width as i32,
+        CV_8UC3,
+        Scalar::new(bgr.0, bgr.1, bgr.2, 0.),
+    )
+    .unwrap();
+    Frame {
+        image,
+        meta: FrameMeta {
+            index,
+            captured: SystemTime::now(),
+        },
+    }
+}
+
+fn pixel(frame: &Frame, x: i32, y: i32) -> Vec3b {
+    *frame.image.at_2d::<Vec3b>(y, x).unwrap()
+}
+
+fn size(frame: &Frame) -> (i32, i32) {
+    (frame.image.cols(), frame.image.rows())
+}
+
+#[test]
+fn letterbox_pads_with_black_bars() {
+    // 4:3 into 16:9 leaves bars left and right
+    let mut resize = Resize::new(320, 180).fit(Fit::Letterbox);
+    let out = resize.apply(frame(0, (0., 0., 255.))).unwrap().unwrap();
+    assert_eq!(size(&out), (320, 180));
+    assert_eq!(pixel(&out, 0, 90), Vec3b::from([0, 0, 0]));
+    assert_eq!(pixel(&out, 160, 90), Vec3b::from([0, 0, 255]));
+    assert_eq!(pixel(&out, 319, 90), Vec3b::from([0, 0, 0]));
+}
+
+#[test]
+fn crop_fit_fills_the_size() {
+    let mut resize = Resize::new(320, 180).fit(Fit::Crop);
+    let out = resize.apply(frame(0, (0., 0., 255.))).unwrap().unwrap();
+    assert_eq!(size(&out), (320, 180));
+    assert_eq!(pixel(&out, 0, 0), Vec3b::from([0, 0, 255]));
+    assert_eq!(pixel(&out, 319, 179), Vec3b::from([0, 0, 255]));
+}
+
+#[test]
+fn crop_cuts_the_region() {
+    let region: Region = "100x50+20+10".parse().unwrap();
+    let mut crop = Crop::new(region, INFO).unwrap();
+    assert_eq!(crop.info(INFO).width, 100);
+    assert_eq!(crop.info(INFO).height, 50);
+    let out = crop.apply(frame(0, (1., 2., 3.))).unwrap().unwrap();
+    assert_eq!(size(&out), (100, 50));
+}
+
+#[test]
+fn crop_outside_the_frame_is_an_error() {
+    let region: Region = "100x50+300+10".parse().unwrap();
+    let error = Crop::new(region, INFO).err().unwrap();
+    assert!(error.to_string().contains("320x240"), "{error}");
+}
+
+#[test]
+fn grayscale_keeps_bgr_layout() {
+    let out = Grayscale
+        .apply(frame(0, (40., 120., 200.)))
+        .unwrap()
+        .unwrap();
+    assert_eq!(out.image.typ(), CV_8UC3);
+    let [b, g, r] = pixel(&out, 5, 5).0;
+    assert!(b == g && g == r, "{b} {g} {r}");
+}
+
+#[test]
+fn decimate_keeps_frames_evenly() {
+    let mut decimate = Decimate::new(30.0, 5.0);
+    assert_eq!(decimate.info(INFO).fps, 5.0);
+    let kept: Vec<u64> = (0..30)
+        .filter_map(|i| decimate.apply(frame(i, (0., 0., 0.))).unwrap())
+        .map(|frame| frame.meta.index)
+        .collect();
+    assert_eq!(kept, [0, 6, 12, 18, 24]);
+}
+
+#[test]
+fn decimate_never_raises_the_rate() {
+    let mut decimate = Decimate::new(10.0, 30.0);
+    assert_eq!(decimate.info(INFO).fps, INFO.fps);
+    assert!((0..10).all(|i| decimate.apply(frame(i, (0., 0., 0.))).unwrap().is_some()));
+}
+
+#[test]
+fn filter_specs_round_trip() {
+    for text in [
+        "resize:640x360",
+        "resize:640x360,letterbox",
+        "resize:640x360,crop",
+        "crop:640x360+0+60",
+        "grayscale",
+        "fps:7.5",
+        "timecode",
+    ] {
+        let spec: FilterSpec = text.parse().unwrap();
+        assert_eq!(spec.to_string(), text);
+    }
+    assert_eq!(
+        "640x360,letterbox".parse::<Scale>().unwrap(),
+        Scale {
+            width: 640,
+            height: 360,
+            fit: Fit::Letterbox
+        }
+    );
+    for bad in [
+        "resize:640x0",
+        "resize:640x360,squash",
+        "crop:640x360",
+        "fps:0",
+    ] {
+        assert!(bad.parse::<FilterSpec>().is_err(), "{bad}");
+    }
+}
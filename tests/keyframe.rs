@@ -0,0 +1,48 @@
+//! Rate limiting of forced keyframes.
+
+use std::time::{Duration, Instant};
+
+use rust_srt_playground::keyframe::{KeyframeLimiter, KeyframeTrigger};
+
+const INTERVAL: Duration = Duration::from_secs(1);
+
+#[test]
+fn first_request_goes_through() {
+    let mut limiter = KeyframeLimiter::new(INTERVAL);
+    let now = Instant::now();
+    assert_eq!(limiter.poll(now), None);
+
+    limiter.request("caller joined");
+    assert_eq!(limiter.poll(now).as_deref(), Some("caller joined"));
+    assert_eq!(limiter.poll(now), None);
+}
+
+#[test]
+fn requests_within_the_interval_wait_and_merge() {
+    let mut limiter = KeyframeLimiter::new(INTERVAL);
+    let start = Instant::now();
+    limiter.request("first");
+    assert!(limiter.poll(start).is_some());
+
+    limiter.request("second");
+    limiter.request("third");
+    limiter.request("fourth");
+    assert_eq!(limiter.poll(start + INTERVAL / 2), None);
+    assert_eq!(
+        limiter.poll(start + INTERVAL).as_deref(),
+        Some("second, 2 later requests merged")
+    );
+    assert_eq!(limiter.poll(start + INTERVAL * 3), None);
+}
+
+#[test]
+fn trigger_fires_once_per_request() {
+    let trigger = KeyframeTrigger::new();
+    let encoder_side = trigger.clone();
+    assert!(!encoder_side.take());
+
+    trigger.request();
+    trigger.request();
+    assert!(encoder_side.take());
+    assert!(!encoder_side.take());
+}
@@ -0,0 +1,128 @@
+//! Runs every sender against its receiver on localhost with the synthetic
+//! source and checks that all frames arrive decodable and in order.
+//!
+//! The proxied variants push the stream through a lossy UDP relay and are
+//! ignored by default, run them with `cargo test -- --ignored`.
+
+#[macro_use]
+mod common;
+
+use common::{
+    assert_all_frames, receiver, run_pair, sender, serial, LossyProxy, Process, FRAMES, TIMEOUT,
+};
+
+fn packets(output: &str) -> u64 {
+    output.lines().filter(|l| l.starts_with("Packet #")).count() as u64
+}
+
+#[test]
+fn v1_sender_to_v1_receiver() {
+    let _ports = serial();
+    let output = run_pair(sender(bin!("v1_sender")), bin!("v1_receiver"), false);
+    // One SRT message per JPEG
+    assert_eq!(packets(&output), FRAMES, "receiver output:\n{output}");
+}
+
+#[test]
+fn v1_sender_frames_decode() {
+    let _ports = serial();
+    let output = run_pair(
+        sender(bin!("v1_sender")),
+        receiver(bin!("universal_receiver")),
+        false,
+    );
+    assert!(output.contains("Detected JPEG per SRT message"), "{output}");
+    assert_all_frames(&output);
+}
+
+#[test]
+fn v2_sender_to_v2_receiver() {
+    let _ports = serial();
+    let mut analyzer = bin!("v2_receiver");
+    analyzer.env("ANALYZE", "1");
+    let output = run_pair(sender(bin!("v2_sender")), analyzer, false);
+    assert!(output.contains("totals: 0 CC errors"), "{output}");
+    assert!(output.contains(", 0 sync losses"), "{output}");
+}
+
+#[test]
+fn v2_sender_frames_decode() {
+    let _ports = serial();
+    let output = run_pair(
+        sender(bin!("v2_sender")),
+        receiver(bin!("universal_receiver")),
+        false,
+    );
+    assert!(output.contains("Detected MPEG-TS"), "{output}");
+    assert_all_frames(&output);
+}
+
+#[test]
+fn v3_sender_to_v3_receiver() {
+    let _ports = serial();
+    let mut analyzer = bin!("v3_receiver");
+    analyzer.env("ANALYZE", "1");
+    let output = run_pair(sender(bin!("v3_sender")), analyzer, false);
+    assert!(output.contains("totals: 0 CC errors"), "{output}");
+    assert!(output.contains(", 0 sync losses"), "{output}");
+}
+
+#[test]
+fn v3_sender_frames_decode() {
+    let _ports = serial();
+    let output = run_pair(
+        sender(bin!("v3_sender")),
+        receiver(bin!("universal_receiver")),
+        false,
+    );
+    assert!(output.contains("Detected MPEG-TS"), "{output}");
+    assert_all_frames(&output);
+}
+
+#[test]
+fn v4_sender_to_v4_receiver() {
+    let _ports = serial();
+    let output = run_pair(receiver(bin!("v4_receiver")), sender(bin!("v4_sender")), true);
+    assert_all_frames(&output);
+}
+
+#[test]
+fn v5_sender_to_v5_receiver() {
+    let _ports = serial();
+    let output = run_pair(sender(bin!("v5_sender")), receiver(bin!("v5_receiver")), false);
+    assert_all_frames(&output);
+}
+
+fn through_proxy(sender_cmd: std::process::Command, upstream: &str) -> String {
+    let sending = Process::spawn(sender(sender_cmd));
+    std::thread::sleep(common::STARTUP_DELAY);
+
+    let proxy = LossyProxy::start(upstream, 5);
+    let mut universal = receiver(bin!("universal_receiver"));
+    universal.env("SRT_CALL", proxy.addr.to_string());
+    let output = Process::spawn(universal).wait(TIMEOUT);
+
+    drop(sending);
+    output
+}
+
+#[test]
+#[ignore = "slow, exercises SRT retransmission through a lossy proxy"]
+fn v1_sender_through_lossy_proxy() {
+    let _ports = serial();
+    assert_all_frames(&through_proxy(bin!("v1_sender"), "127.0.0.1:1234"));
+}
+
+#[test]
+#[ignore = "slow, exercises SRT retransmission through a lossy proxy"]
+fn v3_sender_through_lossy_proxy() {
+    let _ports = serial();
+    assert_all_frames(&through_proxy(bin!("v3_sender"), "127.0.0.1:1234"));
+}
+
+#[test]
+#[ignore = "slow, exercises SRT retransmission through a lossy proxy"]
+fn v5_sender_through_lossy_proxy() {
+    let _ports = serial();
+    assert_all_frames(&through_proxy(bin!("v5_sender"), "127.0.0.1:9999"));
+}
@@ -0,0 +1,127 @@
+//! DTS pacing, on a paused tokio clock.
+
+use std::time::Duration;
+
+use ac_ffmpeg::time::{TimeBase, Timestamp};
+use rust_srt_playground::pacing::DtsPacer;
+use tokio::time::{advance, Instant};
+
+/// One frame at 30 fps, in 90 kHz ticks.
+const FRAME: i64 = 3000;
+
+fn ticks(ticks: i64) -> Timestamp {
+    Timestamp::new(ticks, TimeBase::new(1, 90_000))
+}
+
+/// Paces `dts` and returns how long that took.
+async fn pace(pacer: &mut DtsPacer, dts: Timestamp) -> Duration {
+    let start = Instant::now();
+    pacer.pace(dts).await;
+    start.elapsed()
+}
+
+fn assert_near(actual: Duration, expected: Duration) {
+    let tolerance = Duration::from_millis(2);
+    assert!(
+        actual.abs_diff(expected) <= tolerance,
+        "{actual:?} is not within {tolerance:?} of {expected:?}"
+    );
+}
+
+fn frames(n: u32) -> Duration {
+    Duration::from_nanos(33_333_333) * n
+}
+
+#[tokio::test(start_paused = true)]
+async fn paces_on_decode_time() {
+    let mut pacer = DtsPacer::new();
+    assert_eq!(pace(&mut pacer, ticks(0)).await, Duration::ZERO);
+    assert_near(pace(&mut pacer, ticks(FRAME)).await, frames(1));
+    assert_near(pace(&mut pacer, ticks(3 * FRAME)).await, frames(2));
+    // Messages of the same packet share its timestamp
+    assert_eq!(pace(&mut pacer, ticks(3 * FRAME)).await, Duration::ZERO);
+}
+
+#[tokio::test(start_paused = true)]
+async fn b_frames_pace_evenly() {
+    // An IPBB stream at 30 fps: the PTS goes 0, 3, 1, 2 while the DTS
+    // starts below zero and steps evenly
+    let time_base = TimeBase::new(1, 30);
+    let mut pacer = DtsPacer::new();
+    let start = Instant::now();
+    for dts in -1..3 {
+        pacer.pace(Timestamp::new(dts, time_base)).await;
+    }
+    assert_near(start.elapsed(), frames(3));
+}
+
+#[tokio::test(start_paused = true)]
+async fn small_step_back_is_sent_at_once() {
+    let mut pacer = DtsPacer::new();
+    pace(&mut pacer, ticks(0)).await;
+    pace(&mut pacer, ticks(2 * FRAME)).await;
+    assert_eq!(pace(&mut pacer, ticks(FRAME)).await, Duration::ZERO);
+    assert_near(pace(&mut pacer, ticks(3 * FRAME)).await, frames(1));
+}
+
+#[tokio::test(start_paused = true)]
+async fn jump_forward_reanchors() {
+    let mut pacer = DtsPacer::new();
+    pace(&mut pacer, ticks(0)).await;
+    pace(&mut pacer, ticks(FRAME)).await;
+    // Ten seconds ahead would otherwise stall the stream for ten seconds
+    assert_eq!(pace(&mut pacer, ticks(900_000)).await, Duration::ZERO);
+    assert_near(pace(&mut pacer, ticks(900_000 + FRAME)).await, frames(1));
+}
+
+#[tokio::test(start_paused = true)]
+async fn jump_back_reanchors() {
+    let mut pacer = DtsPacer::new();
+    pace(&mut pacer, ticks(900_000)).await;
+    pace(&mut pacer, ticks(900_000 + FRAME)).await;
+    assert_eq!(pace(&mut pacer, ticks(0)).await, Duration::ZERO);
+    // Paced again from the new anchor rather than sent unpaced
+    assert_near(pace(&mut pacer, ticks(FRAME)).await, frames(1));
+    assert_near(pace(&mut pacer, ticks(2 * FRAME)).await, frames(1));
+}
+
+#[tokio::test(start_paused = true)]
+async fn wraparound_is_a_step_forward() {
+    let wrap = 1 << 33;
+    let mut pacer = DtsPacer::new();
+    pace(&mut pacer, ticks(wrap - FRAME)).await;
+    assert_near(pace(&mut pacer, ticks(0)).await, frames(1));
+    assert_near(pace(&mut pacer, ticks(FRAME)).await, frames(1));
+}
+
+#[tokio::test(start_paused = true)]
+async fn far_behind_reanchors() {
+    let mut pacer = DtsPacer::new();
+    pace(&mut pacer, ticks(0)).await;
+    advance(Duration::from_secs(1)).await;
+    assert_eq!(pace(&mut pacer, ticks(FRAME)).await, Duration::ZERO);
+    // Without re-anchoring the next thirty frames would go out in a burst
+    assert_near(pace(&mut pacer, ticks(2 * FRAME)).await, frames(1));
+}
+
+#[tokio::test(start_paused = true)]
+async fn slow_source_does_not_build_up_a_burst() {
+    let mut pacer = DtsPacer::new();
+    pace(&mut pacer, ticks(0)).await;
+    // A camera delivering 35ms frames stamped 33.3ms apart falls behind
+    // by 1.7ms a frame, 170ms over these hundred frames
+    for frame in 1..=100 {
+        advance(Duration::from_millis(35)).await;
+        pace(&mut pacer, ticks(frame * FRAME)).await;
+    }
+    // When it catches up, the backlog is paced rather than sent at once
+    let start = Instant::now();
+    for frame in 101..=110 {
+        pacer.pace(ticks(frame * FRAME)).await;
+    }
+    assert!(
+        start.elapsed() > frames(8),
+        "ten frames went out in {:?}",
+        start.elapsed()
+    );
+}
@@ -0,0 +1,104 @@
+//! Jitter estimate and delay adaptation of the playout buffer.
+
+use std::time::{Duration, Instant};
+
+use rust_srt_playground::playout::PlayoutBuffer;
+
+const TARGET: Duration = Duration::from_millis(100);
+const MAX: Duration = Duration::from_millis(400);
+const FRAME: Duration = Duration::from_millis(33);
+
+/// Pushes `transits.len()` frames one [`FRAME`] apart from `start`, each
+/// arriving its transit time after it was sent.
+fn feed(playout: &mut PlayoutBuffer<u64>, start: Instant, first: u64, transits: &[u64]) {
+    for (i, &transit) in transits.iter().enumerate() {
+        let index = first + i as u64;
+        let sent_at = start + FRAME * index as u32;
+        let arrived = sent_at + Duration::from_millis(transit);
+        playout.push(sent_at, arrived, index);
+    }
+}
+
+fn assert_near(actual: Duration, expected: Duration, tolerance: Duration) {
+    assert!(
+        actual.abs_diff(expected) <= tolerance,
+        "{actual:?} is not within {tolerance:?} of {expected:?}"
+    );
+}
+
+#[test]
+fn steady_arrival_keeps_the_target() {
+    let mut playout = PlayoutBuffer::new(TARGET, MAX);
+    let start = Instant::now();
+    feed(&mut playout, start, 0, &[20; 100]);
+
+    assert_eq!(playout.jitter(), Duration::ZERO);
+    assert_eq!(playout.delay(), TARGET);
+    assert_eq!(playout.late(), 0);
+    // Every frame is due its delay after it was sent
+    assert_eq!(playout.next_deadline(), Some(start + TARGET));
+    let played = (0..100)
+        .filter_map(|i| playout.pop_due(start + FRAME * i + TARGET))
+        .collect::<Vec<_>>();
+    assert_eq!(played, (0..100).collect::<Vec<_>>());
+}
+
+#[test]
+fn jitter_burst_raises_the_delay() {
+    let mut playout = PlayoutBuffer::new(TARGET, MAX);
+    let start = Instant::now();
+    feed(&mut playout, start, 0, &[20; 50]);
+    // Transit swinging by 40ms between frames
+    let burst: Vec<u64> = (0..100).map(|i| if i % 2 == 0 { 20 } else { 60 }).collect();
+    feed(&mut playout, start, 50, &burst);
+
+    assert_near(
+        playout.jitter(),
+        Duration::from_millis(40),
+        Duration::from_millis(1),
+    );
+    // Heading for the target plus three jitter estimates, 220ms
+    assert!(playout.delay() > TARGET + Duration::from_millis(100));
+    assert!(playout.delay() < TARGET + Duration::from_millis(120));
+}
+
+#[test]
+fn delay_never_exceeds_the_maximum() {
+    let mut playout = PlayoutBuffer::new(TARGET, MAX);
+    let burst: Vec<u64> = (0..200)
+        .map(|i| if i % 2 == 0 { 20 } else { 500 })
+        .collect();
+    feed(&mut playout, Instant::now(), 0, &burst);
+
+    assert!(playout.jitter() > Duration::from_millis(400));
+    assert_near(playout.delay(), MAX, Duration::from_millis(1));
+}
+
+#[test]
+fn delay_decays_back_after_the_burst() {
+    let mut playout = PlayoutBuffer::new(TARGET, MAX);
+    let start = Instant::now();
+    let burst: Vec<u64> = (0..100).map(|i| if i % 2 == 0 { 20 } else { 60 }).collect();
+    feed(&mut playout, start, 0, &burst);
+    let raised = playout.delay();
+    assert!(raised > TARGET + Duration::from_millis(50));
+
+    feed(&mut playout, start, 100, &[20; 50]);
+    let decaying = playout.delay();
+    assert!(decaying < raised, "{decaying:?} is not below {raised:?}");
+
+    feed(&mut playout, start, 150, &[20; 250]);
+    assert!(playout.jitter() < Duration::from_millis(1));
+    assert_near(playout.delay(), TARGET, Duration::from_millis(2));
+}
+
+#[test]
+fn frames_past_their_deadline_are_dropped() {
+    let mut playout = PlayoutBuffer::new(TARGET, MAX);
+    let start = Instant::now();
+    feed(&mut playout, start, 0, &[20; 10]);
+    // Far beyond the delay, which has barely moved
+    assert!(!playout.push(start + FRAME * 10, start + FRAME * 10 + MAX, 10));
+    assert_eq!(playout.late(), 1);
+    assert_eq!(playout.buffered(), 10);
+}
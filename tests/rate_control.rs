@@ -0,0 +1,146 @@
+//! Drives the adaptive JPEG quality and H.264 ladder controllers with made-up
+//! link statistics.
+
+use std::time::Duration;
+
+use rust_srt_playground::rate::{
+    Ladder, LadderController, LinkStats, QualityController, QualityKnob, QualityRange, RungKnob,
+};
+
+fn start(start: i32, min: i32, max: i32) -> (QualityController, QualityKnob) {
+    let knob = QualityKnob::new(start);
+    let range = QualityRange::new(min, max).unwrap();
+    (QualityController::new(knob.clone(), range), knob)
+}
+
+fn sample(buffer_ms: u64, loss: f64, rtt_ms: u64) -> LinkStats {
+    LinkStats {
+        send_buffer: Duration::from_millis(buffer_ms),
+        loss,
+        rtt: Duration::from_millis(rtt_ms),
+    }
+}
+
+fn clean() -> LinkStats {
+    sample(10, 0.0, 20)
+}
+
+#[test]
+fn starts_within_the_bounds() {
+    let (controller, knob) = start(95, 30, 80);
+    assert_eq!(controller.quality(), 80);
+    assert_eq!(knob.get(), 80);
+}
+
+#[test]
+fn backs_off_on_a_full_send_buffer() {
+    let (mut controller, knob) = start(80, 30, 90);
+    assert_eq!(controller.update(&sample(150, 0.0, 20)), Some(75));
+    // Far beyond the target backs off harder
+    assert_eq!(controller.update(&sample(400, 0.0, 20)), Some(60));
+    assert_eq!(knob.get(), 60);
+}
+
+#[test]
+fn backs_off_on_loss_and_growing_rtt() {
+    let (mut controller, _) = start(80, 30, 90);
+    assert_eq!(controller.update(&sample(10, 0.03, 20)), Some(75));
+    assert_eq!(controller.update(&sample(10, 0.0, 20)), None);
+    // Queues on the path show up in the RTT first
+    assert_eq!(controller.update(&sample(10, 0.0, 90)), Some(70));
+}
+
+#[test]
+fn recovers_after_clean_samples() {
+    let (mut controller, _) = start(50, 30, 90);
+    assert_eq!(controller.update(&clean()), None);
+    assert_eq!(controller.update(&clean()), None);
+    assert_eq!(controller.update(&clean()), Some(52));
+    // A congested sample starts the count over
+    assert_eq!(controller.update(&sample(150, 0.0, 20)), Some(47));
+    assert_eq!(controller.update(&clean()), None);
+    assert_eq!(controller.update(&clean()), None);
+    assert_eq!(controller.update(&clean()), Some(49));
+}
+
+#[test]
+fn stays_within_the_bounds() {
+    let (mut controller, _) = start(35, 30, 90);
+    assert_eq!(controller.update(&sample(500, 0.2, 20)), Some(30));
+    assert_eq!(controller.update(&sample(500, 0.2, 20)), None);
+    assert_eq!(controller.quality(), 30);
+
+    let (mut controller, _) = start(89, 30, 90);
+    for _ in 0..2 {
+        controller.update(&clean());
+    }
+    assert_eq!(controller.update(&clean()), Some(90));
+    for _ in 0..6 {
+        assert_eq!(controller.update(&clean()), None);
+    }
+}
+
+#[test]
+fn range_parses_from_text() {
+    assert_eq!(
+        "30-90".parse::<QualityRange>().unwrap(),
+        QualityRange::new(30, 90).unwrap()
+    );
+    assert_eq!(QualityRange::new(30, 90).unwrap().to_string(), "30-90");
+    assert!("90-30".parse::<QualityRange>().is_err());
+    assert!("0-50".parse::<QualityRange>().is_err());
+    assert!("fifty".parse::<QualityRange>().is_err());
+}
+
+fn ladder() -> (LadderController, RungKnob) {
+    let knob = RungKnob::new();
+    let ladder = "640x360@600,1280x720@2500,854x480@1200".parse().unwrap();
+    (LadderController::new(knob.clone(), ladder), knob)
+}
+
+#[test]
+fn ladder_orders_rungs_by_bitrate() {
+    let ladder: Ladder = "640x360@600,1280x720@2500,854x480@1200".parse().unwrap();
+    assert_eq!(ladder.to_string(), "1280x720@2500,854x480@1200,640x360@600");
+    assert!("".parse::<Ladder>().is_err());
+    assert!("1280x720".parse::<Ladder>().is_err());
+    assert!("1281x720@2500".parse::<Ladder>().is_err());
+    assert!("1280x720@0".parse::<Ladder>().is_err());
+}
+
+#[test]
+fn ladder_steps_down_on_sustained_trouble() {
+    let (mut controller, knob) = ladder();
+    // A single bad sample is not enough
+    assert_eq!(controller.update(&sample(150, 0.0, 20)), None);
+    assert_eq!(controller.update(&clean()), None);
+    assert_eq!(controller.update(&sample(10, 0.03, 20)), None);
+    assert_eq!(controller.update(&sample(150, 0.0, 20)), Some(1));
+    assert_eq!(knob.get(), 1);
+    // Outright congestion steps down at once
+    assert_eq!(controller.update(&sample(500, 0.0, 20)), Some(2));
+    assert_eq!(controller.update(&sample(500, 0.0, 20)), None);
+    assert_eq!(controller.rung(), 2);
+}
+
+#[test]
+fn ladder_steps_up_after_a_clean_stretch() {
+    let (mut controller, _) = ladder();
+    controller.update(&sample(500, 0.0, 20));
+    controller.update(&sample(500, 0.0, 20));
+    assert_eq!(controller.rung(), 2);
+
+    for _ in 0..9 {
+        assert_eq!(controller.update(&clean()), None);
+    }
+    assert_eq!(controller.update(&clean()), Some(1));
+    // Trouble in between starts the count over
+    for _ in 0..5 {
+        controller.update(&clean());
+    }
+    controller.update(&sample(150, 0.0, 20));
+    for _ in 0..9 {
+        assert_eq!(controller.update(&clean()), None);
+    }
+    assert_eq!(controller.update(&clean()), Some(0));
+}
@@ -0,0 +1,155 @@
+//! The MPEG-TS analyzer on hand-built packets.
+
+use std::time::Instant;
+
+use rust_srt_playground::ts_analyzer::TsAnalyzer;
+
+const PMT_PID: u16 = 0x1000;
+const VIDEO_PID: u16 = 0x0100;
+
+/// A TS packet carrying `payload`, padded with stuffing bytes.
+fn packet(pid: u16, unit_start: bool, cc: u8, payload: &[u8]) -> Vec<u8> {
+    let mut packet = vec![
+        0x47,
+        u8::from(unit_start) << 6 | (pid >> 8) as u8,
+        pid as u8,
+        0x10 | cc,
+    ];
+    packet.extend_from_slice(payload);
+    packet.resize(188, 0xFF);
+    packet
+}
+
+/// A PSI section with the syntax header, `body` follows the last section
+/// number and a dummy CRC ends it.
+fn section(table_id: u8, id: u16, body: &[u8]) -> Vec<u8> {
+    let len = 5 + body.len() + 4;
+    let mut section = vec![table_id, 0xB0 | (len >> 8) as u8, len as u8];
+    section.extend_from_slice(&id.to_be_bytes());
+    section.extend_from_slice(&[0xC1, 0, 0]);
+    section.extend_from_slice(body);
+    section.extend_from_slice(&[0; 4]);
+    section
+}
+
+/// The start of a section in the first packet of a PSI unit.
+fn psi_start(section: &[u8]) -> Vec<u8> {
+    let mut payload = vec![0];
+    payload.extend_from_slice(section);
+    payload
+}
+
+/// A PAT with program 1 on [`PMT_PID`].
+fn pat() -> Vec<u8> {
+    let mut body = 1u16.to_be_bytes().to_vec();
+    body.extend_from_slice(&(0xE000 | PMT_PID).to_be_bytes());
+    packet(0, true, 0, &psi_start(&section(0x00, 1, &body)))
+}
+
+/// A PMT with H.264 on [`VIDEO_PID`], padded with `descriptors` bytes of
+/// program info.
+fn pmt(descriptors: usize) -> Vec<u8> {
+    let mut body = (0xE000 | VIDEO_PID).to_be_bytes().to_vec();
+    body.extend_from_slice(&(0xF000 | descriptors as u16).to_be_bytes());
+    body.resize(body.len() + descriptors, 0);
+    body.push(0x1B);
+    body.extend_from_slice(&(0xE000 | VIDEO_PID).to_be_bytes());
+    body.extend_from_slice(&0xF000u16.to_be_bytes());
+    section(0x02, 1, &body)
+}
+
+/// The first packet of a PES with an H.264 IDR slice.
+fn idr(cc: u8) -> Vec<u8> {
+    let pes = [
+        0, 0, 1, 0xE0, 0, 0, 0x80, 0x80, 5, 0x21, 0, 1, 0, 1, 0, 0, 0, 1, 0x65,
+    ];
+    packet(VIDEO_PID, true, cc, &pes)
+}
+
+/// Packets on [`VIDEO_PID`] with the given continuity counters.
+fn video(counters: &[u8]) -> Vec<u8> {
+    counters
+        .iter()
+        .flat_map(|&cc| packet(VIDEO_PID, false, cc, &[0; 16]))
+        .collect()
+}
+
+#[test]
+fn counts_continuity_gaps() {
+    let mut analyzer = TsAnalyzer::new();
+    // A repeated counter is a duplicate and 15 wraps to 0, only 13 -> 15 skips
+    analyzer.push(&video(&[12, 13, 13, 15, 0, 1]), Instant::now());
+    assert_eq!(analyzer.cc_errors(), 1);
+    assert_eq!(analyzer.sync_losses(), 0);
+}
+
+#[test]
+fn counts_one_sync_loss_until_resync() {
+    let mut analyzer = TsAnalyzer::new();
+    let now = Instant::now();
+    analyzer.push(&video(&[0, 1, 2]), now);
+    // Garbage over several payloads is still one loss of sync
+    for _ in 0..4 {
+        analyzer.push(&[0; 500], now);
+    }
+    analyzer.push(&video(&[3, 4, 5, 6]), now);
+    assert_eq!(analyzer.sync_losses(), 1);
+    assert!(
+        analyzer
+            .to_string()
+            .contains("PID 0x0100: 7 packets, 0 CC errors"),
+        "{analyzer}"
+    );
+
+    analyzer.push(&[0; 100], now);
+    analyzer.push(&video(&[7, 8, 9]), now);
+    assert_eq!(analyzer.sync_losses(), 2);
+}
+
+#[test]
+fn resyncs_within_a_payload() {
+    let mut analyzer = TsAnalyzer::new();
+    let mut payload = vec![0; 400];
+    payload.extend(video(&[0, 1, 2, 3, 4, 5, 6, 7, 8, 9]));
+    analyzer.push(&payload, Instant::now());
+    assert_eq!(analyzer.sync_losses(), 1);
+    assert!(
+        analyzer.to_string().contains("400 bytes skipped"),
+        "{analyzer}"
+    );
+    assert!(
+        analyzer.to_string().contains("PID 0x0100: 10 packets"),
+        "{analyzer}"
+    );
+}
+
+#[test]
+fn reads_tables_split_across_payloads() {
+    // Long enough for the PMT to continue in a second packet
+    let pmt = pmt(250);
+    let (first, rest) = pmt.split_at(183);
+    let mut stream = pat();
+    stream.extend(packet(PMT_PID, true, 0, &psi_start(first)));
+    stream.extend(packet(PMT_PID, false, 1, rest));
+    stream.extend(idr(0));
+
+    // Cut the stream wherever, like v3's 1316 byte chunks
+    let mut analyzer = TsAnalyzer::new();
+    for chunk in stream.chunks(100) {
+        analyzer.push(chunk, Instant::now());
+    }
+
+    let report = analyzer.to_string();
+    assert!(
+        report.contains("program 1: PMT PID 0x1000, PCR PID 0x0100"),
+        "{report}"
+    );
+    assert!(
+        report.contains("PID 0x0100: stream type 0x1b (H.264)"),
+        "{report}"
+    );
+    // The IDR is only found once the PMT names the stream type
+    assert_eq!(analyzer.keyframes(), 1);
+    assert_eq!(analyzer.sync_losses(), 0);
+    assert_eq!(analyzer.cc_errors(), 0);
+}
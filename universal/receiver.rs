@@ -0,0 +1,54 @@
+use anyhow::Result;
+use opencv::prelude::*;
+use rust_srt_playground::{
+    output::FrameOutput,
+    receiver::VideoReceiver,
+    transport::{Connector, Role},
+};
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    pretty_env_logger::init();
+
+    // SRT_LISTEN=0.0.0.0:4200 waits for a calling sender (v4), SRT_RENDEZVOUS
+    // meets a rendezvous sender, otherwise SRT_CALL (default 127.0.0.1:1234)
+    // connects to a listening one.
+    let (role, addr) = if let Ok(addr) = std::env::var("SRT_LISTEN") {
+        (Role::Listener, addr)
+    } else if let Ok(addr) = std::env::var("SRT_RENDEZVOUS") {
+        (Role::Rendezvous, addr)
+    } else {
+        let addr = std::env::var("SRT_CALL").unwrap_or_else(|_| "127.0.0.1:1234".into());
+        (Role::Caller, addr)
+    };
+    let srt = Connector::from_env(role, &addr)?.connect().await?;
+    println!("Connected, probing stream format...");
+
+    let mut receiver = VideoReceiver::detect(srt).await?;
+    let format = receiver.format();
+    println!("Detected {format}");
+
+    // HEADLESS, TIMECODE, MJPEG_ADDR and DUMP_* configure the output
+    let mut output = FrameOutput::from_env("SRT Universal Receiver").await?;
+    let mut frame_count = 0;
+    while let Some(frame) = receiver.next_frame().await? {
+        frame_count = frame.number;
+        println!(
+            "Frame #{} decoded: {}x{}",
+            frame.number,
+            frame.image.cols(),
+            frame.image.rows()
+        );
+        if !output.present(&frame)? {
+            break;
+        }
+    }
+
+    output.summary();
+    println!(
+        "SRT stream closed after {} decoded frames ({format}), {} bytes received",
+        frame_count,
+        receiver.received_bytes()
+    );
+    Ok(())
+}
@@ -1,12 +1,13 @@
 use futures::StreamExt;
-use srt_tokio::SrtSocket;
-use std::io::Error;
+use rust_srt_playground::transport::{Connector, Role};
 
 #[tokio::main]
-async fn main() -> Result<(), Error> {
+async fn main() -> anyhow::Result<()> {
     println!("Connecting to SRT sender...");
 
-    let mut srt_socket = SrtSocket::builder().call("127.0.0.1:1234", None).await?;
+    let mut srt_socket = Connector::from_env(Role::Caller, "127.0.0.1:1234")?
+        .connect()
+        .await?;
     println!("Connected! Receiving packets...");
 
     let mut total_bytes = 0usize;
@@ -1,15 +1,31 @@
 use futures::StreamExt;
-use srt_tokio::SrtSocket;
-use std::io::Error;
+use rust_srt_playground::{
+    transport::{Connector, Role},
+    ts_analyzer::TsAnalyzer,
+};
+use std::time::{Duration, Instant};
 
 #[tokio::main]
-async fn main() -> Result<(), Error> {
+async fn main() -> anyhow::Result<()> {
     println!("Connecting to SRT sender...");
 
     // Connect to sender at 127.0.0.1:1234
-    let mut srt_socket = SrtSocket::builder().call("127.0.0.1:1234", None).await?;
+    let mut srt_socket = Connector::from_env(Role::Caller, "127.0.0.1:1234")?
+        .connect()
+        .await?;
     println!("Connected! Receiving packets...");
 
+    // ANALYZE=1 replaces the per-packet log with periodic TS health reports,
+    // every REPORT_INTERVAL_SECS seconds (default 5)
+    let mut analyzer = std::env::var_os("ANALYZE").map(|_| TsAnalyzer::new());
+    let report_interval = Duration::from_secs(
+        std::env::var("REPORT_INTERVAL_SECS")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(5),
+    );
+    let mut last_report = Instant::now();
+
     let mut total_bytes = 0usize;
     let mut packet_count = 0usize;
 
@@ -18,6 +34,14 @@ async fn main() -> Result<(), Error> {
             Ok((_instant, bytes)) => {
                 total_bytes += bytes.len();
                 packet_count += 1;
+                if let Some(analyzer) = &mut analyzer {
+                    analyzer.push(&bytes, Instant::now());
+                    if last_report.elapsed() >= report_interval {
+                        println!("{analyzer}");
+                        last_report = Instant::now();
+                    }
+                    continue;
+                }
                 println!(
                     "Packet #{} received: {} bytes (total {} bytes)",
                     packet_count,
@@ -30,5 +54,9 @@ async fn main() -> Result<(), Error> {
     }
 
     println!("SRT stream closed");
+    if let Some(analyzer) = &analyzer {
+        println!("End of session, {packet_count} SRT messages, {total_bytes} bytes");
+        println!("{analyzer}");
+    }
     Ok(())
 }
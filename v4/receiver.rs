@@ -1,76 +1,49 @@
 use anyhow::Result;
-use bytes::{Buf, Bytes};
-use futures::StreamExt;
-use opencv::{core::Vector, highgui, imgcodecs, prelude::*};
-use srt_tokio::SrtSocket;
-use std::collections::VecDeque;
+use opencv::prelude::*;
+use rust_srt_playground::{
+    detect::StreamFormat,
+    output::FrameOutput,
+    playout::PlayoutBuffer,
+    receiver::VideoReceiver,
+    transport::{Connector, Role},
+};
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    println!("Listening on SRT port 4200...");
-    let mut srt = SrtSocket::builder().listen_on("0.0.0.0:4200").await?;
-    println!("SRT listener ready");
+    // HEADLESS, TIMECODE, MJPEG_ADDR and DUMP_* configure the output
+    let mut output = FrameOutput::from_env("SRT Receiver").await?;
 
-    let mut frame_buffer: Vec<u8> = Vec::new();
-    let mut frames_queue: VecDeque<Vec<u8>> = VecDeque::new();
-    let mut expected_frame_size: Option<usize> = None;
+    let srt = Connector::from_env(Role::Listener, "0.0.0.0:4200")?
+        .connect()
+        .await?;
+    println!("SRT listener ready");
 
-    highgui::named_window("SRT Receiver", highgui::WINDOW_AUTOSIZE)?;
-    let mut frame_count = 0;
+    // PLAYOUT_DELAY_MS paces display on sender timestamps. The delay counts
+    // from the send time, so it has to cover the SRT latency as well.
+    let mut receiver =
+        VideoReceiver::new(srt, StreamFormat::ChunkedJpeg).playout(PlayoutBuffer::from_env());
 
-    while let Some(Ok((_timestamp, bytes_chunk))) = srt.next().await {
-        // Append received chunk
-        frame_buffer.extend_from_slice(&bytes_chunk);
+    while let Some(frame) = receiver.next_frame().await? {
         println!(
-            "Received chunk, {} bytes, buffer size {}",
-            bytes_chunk.len(),
-            frame_buffer.len()
+            "Frame #{} decoded: {}x{}",
+            frame.number,
+            frame.image.cols(),
+            frame.image.rows()
         );
-
-        loop {
-            if expected_frame_size.is_none() {
-                if frame_buffer.len() >= 4 {
-                    // Read first 4 bytes as u32 frame length
-                    let mut length_bytes = &frame_buffer[..4];
-                    let len = length_bytes.get_u32() as usize;
-                    expected_frame_size = Some(len);
-                    frame_buffer.drain(0..4);
-                } else {
-                    break; // wait for more data
-                }
-            }
-
-            if let Some(frame_len) = expected_frame_size {
-                if frame_buffer.len() >= frame_len {
-                    let frame_bytes = frame_buffer.drain(0..frame_len).collect::<Vec<u8>>();
-                    expected_frame_size = None;
-                    frame_count += 1;
-                    println!(
-                        "Frame #{} complete, {} bytes",
-                        frame_count,
-                        frame_bytes.len()
-                    );
-                    frames_queue.push_back(frame_bytes);
-                } else {
-                    break; // wait for full frame
-                }
-            }
+        if !output.present(&frame)? {
+            println!("Exit requested by user");
+            return Ok(());
         }
+    }
 
-        // Display available frames
-        while let Some(frame_bytes) = frames_queue.pop_front() {
-            let buf = Vector::from_slice(&frame_bytes);
-            let frame = imgcodecs::imdecode(&buf, imgcodecs::IMREAD_COLOR)?;
-            if frame.empty() {
-                println!("Decoded empty frame, skipping");
-                continue;
-            }
-            highgui::imshow("SRT Receiver", &frame)?;
-            if highgui::wait_key(1)? == 27 {
-                println!("ESC pressed, exiting");
-                return Ok(());
-            }
-        }
+    output.summary();
+    if let Some(playout) = receiver.playout_stats() {
+        println!(
+            "Playout: {} frames shown, {} late, final delay {:?}",
+            playout.played(),
+            playout.late(),
+            playout.delay()
+        );
     }
 
     Ok(())
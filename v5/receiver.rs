@@ -1,41 +1,55 @@
 use anyhow::Result;
-use futures::prelude::*;
-use opencv::{highgui, imgcodecs, prelude::*};
-use srt_tokio::SrtSocket;
+use opencv::prelude::*;
+use rust_srt_playground::{
+    detect::StreamFormat,
+    output::FrameOutput,
+    playout::PlayoutBuffer,
+    receiver::VideoReceiver,
+    transport::{Connector, Role},
+};
 
 #[tokio::main]
 async fn main() -> Result<()> {
     pretty_env_logger::init();
 
-    // Connect to the SRT sender on localhost:9999
-    let mut rx = SrtSocket::builder().call("127.0.0.1:9999", None).await?;
-    println!("Connected to SRT sender on 127.0.0.1:9999");
-
-    // Create a window to display received frames
-    let window = "Received Frame";
-    highgui::named_window(window, highgui::WINDOW_AUTOSIZE)?;
+    // HEADLESS, TIMECODE, MJPEG_ADDR and DUMP_* configure the output
+    let mut output = FrameOutput::from_env("Received Frame").await?;
 
-    // Loop: receive (timestamp, data) and display frames
-    while let Some((timestamp, data)) = rx.try_next().await? {
-        println!("Received frame at {:?}", timestamp);
+    // Connect to the SRT sender on localhost:9999
+    let rx = Connector::from_env(Role::Caller, "127.0.0.1:9999")?
+        .connect()
+        .await?;
+    println!("Connected to SRT sender");
 
-        // Convert the Bytes to a Vec<u8> and decode JPEG into a Mat
-        let jpeg_bytes = data.to_vec();
-        let buf = Mat::from_slice::<u8>(&jpeg_bytes)?;
-        let frame = imgcodecs::imdecode(&buf, imgcodecs::IMREAD_COLOR)?;
-        if frame.empty() {
-            println!("Empty frame received, stopping.");
-            break;
-        }
+    // PLAYOUT_DELAY_MS paces display on sender timestamps. The delay counts
+    // from the send time, so it has to cover the SRT latency as well.
+    let mut receiver =
+        VideoReceiver::new(rx, StreamFormat::JpegMessage).playout(PlayoutBuffer::from_env());
 
-        // Show the frame
-        highgui::imshow(window, &frame)?;
-        // Wait briefly (e.g. 1 ms) to allow window to update; exit if 'q' is pressed
-        if highgui::wait_key(1)? == 'q' as i32 {
+    // Loop: decode and display frames
+    while let Some(frame) = receiver.next_frame().await? {
+        println!(
+            "Frame #{} decoded: {}x{}, {} bytes",
+            frame.number,
+            frame.image.cols(),
+            frame.image.rows(),
+            frame.jpeg.as_ref().map_or(0, |jpeg| jpeg.len())
+        );
+        if !output.present(&frame)? {
             println!("Exit requested by user");
-            break;
+            return Ok(());
         }
     }
 
+    output.summary();
+    if let Some(playout) = receiver.playout_stats() {
+        println!(
+            "Playout: {} frames shown, {} late, final delay {:?}",
+            playout.played(),
+            playout.late(),
+            playout.delay()
+        );
+    }
+
     Ok(())
 }